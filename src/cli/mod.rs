@@ -17,4 +17,6 @@
 pub mod parser;
 pub mod show;
 pub mod playbooks;
+pub mod roles;
+pub mod vault;
 pub mod version;
\ No newline at end of file
@@ -31,6 +31,13 @@ use crate::cli::version::{GIT_VERSION,GIT_BRANCH,BUILD_TIME};
 use std::path::Path;
 use std::io;
 use std::collections::HashMap;
+use crate::connection::ssh::{HostKeyChecking,host_key_checking_from_string};
+use crate::connection::ssh_config::SshConfig;
+use crate::inventory::patterns::split_pattern;
+use crate::util::vault::read_vault_password_file;
+use crate::playbooks::visitor::{OutputSpec,OutputFormat};
+use crate::cli::roles::get_roles_cache_path;
+use expanduser::expanduser;
 
 // the CLI parser struct values hold various values calculated when calling parse() on
 // the struct
@@ -49,10 +56,13 @@ pub struct CliParser {
     pub needs_version: bool,
     pub show_hosts: Vec<String>,
     pub show_groups: Vec<String>,
+    pub show_pattern: Option<String>,
     pub batch_size: Option<usize>,
     pub default_user: String,
+    pub user_set: bool,
     pub sudo: Option<String>,
     pub default_port: i64,
+    pub port_set: bool,
     pub threads: usize,
     pub verbosity: u32,
     pub tags: Option<Vec<String>>,
@@ -60,6 +70,22 @@ pub struct CliParser {
     pub extra_vars: serde_yaml::Value,
     pub forward_agent: bool,
     pub login_password: Option<String>,
+    pub host_key_checking: HostKeyChecking,
+    pub ssh_config_path: Option<PathBuf>,
+    pub ssh_config: Arc<SshConfig>,
+    pub refresh_inventory: bool,
+    pub inventory_cache_ttl: u64,
+    pub vault_action: Option<String>,
+    pub vault_files: Vec<String>,
+    pub vault_password: Option<String>,
+    pub vault_password_file: Option<PathBuf>,
+    pub ask_vault_password: bool,
+    pub new_vault_password_file: Option<PathBuf>,
+    pub outputs: Vec<OutputSpec>,
+    pub report_path: Option<PathBuf>,
+    pub diff: bool,
+    pub roles_action: Option<String>,
+    pub requirements_path: Option<PathBuf>,
     pub argument_map: HashMap<String, Arguments>,
 }
 
@@ -74,6 +100,9 @@ pub const CLI_MODE_SSH: u32 = 4;
 pub const CLI_MODE_CHECK_SSH: u32 = 5;
 pub const CLI_MODE_SHOW: u32 = 6;
 pub const CLI_MODE_SIMULATE: u32 = 7;
+pub const CLI_MODE_SHOW_VARS: u32 = 8;
+pub const CLI_MODE_VAULT: u32 = 9;
+pub const CLI_MODE_ROLES: u32 = 10;
 
 fn is_cli_mode_valid(value: &String) -> bool {
     match cli_mode_from_string(value) {
@@ -90,6 +119,9 @@ fn cli_mode_from_string(s: &String) -> Result<u32, String> {
         "check-ssh"       => Ok(CLI_MODE_CHECK_SSH),
         "__simulate"      => Ok(CLI_MODE_SIMULATE),
         "show-inventory"  => Ok(CLI_MODE_SHOW),
+        "show-vars"       => Ok(CLI_MODE_SHOW_VARS),
+        "vault"           => Ok(CLI_MODE_VAULT),
+        "roles"           => Ok(CLI_MODE_ROLES),
         _ => Err(format!("invalid mode: {}", s))
     }
 }
@@ -108,6 +140,7 @@ pub enum Arguments {
     ARGUMENT_ROLES_SHORT,
     ARGUMENT_SHOW_GROUPS,
     ARGUMENT_SHOW_HOSTS,
+    ARGUMENT_SHOW_PATTERN,
     ARGUMENT_LIMIT_GROUPS,
     ARGUMENT_LIMIT_HOSTS,
     ARGUMENT_HELP,
@@ -128,7 +161,18 @@ pub enum Arguments {
     ARGUMENT_EXTRA_VARS_SHORT,
     ARGUMENT_ASK_LOGIN_PASSWORD,
     ARGUMENT_MODULES,
-    ARGUMENT_MODULES_SHORT
+    ARGUMENT_MODULES_SHORT,
+    ARGUMENT_HOST_KEY_CHECKING,
+    ARGUMENT_SSH_CONFIG,
+    ARGUMENT_REFRESH_INVENTORY,
+    ARGUMENT_INVENTORY_CACHE_TTL,
+    ARGUMENT_VAULT_PASSWORD_FILE,
+    ARGUMENT_ASK_VAULT_PASSWORD,
+    ARGUMENT_NEW_VAULT_PASSWORD_FILE,
+    ARGUMENT_OUTPUT,
+    ARGUMENT_REPORT,
+    ARGUMENT_DIFF,
+    ARGUMENT_REQUIREMENTS,
 }
 
 impl Arguments {
@@ -145,6 +189,7 @@ impl Arguments {
             Arguments::ARGUMENT_MODULES_SHORT => "-m",
             Arguments::ARGUMENT_SHOW_GROUPS => "--show-groups",
             Arguments::ARGUMENT_SHOW_HOSTS => "--show-hosts",
+            Arguments::ARGUMENT_SHOW_PATTERN => "--show-pattern",
             Arguments::ARGUMENT_LIMIT_GROUPS => "--limit-groups",
             Arguments::ARGUMENT_LIMIT_HOSTS => "--limit-hosts",
             Arguments::ARGUMENT_HELP => "--help",
@@ -164,6 +209,17 @@ impl Arguments {
             Arguments::ARGUMENT_EXTRA_VARS => "--extra-vars",
             Arguments::ARGUMENT_EXTRA_VARS_SHORT => "-e",
             Arguments::ARGUMENT_ASK_LOGIN_PASSWORD => "--ask-login-password",
+            Arguments::ARGUMENT_HOST_KEY_CHECKING => "--host-key-checking",
+            Arguments::ARGUMENT_SSH_CONFIG => "--ssh-config",
+            Arguments::ARGUMENT_REFRESH_INVENTORY => "--refresh-inventory",
+            Arguments::ARGUMENT_INVENTORY_CACHE_TTL => "--inventory-cache-ttl",
+            Arguments::ARGUMENT_VAULT_PASSWORD_FILE => "--vault-password-file",
+            Arguments::ARGUMENT_ASK_VAULT_PASSWORD => "--ask-vault-password",
+            Arguments::ARGUMENT_NEW_VAULT_PASSWORD_FILE => "--new-vault-password-file",
+            Arguments::ARGUMENT_OUTPUT => "--output",
+            Arguments::ARGUMENT_REPORT => "--report",
+            Arguments::ARGUMENT_DIFF => "--diff",
+            Arguments::ARGUMENT_REQUIREMENTS => "--requirements",
         }
     }
 }
@@ -182,6 +238,7 @@ fn build_argument_map() -> HashMap<String, Arguments> {
         (Arguments::ARGUMENT_ROLES_SHORT, "-r"),
         (Arguments::ARGUMENT_SHOW_GROUPS, "--show-groups"),
         (Arguments::ARGUMENT_SHOW_HOSTS, "--show-hosts"),
+        (Arguments::ARGUMENT_SHOW_PATTERN, "--show-pattern"),
         (Arguments::ARGUMENT_LIMIT_GROUPS, "--limit-groups"),
         (Arguments::ARGUMENT_LIMIT_HOSTS, "--limit-hosts"),
         (Arguments::ARGUMENT_HELP, "--help"),
@@ -201,6 +258,17 @@ fn build_argument_map() -> HashMap<String, Arguments> {
         (Arguments::ARGUMENT_EXTRA_VARS, "--extra-vars"),
         (Arguments::ARGUMENT_EXTRA_VARS_SHORT, "-e"),
         (Arguments::ARGUMENT_ASK_LOGIN_PASSWORD, "--ask-login-password"),
+        (Arguments::ARGUMENT_HOST_KEY_CHECKING, "--host-key-checking"),
+        (Arguments::ARGUMENT_SSH_CONFIG, "--ssh-config"),
+        (Arguments::ARGUMENT_REFRESH_INVENTORY, "--refresh-inventory"),
+        (Arguments::ARGUMENT_INVENTORY_CACHE_TTL, "--inventory-cache-ttl"),
+        (Arguments::ARGUMENT_VAULT_PASSWORD_FILE, "--vault-password-file"),
+        (Arguments::ARGUMENT_ASK_VAULT_PASSWORD, "--ask-vault-password"),
+        (Arguments::ARGUMENT_NEW_VAULT_PASSWORD_FILE, "--new-vault-password-file"),
+        (Arguments::ARGUMENT_OUTPUT, "--output"),
+        (Arguments::ARGUMENT_REPORT, "--report"),
+        (Arguments::ARGUMENT_DIFF, "--diff"),
+        (Arguments::ARGUMENT_REQUIREMENTS, "--requirements"),
     ];
     let mut map : HashMap<String, Arguments> = HashMap::new();
     for (e,i) in inputs.iter() {
@@ -235,7 +303,13 @@ fn show_help() {
                       | *Category* | *Mode* | *Description*\n\
                       | --- | --- | ---\n\
                       | utility: |\n\
-                      | | show-inventory | displays inventory, specify --show-groups group1:group2 or --show-hosts host1:host2\n\
+                      | | show-inventory | displays inventory, specify --show-groups group1:group2 or --show-hosts host1:host2, or preview a host pattern with --show-pattern 'web*:&prod'\n\
+                      | |\n\
+                      | | roles | install roles from git or tarballs into $JET_ROLES_CACHE (default ~/.cache/jetp/roles), ex: jetp roles install --requirements requirements.yml\n\
+                      | |\n\
+                      | | vault | encrypt, decrypt, edit or rekey vault files, ex: jetp vault encrypt group_vars/all, use - to encrypt a single value from stdin\n\
+                      | |\n\
+                      | | show-vars | shows each variable for --show-hosts host1:host2 and where it was set, add -p to include play variables\n\
                       | |\n\
                       | --- | --- | ---\n\
                       | local machine management: |\n\
@@ -264,6 +338,20 @@ fn show_help() {
                        | | -r, --roles path1:path2| adds additional role search paths. Also uses $JET_ROLES_PATH\n\
                        | |\n\
                        | --- | ---\n\
+                       | Inventory options:\n\
+                       | | --inventory-cache-ttl N| reuse dynamic inventory output for N seconds. Also uses $JET_INVENTORY_CACHE_TTL\n\
+                       | |\n\
+                       | | --refresh-inventory | always run dynamic inventory scripts, ignoring any cached output\n\
+                       | |\n\
+                       | --- | ---\n\
+                       | Vault options:\n\
+                       | | --ask-vault-password | prompt for the vault password on standard input\n\
+                       | |\n\
+                       | | --new-vault-password-file path | the new password for 'jetp vault rekey', otherwise prompted for\n\
+                       | |\n\
+                       | | --vault-password-file path | read the vault password from this file, or its output if executable. Also uses $JET_VAULT_PASSWORD_FILE\n\
+                       | |\n\
+                       | --- | ---\n\
                        | SSH options:\n\
                        | | --ask-login-password | prompt for the login password on standard input\n\
                        | |\n\
@@ -271,12 +359,16 @@ fn show_help() {
                        | |\n\
                        | | --forward-agent | enables SSH agent forwarding but only on specific tasks (ex: git)\n\
                        | |\n\
-                       | | --limit-groups group1:group2 | further limits scope for playbook runs\n\
+                       | | --host-key-checking policy | strict (default), accept-new, or off. Also uses $JET_SSH_HOST_KEY_CHECKING\n\
+                       | |\n\
+                       | | --limit-groups pattern | further limits scope for playbook runs to hosts in matching groups, ex: 'webservers:&prod:!canary'\n\
                        | |\n\
-                       | | --limit-hosts host1 | further limits scope for playbook runs\n\
+                       | | --limit-hosts pattern | further limits scope for playbook runs, ex: 'web*' or 'db[01:12].example.com'\n\
                        | |\n\
                        | | --port N | use this default port instead of $JET_SSH_PORT or 22\n\
                        | |\n\
+                       | | --ssh-config path | read host aliases, ports, users and keys from this file instead of ~/.ssh/config\n\
+                       | |\n\
                        | | -t, --threads N| how many parallel threads to use. Alternatively set $JET_THREADS\n\
                        | |\n\
                        | | -u, --user username | use this default username instead of $JET_SSH_USER or $USER\n\
@@ -285,8 +377,16 @@ fn show_help() {
                        | Misc options:\n\
                        | | --allow-localhost-delegation | signs off on variable sourcing risks and enables localhost actions with delegate_to\n\
                        | |\n\
+                       | | --diff | in check modes, show a diff of file content and attribute changes the template and copy modules would make\n\
+                       | |\n\
                        | | -e, --extra-vars @filename | injects extra variables into the playbook runtime context from a YAML file, or quoted JSON\n\
                        | |\n\
+                       | | --output format,... | human (default), json, junit, or minimal. json and junit can go to a file, ex: human,junit=results.xml\n\
+                       | |\n\
+                       | | --report filename | when the run ends, write a JSON report of every task and host result with commands and timings\n\
+                       | |\n\
+                       | | --requirements path | the role requirements file for 'jetp roles install', default requirements.yml\n\
+                       | |\n\
                        | | --sudo username | sudo to this user by default for all tasks\n\
                        | |\n\
                        | | --tags tag1:tag2 | only run tasks or roles with one of these tags\n\
@@ -320,6 +420,7 @@ impl CliParser  {
             mode: CLI_MODE_UNSET,
             show_hosts: Vec::new(),
             show_groups: Vec::new(),
+            show_pattern: None,
             batch_size: None,
             default_user: match env::var("JET_SSH_USER") {
                 Ok(x) => {
@@ -331,6 +432,7 @@ impl CliParser  {
                     Err(_) => String::from("root")
                 }
             },
+            user_set: env::var("JET_SSH_USER").is_ok(),
             sudo: None,
             default_port: match env::var("JET_SSH_PORT") {
                 Ok(x) => match x.parse::<i64>() {
@@ -342,6 +444,7 @@ impl CliParser  {
                 },
                 Err(_) => 22
             },
+            port_set: env::var("JET_SSH_PORT").is_ok(),
             threads: match env::var("JET_THREADS") {
                 Ok(x) => match x.parse::<usize>() {
                         Ok(i)  => i,
@@ -359,6 +462,37 @@ impl CliParser  {
             extra_vars: serde_yaml::Value::Mapping(serde_yaml::Mapping::new()),
             forward_agent: false,
             login_password: None,
+            host_key_checking: match env::var("JET_SSH_HOST_KEY_CHECKING") {
+                Ok(x) => match host_key_checking_from_string(&x) {
+                    Ok(policy) => policy,
+                    Err(_) => { println!("environment variable JET_SSH_HOST_KEY_CHECKING has an invalid value, ignoring: {}", x); HostKeyChecking::Strict }
+                },
+                Err(_) => HostKeyChecking::Strict
+            },
+            ssh_config_path: None,
+            ssh_config: Arc::new(SshConfig::new()),
+            refresh_inventory: false,
+            inventory_cache_ttl: match env::var("JET_INVENTORY_CACHE_TTL") {
+                Ok(x) => match x.parse::<u64>() {
+                    Ok(i) => i,
+                    Err(_) => { println!("environment variable JET_INVENTORY_CACHE_TTL has an invalid value, ignoring: {}", x); 0 }
+                },
+                Err(_) => 0
+            },
+            vault_action: None,
+            vault_files: Vec::new(),
+            vault_password: None,
+            vault_password_file: match env::var("JET_VAULT_PASSWORD_FILE") {
+                Ok(x) => Some(PathBuf::from(x)),
+                Err(_) => None
+            },
+            ask_vault_password: false,
+            new_vault_password_file: None,
+            outputs: vec![OutputSpec { format: OutputFormat::Human, path: None }],
+            report_path: None,
+            diff: false,
+            roles_action: None,
+            requirements_path: None,
             argument_map: build_argument_map(),
         };
         return p;
@@ -418,6 +552,17 @@ impl CliParser  {
 
                     if next_is_value == false {
 
+                        // jetp vault <action> <files> is the only mode with positional arguments
+                        if self.mode == CLI_MODE_VAULT && (argument_str == "-" || !argument_str.starts_with("-")) {
+                            self.store_vault_argument(argument)?;
+                            continue 'each_argument;
+                        }
+                        // as does jetp roles <action>
+                        if self.mode == CLI_MODE_ROLES && !argument_str.starts_with("-") {
+                            self.store_roles_argument(argument)?;
+                            continue 'each_argument;
+                        }
+
                         // if we expect a flag...
                         // the --help argument requires special handling as it has no
                         // following value
@@ -445,6 +590,9 @@ impl CliParser  {
                             Arguments::ARGUMENT_VERBOSER           => self.increase_verbosity(2),
                             Arguments::ARGUMENT_VERBOSEST          => self.increase_verbosity(3),
                             Arguments::ARGUMENT_ASK_LOGIN_PASSWORD => self.store_login_password(),
+                            Arguments::ARGUMENT_REFRESH_INVENTORY  => self.store_refresh_inventory(),
+                            Arguments::ARGUMENT_ASK_VAULT_PASSWORD => self.store_ask_vault_password(),
+                            Arguments::ARGUMENT_DIFF               => self.store_diff(),
                             _ => Ok({ standalone_arg_found = false; next_is_value = true; })
                         };
 
@@ -469,6 +617,7 @@ impl CliParser  {
                                     Arguments::ARGUMENT_USER_SHORT        => self.store_default_user(&args[arg_count]),
                                     Arguments::ARGUMENT_SHOW_GROUPS       => self.store_show_groups(&args[arg_count]),
                                     Arguments::ARGUMENT_SHOW_HOSTS        => self.store_show_hosts(&args[arg_count]),
+                                    Arguments::ARGUMENT_SHOW_PATTERN      => self.store_show_pattern(&args[arg_count]),
                                     Arguments::ARGUMENT_LIMIT_GROUPS      => self.store_limit_groups(&args[arg_count]),
                                     Arguments::ARGUMENT_LIMIT_HOSTS       => self.store_limit_hosts(&args[arg_count]),
                                     Arguments::ARGUMENT_BATCH_SIZE        => self.store_batch_size(&args[arg_count]),
@@ -477,6 +626,14 @@ impl CliParser  {
                                     Arguments::ARGUMENT_PORT              => self.store_port(&args[arg_count]),
                                     Arguments::ARGUMENT_EXTRA_VARS        => self.store_extra_vars(&args[arg_count]),
                                     Arguments::ARGUMENT_EXTRA_VARS_SHORT  => self.store_extra_vars(&args[arg_count]),
+                                    Arguments::ARGUMENT_HOST_KEY_CHECKING => self.store_host_key_checking(&args[arg_count]),
+                                    Arguments::ARGUMENT_SSH_CONFIG        => self.store_ssh_config(&args[arg_count]),
+                                    Arguments::ARGUMENT_INVENTORY_CACHE_TTL => self.store_inventory_cache_ttl(&args[arg_count]),
+                                    Arguments::ARGUMENT_VAULT_PASSWORD_FILE => self.store_vault_password_file(&args[arg_count]),
+                                    Arguments::ARGUMENT_NEW_VAULT_PASSWORD_FILE => self.store_new_vault_password_file(&args[arg_count]),
+                                    Arguments::ARGUMENT_OUTPUT            => self.store_output(&args[arg_count]),
+                                    Arguments::ARGUMENT_REPORT            => self.store_report(&args[arg_count]),
+                                    Arguments::ARGUMENT_REQUIREMENTS      => self.store_requirements(&args[arg_count]),
                                     _  => Err(format!("invalid flag: {}", argument_str)),
                                 };
                             }
@@ -502,13 +659,27 @@ impl CliParser  {
             CLI_MODE_CHECK_LOCAL => { self.threads = 1 },
             CLI_MODE_SYNTAX      => { self.threads = 1 },
             CLI_MODE_SHOW        => { self.threads = 1 },
+            CLI_MODE_SHOW_VARS   => { self.threads = 1 },
+            CLI_MODE_VAULT       => { self.threads = 1 },
+            CLI_MODE_ROLES       => { self.threads = 1 },
             CLI_MODE_UNSET       => { self.needs_help = true; },
             _ => {}
         }
 
+        if self.diff && ! (self.mode == CLI_MODE_CHECK_LOCAL || self.mode == CLI_MODE_CHECK_SSH) {
+            return Err(String::from("--diff can only be used with check-local or check-ssh"));
+        }
+
+        if self.mode == CLI_MODE_SSH || self.mode == CLI_MODE_CHECK_SSH {
+            self.load_ssh_config()?;
+        }
+
+        self.load_vault_password()?;
+
         if self.playbook_set {
             self.add_role_paths_from_environment()?;
             self.add_implicit_role_paths()?;
+            self.add_role_cache_path()?;
             self.add_module_paths_from_environment()?;
             self.add_implicit_module_paths()?;
         }
@@ -612,19 +783,20 @@ impl CliParser  {
         return Ok(());
     }
 
+    fn store_show_pattern(&mut self, value: &String) -> Result<(), String> {
+        self.show_pattern = Some(value.clone());
+        return Ok(());
+    }
+
     fn store_limit_groups(&mut self, value: &String) -> Result<(), String> {
-        match split_string(value) {
-            Ok(values)  =>  { self.limit_groups = values; },
-            Err(err_msg) =>  return Err(format!("--{} {}", Arguments::ARGUMENT_LIMIT_GROUPS.as_str(), err_msg)),
-        }
+        // host patterns may contain ranges like [01:12], so these are not simply split on ':'
+        self.limit_groups = split_pattern(value);
         return Ok(());
     }
 
     fn store_limit_hosts(&mut self, value: &String) -> Result<(), String> {
-        match split_string(value) {
-            Ok(values)  =>  { self.limit_hosts = values; },
-            Err(err_msg) =>  return Err(format!("--{} {}", Arguments::ARGUMENT_LIMIT_HOSTS.as_str(), err_msg)),
-        }
+        // host patterns may contain ranges like [01:12], so these are not simply split on ':'
+        self.limit_hosts = split_pattern(value);
         return Ok(());
     }
 
@@ -636,6 +808,20 @@ impl CliParser  {
         return Ok(());
     }
 
+    fn store_output(&mut self, value: &String) -> Result<(), String> {
+        let mut outputs : Vec<OutputSpec> = Vec::new();
+        for spec in value.split(',') {
+            outputs.push(OutputSpec::parse(&String::from(spec))?);
+        }
+        self.outputs = outputs;
+        return Ok(());
+    }
+
+    fn store_report(&mut self, value: &String) -> Result<(), String> {
+        self.report_path = Some(PathBuf::from(value));
+        return Ok(());
+    }
+
     fn store_sudo(&mut self, value: &String) -> Result<(), String> {
         self.sudo = Some(value.clone());
         return Ok(());
@@ -643,6 +829,7 @@ impl CliParser  {
 
     fn store_default_user(&mut self, value: &String) -> Result<(), String> {
         self.default_user = value.clone();
+        self.user_set = true;
         return Ok(());
     }
 
@@ -665,11 +852,126 @@ impl CliParser  {
 
     fn store_port(&mut self, value: &String) -> Result<(), String> {
         match value.parse::<i64>() {
-            Ok(n) =>  { self.default_port = n; return Ok(()); }
+            Ok(n) =>  { self.default_port = n; self.port_set = true; return Ok(()); }
             Err(_e) => { return Err(format!("{}: invalid value", Arguments::ARGUMENT_PORT.as_str())); }
         }
     }
 
+    fn store_host_key_checking(&mut self, value: &String) -> Result<(), String> {
+        match host_key_checking_from_string(value) {
+            Ok(policy) => { self.host_key_checking = policy; return Ok(()); }
+            Err(y) => { return Err(format!("{}: {}", Arguments::ARGUMENT_HOST_KEY_CHECKING.as_str(), y)); }
+        }
+    }
+
+    fn store_ssh_config(&mut self, value: &String) -> Result<(), String> {
+        let path = PathBuf::from(value);
+        if ! path.is_file() {
+            return Err(format!("{}: file not found: {}", Arguments::ARGUMENT_SSH_CONFIG.as_str(), value));
+        }
+        self.ssh_config_path = Some(path);
+        return Ok(());
+    }
+
+    // without --ssh-config we still read ~/.ssh/config, but only if there is one
+
+    fn load_ssh_config(&mut self) -> Result<(), String> {
+        let path = match &self.ssh_config_path {
+            Some(x) => x.clone(),
+            None => match expanduser("~/.ssh/config") {
+                Ok(x) => x,
+                Err(_) => { return Ok(()); }
+            }
+        };
+        if path.is_file() {
+            self.ssh_config = Arc::new(SshConfig::load(&path)?);
+        }
+        return Ok(());
+    }
+
+    fn store_inventory_cache_ttl(&mut self, value: &String) -> Result<(), String> {
+        match value.parse::<u64>() {
+            Ok(n) =>  { self.inventory_cache_ttl = n; return Ok(()); }
+            Err(_e) => { return Err(format!("{}: invalid value", Arguments::ARGUMENT_INVENTORY_CACHE_TTL.as_str())); }
+        }
+    }
+
+    fn store_vault_argument(&mut self, value: &String) -> Result<(), String> {
+        if self.vault_action.is_none() {
+            match value.as_str() {
+                "encrypt" | "decrypt" | "edit" | "rekey" => { self.vault_action = Some(value.clone()); },
+                _ => { return Err(format!("invalid vault action ({}), expecting encrypt, decrypt, edit or rekey", value)); }
+            }
+        } else {
+            self.vault_files.push(value.clone());
+        }
+        return Ok(());
+    }
+
+    fn store_roles_argument(&mut self, value: &String) -> Result<(), String> {
+        if self.roles_action.is_some() {
+            return Err(format!("unexpected argument: {}", value));
+        }
+        match value.as_str() {
+            "install" => { self.roles_action = Some(value.clone()); },
+            _ => { return Err(format!("invalid roles action ({}), expecting install", value)); }
+        }
+        return Ok(());
+    }
+
+    fn store_requirements(&mut self, value: &String) -> Result<(), String> {
+        let path = PathBuf::from(value);
+        if ! path.is_file() {
+            return Err(format!("{}: file not found: {}", Arguments::ARGUMENT_REQUIREMENTS.as_str(), value));
+        }
+        self.requirements_path = Some(path);
+        return Ok(());
+    }
+
+    fn store_vault_password_file(&mut self, value: &String) -> Result<(), String> {
+        let path = PathBuf::from(value);
+        if ! path.is_file() {
+            return Err(format!("{}: file not found: {}", Arguments::ARGUMENT_VAULT_PASSWORD_FILE.as_str(), value));
+        }
+        self.vault_password_file = Some(path);
+        return Ok(());
+    }
+
+    fn store_new_vault_password_file(&mut self, value: &String) -> Result<(), String> {
+        let path = PathBuf::from(value);
+        if ! path.is_file() {
+            return Err(format!("{}: file not found: {}", Arguments::ARGUMENT_NEW_VAULT_PASSWORD_FILE.as_str(), value));
+        }
+        self.new_vault_password_file = Some(path);
+        return Ok(());
+    }
+
+    fn store_ask_vault_password(&mut self) -> Result<(), String> {
+        self.ask_vault_password = true;
+        return Ok(());
+    }
+
+    // the vault password is only read once, after all the flags are known
+
+    fn load_vault_password(&mut self) -> Result<(), String> {
+        if self.ask_vault_password {
+            self.vault_password = Some(prompt_for_password(&String::from("enter vault password:"))?);
+        } else if self.vault_password_file.is_some() {
+            self.vault_password = Some(read_vault_password_file(self.vault_password_file.as_ref().unwrap())?);
+        }
+        return Ok(());
+    }
+
+    fn store_refresh_inventory(&mut self) -> Result<(), String> {
+        self.refresh_inventory = true;
+        return Ok(());
+    }
+
+    fn store_diff(&mut self) -> Result<(), String> {
+        self.diff = true;
+        return Ok(());
+    }
+
     fn store_allow_localhost_delegation(&mut self) -> Result<(), String> {
         self.allow_localhost_delegation = true;
         Ok(())
@@ -697,6 +999,17 @@ impl CliParser  {
         return Ok(());
     }
 
+    // roles installed by 'jetp roles install' are found after all other role paths
+
+    fn add_role_cache_path(&mut self) -> Result<(), String> {
+        let cache_path = get_roles_cache_path();
+        if cache_path.is_dir() {
+            let full = fs::canonicalize(cache_path.as_path()).unwrap();
+            self.role_paths.write().unwrap().push(full.to_path_buf());
+        }
+        return Ok(());
+    }
+
     fn add_implicit_module_paths(&mut self) -> Result<(), String> {
         let paths = self.playbook_paths.read().unwrap();
         for pb in paths.iter() {
@@ -804,6 +1117,15 @@ impl CliParser  {
 
 }
 
+pub fn prompt_for_password(prompt: &String) -> Result<String, String> {
+    let mut value = String::new();
+    println!("{}", prompt);
+    return match io::stdin().read_line(&mut value) {
+        Ok(_) => Ok(String::from(value.trim())),
+        Err(e) => Err(format!("failure reading input: {}", e))
+    };
+}
+
 fn split_string(value: &String) -> Result<Vec<String>, String> {
     return Ok(value.split(":").map(|x| String::from(x)).collect());
 }
@@ -21,7 +21,7 @@ use crate::connection::local::LocalFactory;
 use crate::connection::no::NoFactory;
 use crate::playbooks::traversal::{playbook_traversal,RunState};
 use crate::playbooks::context::PlaybookContext;
-use crate::playbooks::visitor::{PlaybookVisitor,CheckMode};
+use crate::playbooks::visitor::{PlaybookVisitor,MultiVisitor,CheckMode};
 use crate::inventory::inventory::Inventory;
 use std::sync::{Arc,RwLock};
 
@@ -54,6 +54,10 @@ pub fn playbook_simulate(inventory: &Arc<RwLock<Inventory>>, parser: &CliParser)
 }
 
 fn playbook(inventory: &Arc<RwLock<Inventory>>, parser: &CliParser, check_mode: CheckMode, connection_mode: ConnectionMode) -> i32 {
+    let visitor = match MultiVisitor::new(check_mode, parser.diff, &parser.outputs, &parser.report_path) {
+        Ok(x) => x,
+        Err(s) => { println!("{}", s); return 1; }
+    };
     let run_state = Arc::new(RunState {
         // every object gets an inventory, though with local modes it's empty.
         inventory: Arc::clone(inventory),
@@ -67,7 +71,7 @@ fn playbook(inventory: &Arc<RwLock<Inventory>>, parser: &CliParser, check_mode:
         // to run-state.  Context should mostly *not* get parameters from the parser unless they
         // are going to appear in variables.
         context: Arc::new(RwLock::new(PlaybookContext::new(parser))),
-        visitor: Arc::new(RwLock::new(PlaybookVisitor::new(check_mode))),
+        visitor: Arc::new(RwLock::new(visitor)),
         connection_factory: match connection_mode {
             ConnectionMode::Ssh => Arc::new(RwLock::new(SshFactory::new(inventory, parser.forward_agent, parser.login_password.clone()))),
             ConnectionMode::Local => Arc::new(RwLock::new(LocalFactory::new(inventory))),
@@ -78,7 +82,11 @@ fn playbook(inventory: &Arc<RwLock<Inventory>>, parser: &CliParser, check_mode:
     });
     return match playbook_traversal(&run_state) {
         Ok(_)  => run_state.visitor.read().unwrap().get_exit_status(&run_state.context),
-        Err(s) => { println!("{}", s); 1 }
+        Err(s) => {
+            run_state.visitor.read().unwrap().on_error(&run_state.context, &s);
+            println!("{}", s);
+            1
+        }
     };
 }
 
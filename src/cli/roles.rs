@@ -0,0 +1,448 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path,PathBuf};
+use std::process::Command;
+use expanduser::expanduser;
+use guid_create::GUID;
+use serde::{Deserialize,Serialize};
+use sha2::{Sha512, Digest};
+use crate::cli::parser::CliParser;
+use crate::playbooks::language::Role;
+use crate::util::io::{jet_file_open,read_local_file};
+use crate::util::yaml::show_yaml_error_in_context;
+
+// cli support for the roles subcommand
+//
+// jetp roles install [--requirements requirements.yml]
+//
+// each role in the requirements file comes from a git repository or a local tarball and is
+// installed into the roles cache ($JET_ROLES_CACHE or ~/.cache/jetp/roles), which playbook runs
+// search after the other role paths.  The git commit (or tarball checksum) of each role is written
+// to a lockfile next to the requirements file, and later installs use the locked commit so every
+// machine gets the same content.  Remove an entry from the lockfile to pick up a new version.
+//
+// - name: nginx
+//   git: https://git.example.com/roles/nginx.git
+//   version: v1.2
+// - name: certs
+//   tarball: dist/certs.tar.gz
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RoleRequirement {
+    pub name: String,
+    pub git: Option<String>,
+    pub tarball: Option<String>,
+    pub version: Option<String>
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LockedRole {
+    pub name: String,
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha512: Option<String>
+}
+
+// ==============================================================================================================
+// PUBLIC API
+// ==============================================================================================================
+
+pub fn handle_roles(parser: &CliParser) -> Result<(), String> {
+    return match parser.roles_action.as_deref() {
+        Some("install") => roles_install(parser),
+        _ => Err(String::from("usage: jetp roles install [--requirements requirements.yml]"))
+    };
+}
+
+// where installed roles live, this is also added to the role search path for playbook runs
+
+pub fn get_roles_cache_path() -> PathBuf {
+    return match env::var("JET_ROLES_CACHE") {
+        Ok(x) => PathBuf::from(x),
+        Err(_) => match expanduser("~/.cache/jetp/roles") {
+            Ok(x) => x,
+            Err(_) => PathBuf::from(".jetp_roles_cache")
+        }
+    };
+}
+
+// ==============================================================================================================
+// PRIVATE INTERNALS
+// ==============================================================================================================
+
+fn roles_install(parser: &CliParser) -> Result<(), String> {
+
+    let requirements_path = match &parser.requirements_path {
+        Some(x) => x.clone(),
+        None => PathBuf::from("requirements.yml")
+    };
+    return install_roles(&requirements_path, &get_roles_cache_path());
+}
+
+fn install_roles(requirements_path: &PathBuf, cache_path: &PathBuf) -> Result<(), String> {
+
+    let requirements = load_requirements(requirements_path)?;
+    let lock_path = requirements_path.with_extension("lock");
+    let locked = load_lockfile(&lock_path)?;
+
+    // relative tarball paths are relative to the requirements file
+    let base_dir = match requirements_path.parent() {
+        Some(x) if ! x.as_os_str().is_empty() => x.to_path_buf(),
+        _ => PathBuf::from(".")
+    };
+
+    if let Err(y) = fs::create_dir_all(cache_path) {
+        return Err(format!("unable to create roles cache {}: {}", cache_path.display(), y));
+    }
+
+    let mut results : Vec<LockedRole> = Vec::new();
+    for requirement in requirements.iter() {
+        let source = get_source(requirement);
+        let previous = locked.iter().find(|x| x.name.eq(&requirement.name) && x.source.eq(&source) && x.version.eq(&requirement.version));
+
+        // roles are unpacked into a staging directory in the cache and only replace the installed
+        // copy once they have been checked, so a failed install leaves the old one in place
+        let staging = cache_path.join(format!(".{}-{}", requirement.name, GUID::rand().to_string()));
+        let result = match requirement.git.is_some() {
+            true => install_git(requirement, &source, previous, &staging),
+            false => install_tarball(requirement, &base_dir, previous, &staging)
+        };
+        let result = result.and_then(|(role_dir, lock)| {
+            validate_role(&requirement.name, &role_dir)?;
+            replace_dir(&role_dir, &cache_path.join(&requirement.name))?;
+            Ok(lock)
+        });
+        let _ = fs::remove_dir_all(&staging);
+        let lock = result?;
+
+        match &lock.commit {
+            Some(commit) => println!("installed: {} ({})", lock.name, commit),
+            None => println!("installed: {}", lock.name)
+        }
+        results.push(lock);
+    }
+
+    write_lockfile(&lock_path, &results)?;
+    println!("roles installed to {}, lockfile: {}", cache_path.display(), lock_path.display());
+    return Ok(());
+}
+
+fn load_requirements(path: &Path) -> Result<Vec<RoleRequirement>, String> {
+    let file = jet_file_open(path)?;
+    let parsed: Result<Vec<RoleRequirement>, serde_yaml::Error> = serde_yaml::from_reader(file);
+    let requirements = match parsed {
+        Ok(x) => x,
+        Err(y) => {
+            show_yaml_error_in_context(&y, path);
+            return Err(format!("edit the file and try again?"));
+        }
+    };
+    validate_requirements(&requirements)?;
+    return Ok(requirements);
+}
+
+fn validate_requirements(requirements: &Vec<RoleRequirement>) -> Result<(), String> {
+    let mut names : HashSet<String> = HashSet::new();
+    for requirement in requirements.iter() {
+        let name = &requirement.name;
+        if name.is_empty() || name.starts_with(".") || name.contains("/") || name.contains("\\") {
+            return Err(format!("invalid role name in requirements: {:?}", name));
+        }
+        if ! names.insert(name.clone()) {
+            return Err(format!("role {} is listed more than once in requirements", name));
+        }
+        match (&requirement.git, &requirement.tarball) {
+            (Some(_), None) => {
+                // the version is passed to git checkout, where it must not be taken for an option
+                if requirement.version.as_ref().is_some_and(|x| x.starts_with("-")) {
+                    return Err(format!("role {}: invalid version: {}", name, requirement.version.as_ref().unwrap()));
+                }
+            },
+            (None, Some(_)) => {
+                if requirement.version.is_some() {
+                    return Err(format!("role {}: version can only be used with git", name));
+                }
+            },
+            (_, _) => { return Err(format!("role {}: exactly one of git or tarball is required", name)); }
+        }
+    }
+    return Ok(());
+}
+
+fn get_source(requirement: &RoleRequirement) -> String {
+    return match &requirement.git {
+        Some(x) => x.clone(),
+        None => requirement.tarball.as_ref().unwrap().clone()
+    };
+}
+
+fn install_git(requirement: &RoleRequirement, source: &String, previous: Option<&LockedRole>, staging: &PathBuf) -> Result<(PathBuf, LockedRole), String> {
+
+    // a locked commit wins over the version, which may be a branch that has moved since
+    let reference = match previous {
+        Some(x) if x.commit.is_some() => x.commit.clone(),
+        _ => requirement.version.clone()
+    };
+
+    // '--' keeps a source starting with a dash from being read as an option
+    run_command(Command::new("git").arg("clone").arg("--quiet").arg("--").arg(source).arg(staging))?;
+    if let Some(reference) = &reference {
+        run_command(Command::new("git").arg("-C").arg(staging).arg("checkout").arg("--quiet").arg(reference))?;
+    }
+    let commit = run_command(Command::new("git").arg("-C").arg(staging).arg("rev-parse").arg("HEAD"))?;
+    if let Err(y) = fs::remove_dir_all(staging.join(".git")) {
+        return Err(format!("unable to remove {}: {}", staging.join(".git").display(), y));
+    }
+
+    return Ok((staging.clone(), LockedRole {
+        name: requirement.name.clone(),
+        source: source.clone(),
+        version: requirement.version.clone(),
+        commit: Some(String::from(commit.trim())),
+        sha512: None
+    }));
+}
+
+fn install_tarball(requirement: &RoleRequirement, base_dir: &PathBuf, previous: Option<&LockedRole>, staging: &PathBuf) -> Result<(PathBuf, LockedRole), String> {
+
+    let source = requirement.tarball.as_ref().unwrap();
+    let path = base_dir.join(source);
+    let bytes = match fs::read(&path) {
+        Ok(x) => x,
+        Err(y) => { return Err(format!("unable to read tarball {}: {}", path.display(), y)); }
+    };
+    let mut hasher = Sha512::new();
+    hasher.update(&bytes);
+    let checksum = format!("{:x}", hasher.finalize());
+
+    if let Some(previous) = previous {
+        if previous.sha512.is_some() && ! previous.sha512.as_ref().unwrap().eq(&checksum) {
+            return Err(format!("role {}: {} does not match the checksum in the lockfile, remove the entry to accept the new tarball", requirement.name, path.display()));
+        }
+    }
+
+    if let Err(y) = fs::create_dir_all(staging) {
+        return Err(format!("unable to create {}: {}", staging.display(), y));
+    }
+    run_command(Command::new("tar").arg("-xf").arg(&path).arg("-C").arg(staging))?;
+
+    // tarballs often wrap the role in a single top level directory
+    let mut role_dir = staging.clone();
+    if ! role_dir.join("role.yml").exists() {
+        let entries : Vec<PathBuf> = match fs::read_dir(staging) {
+            Ok(x) => x.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+            Err(y) => { return Err(format!("unable to read {}: {}", staging.display(), y)); }
+        };
+        if entries.len() == 1 && entries[0].is_dir() {
+            role_dir = entries[0].clone();
+        }
+    }
+
+    return Ok((role_dir, LockedRole {
+        name: requirement.name.clone(),
+        source: source.clone(),
+        version: None,
+        commit: None,
+        sha512: Some(checksum)
+    }));
+}
+
+// the same check find_role makes when a playbook uses the role, done before the role is installed
+
+fn validate_role(name: &String, role_dir: &PathBuf) -> Result<(), String> {
+    let role_file = role_dir.join("role.yml");
+    if ! role_file.is_file() {
+        return Err(format!("role {}: no role.yml found, this does not look like a jetp role", name));
+    }
+    let parsed: Result<Role, serde_yaml::Error> = serde_yaml::from_reader(jet_file_open(&role_file)?);
+    if let Err(y) = parsed {
+        return Err(format!("role {}: role.yml could not be loaded: {}", name, y));
+    }
+    return Ok(());
+}
+
+fn replace_dir(from: &PathBuf, to: &PathBuf) -> Result<(), String> {
+    if to.exists() {
+        if let Err(y) = fs::remove_dir_all(to) {
+            return Err(format!("unable to remove {}: {}", to.display(), y));
+        }
+    }
+    return match fs::rename(from, to) {
+        Ok(_) => Ok(()),
+        Err(y) => Err(format!("unable to move {} to {}: {}", from.display(), to.display(), y))
+    };
+}
+
+fn load_lockfile(path: &Path) -> Result<Vec<LockedRole>, String> {
+    if ! path.exists() {
+        return Ok(Vec::new());
+    }
+    return match serde_yaml::from_str(&read_local_file(path)?) {
+        Ok(x) => Ok(x),
+        Err(y) => Err(format!("unable to load lockfile {}: {}", path.display(), y))
+    };
+}
+
+fn write_lockfile(path: &Path, locked: &Vec<LockedRole>) -> Result<(), String> {
+    let body = match serde_yaml::to_string(locked) {
+        Ok(x) => x,
+        Err(y) => { return Err(format!("unable to serialize lockfile: {}", y)); }
+    };
+    let contents = format!("# written by 'jetp roles install', commit this file alongside the requirements\n{}", body);
+    return match fs::write(path, contents) {
+        Ok(_) => Ok(()),
+        Err(y) => Err(format!("unable to write lockfile {}: {}", path.display(), y))
+    };
+}
+
+fn run_command(command: &mut Command) -> Result<String, String> {
+    let description = format!("{:?}", command);
+    return match command.output() {
+        Ok(x) if x.status.success() => Ok(String::from_utf8_lossy(&x.stdout).to_string()),
+        Ok(x) => Err(format!("{} failed: {}", description, String::from_utf8_lossy(&x.stderr).trim())),
+        Err(y) => Err(format!("unable to run {}: {}", description, y))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_requirements() {
+        let good : Vec<RoleRequirement> = serde_yaml::from_str("- name: nginx\n  git: /srv/nginx.git\n  version: v1\n- name: certs\n  tarball: certs.tgz\n").unwrap();
+        assert!(validate_requirements(&good).is_ok());
+        let both : Vec<RoleRequirement> = serde_yaml::from_str("- name: nginx\n  git: /srv/nginx.git\n  tarball: nginx.tgz\n").unwrap();
+        assert!(validate_requirements(&both).is_err());
+        let duplicate : Vec<RoleRequirement> = serde_yaml::from_str("- name: a\n  git: x\n- name: a\n  git: y\n").unwrap();
+        assert!(validate_requirements(&duplicate).is_err());
+        let escaping : Vec<RoleRequirement> = serde_yaml::from_str("- name: ../a\n  git: x\n").unwrap();
+        assert!(validate_requirements(&escaping).is_err());
+        let option : Vec<RoleRequirement> = serde_yaml::from_str("- name: a\n  git: x\n  version: --help\n").unwrap();
+        assert!(validate_requirements(&option).is_err());
+    }
+
+    fn run_in(dir: &PathBuf, args: &[&str]) -> String {
+        let program = args[0];
+        return run_command(Command::new(program).current_dir(dir).args(&args[1..])).unwrap().trim().to_string();
+    }
+
+    fn commit_role(work: &PathBuf, role_yml: &str) -> String {
+        fs::write(work.join("role.yml"), role_yml).unwrap();
+        run_in(work, &["git", "add", "role.yml"]);
+        run_in(work, &["git", "-c", "user.name=test", "-c", "user.email=test@example.com", "commit", "--quiet", "-m", "update"]);
+        run_in(work, &["git", "push", "--quiet", "origin", "HEAD"]);
+        return run_in(work, &["git", "rev-parse", "HEAD"]);
+    }
+
+    fn installed_commit(lock_path: &PathBuf, name: &str) -> Option<String> {
+        let locked = load_lockfile(lock_path).unwrap();
+        return locked.iter().find(|x| x.name.eq(name)).unwrap().commit.clone();
+    }
+
+    fn leftover_staging(cache: &PathBuf) -> usize {
+        return fs::read_dir(cache).unwrap().filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().starts_with(".")).count();
+    }
+
+    #[test]
+    fn test_install_from_git() {
+        let base = env::temp_dir().join(format!("jetp-roles-test-{}", GUID::rand().to_string()));
+        let bare = base.join("web.git");
+        let work = base.join("work");
+        let cache = base.join("cache");
+        fs::create_dir_all(&base).unwrap();
+        run_in(&base, &["git", "init", "--quiet", "--bare", "web.git"]);
+        run_in(&base, &["git", "clone", "--quiet", "web.git", "work"]);
+
+        let first = commit_role(&work, "name: web\n");
+        let requirements = base.join("requirements.yml");
+        let lock_path = base.join("requirements.lock");
+        fs::write(&requirements, format!("- name: web\n  git: {}\n", bare.display())).unwrap();
+
+        install_roles(&requirements, &cache).unwrap();
+        assert_eq!(installed_commit(&lock_path, "web"), Some(first.clone()));
+        assert!(cache.join("web/role.yml").is_file());
+        assert!(! cache.join("web/.git").exists());
+
+        // new commits are not picked up while the lockfile pins the old one
+        let second = commit_role(&work, "name: web\ndefaults:\n  port: 80\n");
+        install_roles(&requirements, &cache).unwrap();
+        assert_eq!(installed_commit(&lock_path, "web"), Some(first.clone()));
+        assert_eq!(fs::read_to_string(cache.join("web/role.yml")).unwrap(), "name: web\n");
+
+        fs::remove_file(&lock_path).unwrap();
+        install_roles(&requirements, &cache).unwrap();
+        assert_eq!(installed_commit(&lock_path, "web"), Some(second.clone()));
+
+        // a broken role.yml is refused and the installed copy stays as it was
+        commit_role(&work, "name: web\nnot_a_role_field: true\n");
+        fs::remove_file(&lock_path).unwrap();
+        assert!(install_roles(&requirements, &cache).is_err());
+        assert_eq!(fs::read_to_string(cache.join("web/role.yml")).unwrap(), "name: web\ndefaults:\n  port: 80\n");
+        assert_eq!(leftover_staging(&cache), 0);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_install_from_tarball() {
+        let base = env::temp_dir().join(format!("jetp-roles-test-{}", GUID::rand().to_string()));
+        let cache = base.join("cache");
+        fs::create_dir_all(base.join("pkg/certs")).unwrap();
+        fs::write(base.join("pkg/certs/role.yml"), "name: certs\n").unwrap();
+        run_in(&base, &["tar", "-czf", "certs.tar.gz", "-C", "pkg", "certs"]);
+
+        // relative to the requirements file, not the current directory
+        let requirements = base.join("requirements.yml");
+        let lock_path = base.join("requirements.lock");
+        fs::write(&requirements, "- name: certs\n  tarball: certs.tar.gz\n").unwrap();
+
+        install_roles(&requirements, &cache).unwrap();
+        assert_eq!(fs::read_to_string(cache.join("certs/role.yml")).unwrap(), "name: certs\n");
+        let locked = load_lockfile(&lock_path).unwrap();
+        let checksum = locked[0].sha512.clone().unwrap();
+        assert_eq!(checksum.len(), 128);
+
+        // a tarball that changed since it was locked is refused
+        fs::write(base.join("pkg/certs/role.yml"), "name: certs\ndefaults:\n  days: 30\n").unwrap();
+        run_in(&base, &["tar", "-czf", "certs.tar.gz", "-C", "pkg", "certs"]);
+        let result = install_roles(&requirements, &cache);
+        assert!(result.unwrap_err().contains("does not match the checksum in the lockfile"));
+        assert_eq!(fs::read_to_string(cache.join("certs/role.yml")).unwrap(), "name: certs\n");
+        assert_eq!(load_lockfile(&lock_path).unwrap()[0].sha512, Some(checksum));
+
+        // a tarball without a role.yml is refused as well
+        fs::remove_file(&lock_path).unwrap();
+        fs::remove_file(base.join("pkg/certs/role.yml")).unwrap();
+        fs::write(base.join("pkg/certs/README"), "not a role\n").unwrap();
+        run_in(&base, &["tar", "-czf", "certs.tar.gz", "-C", "pkg", "certs"]);
+        assert!(install_roles(&requirements, &cache).is_err());
+        assert_eq!(fs::read_to_string(cache.join("certs/role.yml")).unwrap(), "name: certs\n");
+        assert_eq!(leftover_staging(&cache), 0);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
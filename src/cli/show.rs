@@ -14,10 +14,23 @@
 // You should have received a copy of the GNU General Public License
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::util::terminal::{two_column_table, captioned_display};
+use crate::util::terminal::{two_column_table, captioned_display, markdown_print};
+use crate::util::yaml::{VariableLayer,blend_layers,yaml_key_as_string};
 use std::sync::Arc;
 use std::sync::RwLock;
+use std::env;
+use std::path::Path;
 use crate::inventory::inventory::Inventory;
+use crate::inventory::patterns::{HostPattern,PatternScope};
+use crate::cli::parser::CliParser;
+use crate::playbooks::context::PlaybookContext;
+use crate::playbooks::traversal::{load_playbook,load_vars_into_context};
+use crate::handle::template::BlendTarget;
+use crate::util::io::directory_as_string;
+use crate::util::secrets::mask_secrets_in_value;
+
+// values longer than this are cut short in the show-vars report
+const SHOW_VARS_VALUE_WIDTH: usize = 60;
 
 // cli support for the show-inventory subcommand
 
@@ -32,6 +45,20 @@ fn string_slice(values: &Vec<String>) -> String {
     return values.join(", ");
 }
 
+fn value_slice(value: &serde_yaml::Value) -> String {
+    // vault values are masked here as they are in task output
+    let value = mask_secrets_in_value(value);
+    let mut result = match serde_json::to_string(&value) {
+        Ok(x) => x,
+        Err(_) => String::from(serde_yaml::to_string(&value).unwrap_or(String::new()).trim())
+    };
+    if result.chars().count() > SHOW_VARS_VALUE_WIDTH {
+        result = format!("{}...", result.chars().take(SHOW_VARS_VALUE_WIDTH).collect::<String>());
+    }
+    // table cells can't contain the column separator, and masked values would be read as emphasis
+    return result.replace("|", "¦").replace("*", "\\*");
+}
+
 // ==============================================================================================================
 // PUBLIC API
 // ==============================================================================================================
@@ -143,5 +170,112 @@ pub fn show_inventory_group(inventory: &Arc<RwLock<Inventory>>, group_name: &Str
     return Ok(());
 }
 
+// jetp show --inventory <path> --show-pattern 'webservers:&prod:!web07'
+
+pub fn show_inventory_pattern(inventory: &Arc<RwLock<Inventory>>, pattern: &String) -> Result<(),String> {
+
+    let inventory = inventory.read().expect("inventory read");
+
+    let host_pattern = HostPattern::parse(pattern)?;
+    let hosts = host_pattern.select_names(&inventory, PatternScope::Any)?;
+
+    println!("Pattern: {}", pattern);
+    println!("");
+
+    let host_elements : Vec<(String, String)> = vec![
+        (format!("Matched Hosts ({})", hosts.len()), string_slice(&hosts)),
+    ];
+
+    two_column_table(&String::from("Pattern Report:"), &String::from(""), &host_elements);
+    println!("");
+
+    return Ok(());
+}
+
+// jetp show-vars --inventory <path> --show-hosts host1 [--playbook <path>] [--extra-vars @file]
+// without a playbook only inventory and extra vars are shown, with one the report is repeated for
+// every play that would target the host.  Role variables only exist while a role runs and are not shown.
+
+pub fn show_host_variables(inventory: &Arc<RwLock<Inventory>>, parser: &CliParser, host_name: &String) -> Result<(),String> {
+
+    let host = {
+        let inv = inventory.read().expect("inventory read");
+        if !inv.has_host(&host_name.clone()) {
+            return Err(format!("no such host: {}", host_name));
+        }
+        inv.get_host(&host_name.clone())
+    };
+    let context = Arc::new(RwLock::new(PlaybookContext::new(parser)));
+
+    let playbook_paths = parser.playbook_paths.read().unwrap().clone();
+    if playbook_paths.is_empty() {
+        println!("Host: {}", host_name);
+        println!("");
+        show_variable_layers(&context.read().unwrap().get_variable_layers(&host, BlendTarget::NotTemplateModule));
+        return Ok(());
+    }
+
+    for playbook_path in playbook_paths.iter() {
+        context.write().unwrap().set_playbook_path(playbook_path);
+        let plays = load_playbook(playbook_path)?;
+
+        // vars_files are relative to the playbook, as when running it
+        let previous = env::current_dir().expect("could not get current directory");
+        let pbdirname = directory_as_string(playbook_path);
+        if ! pbdirname.eq(&String::from("")) {
+            env::set_current_dir(Path::new(&pbdirname)).expect("could not chdir into playbook directory");
+        }
+
+        for play in plays.iter() {
+            let selected = {
+                let inv = inventory.read().expect("inventory read");
+                HostPattern::from_list(&play.groups)?.select(&inv, PatternScope::Any)?
+            };
+            if ! selected.contains_key(host_name) {
+                continue;
+            }
+            load_vars_into_context(&context, play)?;
+            println!("Host: {}, Play: {} ({})", host_name, play.name, playbook_path.display());
+            println!("");
+            show_variable_layers(&context.read().unwrap().get_variable_layers(&host, BlendTarget::NotTemplateModule));
+        }
+
+        env::set_current_dir(&previous).expect("could not restore previous directory");
+    }
+    return Ok(());
+}
+
+// one row per variable with its final value and every layer that set it, the last of which wins
+// (except that lists are appended to and mappings are merged, as with all variable blending)
+
+fn show_variable_layers(layers: &Vec<VariableLayer>) {
 
+    let blended = blend_layers(layers);
+    let blended = blended.as_mapping().expect("blended variables are a mapping");
 
+    let mut names : Vec<String> = blended.iter().map(|(k,_v)| yaml_key_as_string(k)).collect();
+    names.sort();
+
+    let mut buffer = String::from("|:-|:-|:-\n|Variable|Value|Set By (lowest precedence first)\n");
+    for name in names.iter() {
+        let value = match blended.get(&serde_yaml::Value::String(name.clone())) {
+            Some(x) => value_slice(x),
+            None => String::from("?")
+        };
+        let mut set_by : Vec<String> = Vec::new();
+        for layer in layers.iter() {
+            if ! layer.variables.contains_key(&serde_yaml::Value::String(name.clone())) {
+                continue;
+            }
+            set_by.push(match layer.get_source(name) {
+                Some(source) => format!("{} ({})", layer.name, source),
+                None => layer.name.clone()
+            });
+        }
+        buffer.push_str("|-|-|-\n");
+        buffer.push_str(&format!("|{}|{}|{}\n", name, value, set_by.join(" < ")));
+    }
+    buffer.push_str("|-|-|-\n");
+    markdown_print(&buffer);
+    println!("");
+}
@@ -0,0 +1,245 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::env;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::{Read,Write};
+use std::os::unix::fs::{OpenOptionsExt,PermissionsExt};
+use std::path::{Path,PathBuf};
+use std::process::Command;
+use guid_create::GUID;
+use crate::cli::parser::{CliParser,prompt_for_password};
+use crate::util::io::read_local_file;
+use crate::util::vault::{encrypt,decrypt,is_vaulted,format_vault_tag,read_vault_password_file,rekey_tagged_values};
+
+// cli support for the vault subcommand
+//
+// jetp vault encrypt <files>    encrypts whole files in place
+// jetp vault encrypt -          encrypts standard input, printing a !vault value to paste into a YAML file
+// jetp vault decrypt <files>    decrypts whole files in place, or standard input with -
+// jetp vault edit <file>        decrypts to a temporary file, runs $EDITOR, and encrypts the result
+// jetp vault rekey <files>      re-encrypts files, or the !vault values in them, with a new password
+
+// ==============================================================================================================
+// PUBLIC API
+// ==============================================================================================================
+
+pub fn handle_vault(parser: &CliParser) -> Result<(), String> {
+
+    let action = match &parser.vault_action {
+        Some(x) => x.clone(),
+        None => { return Err(String::from("usage: jetp vault encrypt|decrypt|edit|rekey <files>")); }
+    };
+    if parser.vault_files.is_empty() {
+        return Err(format!("jetp vault {} requires at least one file, or - for standard input", action));
+    }
+    let password = match &parser.vault_password {
+        Some(x) => x.clone(),
+        None => { return Err(String::from("a vault password is required, use --vault-password-file, --ask-vault-password or $JET_VAULT_PASSWORD_FILE")); }
+    };
+
+    return match action.as_str() {
+        "encrypt" => vault_encrypt(&parser.vault_files, &password),
+        "decrypt" => vault_decrypt(&parser.vault_files, &password),
+        "edit"    => vault_edit(&parser.vault_files, &password),
+        "rekey"   => vault_rekey(&parser.vault_files, &password, &get_new_password(parser)?),
+        _ => Err(format!("invalid vault action: {}", action))
+    };
+}
+
+// ==============================================================================================================
+// PRIVATE INTERNALS
+// ==============================================================================================================
+
+fn vault_encrypt(files: &Vec<String>, password: &String) -> Result<(), String> {
+    for file in files.iter() {
+        if file.eq("-") {
+            let plaintext = read_stdin()?;
+            print!("{}", format_vault_tag(&encrypt(&String::from(plaintext.trim_end()), password)?));
+            continue;
+        }
+        let path = Path::new(file);
+        let contents = read_local_file(path)?;
+        if is_vaulted(&contents) {
+            return Err(format!("{} is already vault encrypted", path.display()));
+        }
+        write_file(path, &encrypt(&contents, password)?)?;
+        println!("encrypted: {}", path.display());
+    }
+    return Ok(());
+}
+
+fn vault_decrypt(files: &Vec<String>, password: &String) -> Result<(), String> {
+    for file in files.iter() {
+        if file.eq("-") {
+            // accept a value pasted along with its !vault tag and indentation
+            let input = read_stdin()?;
+            let input = String::from(input.trim_start().trim_start_matches("!vault |-").trim_start_matches("!vault |"));
+            print!("{}", decrypt(&input, password)?);
+            continue;
+        }
+        let path = Path::new(file);
+        let contents = read_local_file(path)?;
+        if ! is_vaulted(&contents) {
+            return Err(format!("{} is not vault encrypted", path.display()));
+        }
+        let plaintext = match decrypt(&contents, password) {
+            Ok(x) => x,
+            Err(y) => { return Err(format!("{}: {}", path.display(), y)); }
+        };
+        write_file(path, &plaintext)?;
+        println!("decrypted: {}", path.display());
+    }
+    return Ok(());
+}
+
+fn vault_edit(files: &Vec<String>, password: &String) -> Result<(), String> {
+    for file in files.iter() {
+        let path = Path::new(file);
+        // editing a file that does not exist yet creates it
+        let plaintext = match path.exists() {
+            true => {
+                let contents = read_local_file(path)?;
+                if ! is_vaulted(&contents) {
+                    return Err(format!("{} is not vault encrypted, use 'jetp vault encrypt' first", path.display()));
+                }
+                match decrypt(&contents, password) {
+                    Ok(x) => x,
+                    Err(y) => { return Err(format!("{}: {}", path.display(), y)); }
+                }
+            },
+            false => String::new()
+        };
+
+        // the plaintext only ever exists in a file readable by the current user, and is removed afterwards
+        let tmp_path = env::temp_dir().join(format!("jetp-vault-{}.yml", GUID::rand().to_string()));
+        write_private_file(&tmp_path, &plaintext)?;
+        let edited = run_editor(&tmp_path).and_then(|_| read_local_file(&tmp_path));
+        let _ = fs::remove_file(&tmp_path);
+        let edited = edited?;
+
+        if edited.eq(&plaintext) {
+            println!("unchanged: {}", path.display());
+            continue;
+        }
+        write_file(path, &encrypt(&edited, password)?)?;
+        println!("saved: {}", path.display());
+    }
+    return Ok(());
+}
+
+fn vault_rekey(files: &Vec<String>, password: &String, new_password: &String) -> Result<(), String> {
+    for file in files.iter() {
+        let path = Path::new(file);
+        let contents = read_local_file(path)?;
+        let rekeyed = match is_vaulted(&contents) {
+            true => match decrypt(&contents, password) {
+                Ok(x) => encrypt(&x, new_password)?,
+                Err(y) => { return Err(format!("{}: {}", path.display(), y)); }
+            },
+            false => match rekey_tagged_values(&contents, password, new_password) {
+                Ok(Some(x)) => x,
+                Ok(None) => { return Err(format!("{} does not contain any vault encrypted content", path.display())); },
+                Err(y) => { return Err(format!("{}: {}", path.display(), y)); }
+            }
+        };
+        write_file(path, &rekeyed)?;
+        println!("rekeyed: {}", path.display());
+    }
+    return Ok(());
+}
+
+fn get_new_password(parser: &CliParser) -> Result<String, String> {
+    let new_password = match &parser.new_vault_password_file {
+        Some(x) => read_vault_password_file(x)?,
+        None => prompt_for_password(&String::from("enter new vault password:"))?
+    };
+    if new_password.is_empty() {
+        return Err(String::from("the new vault password cannot be empty"));
+    }
+    return Ok(new_password);
+}
+
+fn run_editor(path: &PathBuf) -> Result<(), String> {
+    let editor = match env::var("EDITOR") {
+        Ok(x) => x,
+        Err(_) => String::from("vi")
+    };
+    // $EDITOR may include arguments, such as "code --wait"
+    let mut tokens = editor.split_whitespace();
+    let program = match tokens.next() {
+        Some(x) => x,
+        None => { return Err(String::from("$EDITOR is empty")); }
+    };
+    return match Command::new(program).args(tokens).arg(path).status() {
+        Ok(x) if x.success() => Ok(()),
+        Ok(x) => Err(format!("editor exited with {:?}, leaving the vault file unchanged", x.code())),
+        Err(y) => Err(format!("unable to run editor ({}): {}", editor, y))
+    };
+}
+
+fn read_stdin() -> Result<String, String> {
+    let mut buffer = String::new();
+    return match std::io::stdin().read_to_string(&mut buffer) {
+        Ok(_) => Ok(buffer),
+        Err(y) => Err(format!("failure reading input: {}", y))
+    };
+}
+
+// the files written here are often the only copy of the secrets in them, so a new copy is written next
+// to the original and renamed over it, and an interrupted write leaves the original alone
+
+fn write_file(path: &Path, contents: &String) -> Result<(), String> {
+    // a linked file is replaced where it really lives, rather than the link being replaced by a file
+    let path = &fs::canonicalize(path).unwrap_or(path.to_path_buf());
+    let name = match path.file_name() {
+        Some(x) => x.to_string_lossy().to_string(),
+        None => { return Err(format!("unable to write {}: not a file", path.display())); }
+    };
+    let tmp_path = path.with_file_name(format!(".{}.{}.tmp", name, GUID::rand().to_string()));
+    // the new file keeps the permissions of the one it replaces
+    let mode = match fs::metadata(path) {
+        Ok(x) => x.permissions().mode() & 0o777,
+        Err(_) => 0o600
+    };
+    let written = match OpenOptions::new().write(true).create_new(true).mode(mode).open(&tmp_path) {
+        Ok(mut file) => file.write_all(contents.as_bytes()).and_then(|_| file.sync_all()),
+        Err(y) => Err(y)
+    };
+    if let Err(y) = written {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(format!("unable to write {}: {}", tmp_path.display(), y));
+    }
+    return match fs::rename(&tmp_path, path) {
+        Ok(_) => Ok(()),
+        Err(y) => {
+            let _ = fs::remove_file(&tmp_path);
+            Err(format!("unable to write {}: {}", path.display(), y))
+        }
+    };
+}
+
+fn write_private_file(path: &Path, contents: &String) -> Result<(), String> {
+    let written = match OpenOptions::new().write(true).create_new(true).mode(0o600).open(path) {
+        Ok(mut file) => file.write_all(contents.as_bytes()),
+        Err(y) => Err(y)
+    };
+    return match written {
+        Ok(_) => Ok(()),
+        Err(y) => Err(format!("unable to write {}: {}", path.display(), y))
+    };
+}
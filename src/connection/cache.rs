@@ -16,19 +16,28 @@
 
 use crate::connection::connection::{Connection};
 use crate::inventory::hosts::Host;
+use crate::connection::jump::SshJumpHost;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::RwLock;
 use std::collections::HashMap;
 
 pub struct ConnectionCache {
-    connections: HashMap<String, Arc<Mutex<dyn Connection>>>
+    connections: HashMap<String, Arc<Mutex<dyn Connection>>>,
+    // bastion sessions used by jet_ssh_proxy_jump, keyed by the chain of user@host:port hops leading to them
+    jump_hosts: HashMap<String, JumpHostSlot>
 }
 
+// each bastion gets its own lock, held while connecting to it, so hosts behind the same bastion wait for
+// one session instead of each opening their own, without blocking lookups for everything else
+
+pub type JumpHostSlot = Arc<Mutex<Option<Arc<SshJumpHost>>>>;
+
 impl ConnectionCache {
     pub fn new() -> Self {
         Self {
-            connections: HashMap::new()
+            connections: HashMap::new(),
+            jump_hosts: HashMap::new()
         }
     }
 
@@ -47,7 +56,14 @@ impl ConnectionCache {
         return Arc::clone(self.connections.get(&host2.name.clone()).unwrap());
     }
 
+    pub fn get_jump_host_slot(&mut self, key: &String) -> JumpHostSlot {
+        let slot = self.jump_hosts.entry(key.clone()).or_insert_with(|| Arc::new(Mutex::new(None)));
+        return Arc::clone(slot);
+    }
+
     pub fn clear(&mut self) {
+        // open connections keep their own reference to any bastion they go through
         self.connections.clear();
+        self.jump_hosts.clear();
     }
 }
\ No newline at end of file
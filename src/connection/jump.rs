@@ -0,0 +1,228 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use ssh2::{Session,Channel,ErrorCode};
+use std::io::{Read,Write,ErrorKind};
+use std::os::unix::io::{AsRawFd,RawFd};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::{Duration,Instant};
+
+// support for reaching hosts through one or more bastions (jet_ssh_proxy_jump), equivalent to OpenSSH's ProxyJump.
+//
+// libssh2 sessions need a real socket, so for each target we open a direct-tcpip channel on the bastion
+// session and pump bytes between it and one end of a unix socket pair, handing the other end to the new
+// session as if it were a TCP connection.  The bastion session is switched to non-blocking mode once it
+// is authenticated so that any number of tunnels can share it.
+
+const LIBSSH2_ERROR_EAGAIN: i32 = -37;
+
+// one hop in a proxy jump chain, parsed from [ssh://][user@]host[:port]
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct JumpSpec {
+    pub user: Option<String>,
+    pub hostname: String,
+    pub port: Option<i64>,
+}
+
+pub struct SshJumpHost {
+    pub name: String,
+    session: Session,
+}
+
+// ==============================================================================================================
+// PUBLIC API
+// ==============================================================================================================
+
+// "bastion1,deploy@bastion2:2222" -> two hops, connected in order
+
+pub fn parse_proxy_jump(value: &String) -> Result<Vec<JumpSpec>, String> {
+    let mut results : Vec<JumpSpec> = Vec::new();
+    for item in value.split(",") {
+        let mut rest = item.trim();
+        if rest.is_empty() {
+            return Err(format!("invalid jet_ssh_proxy_jump: {}", value));
+        }
+        if rest.starts_with("ssh://") {
+            rest = &rest[6..];
+        }
+        let user = match rest.rfind("@") {
+            Some(idx) => {
+                let u = String::from(&rest[0..idx]);
+                rest = &rest[idx+1..];
+                Some(u)
+            },
+            None => None
+        };
+        // [2001:db8::1]:22 style addresses need the brackets to tell the port apart
+        let (hostname, port_str) = match rest.starts_with("[") {
+            true => match rest.find("]") {
+                Some(idx) => (String::from(&rest[1..idx]), rest[idx+1..].strip_prefix(":")),
+                None => { return Err(format!("invalid jet_ssh_proxy_jump host: {}", item)); }
+            },
+            false => match rest.rfind(":") {
+                Some(idx) => (String::from(&rest[0..idx]), Some(&rest[idx+1..])),
+                None => (String::from(rest), None)
+            }
+        };
+        let port = match port_str {
+            Some(p) => match p.parse::<i64>() {
+                Ok(x) => Some(x),
+                Err(_) => { return Err(format!("invalid port in jet_ssh_proxy_jump: {}", item)); }
+            },
+            None => None
+        };
+        if hostname.is_empty() {
+            return Err(format!("invalid jet_ssh_proxy_jump host: {}", item));
+        }
+        results.push(JumpSpec { user, hostname, port });
+    }
+    return Ok(results);
+}
+
+impl SshJumpHost {
+
+    // takes an authenticated session to the bastion
+    pub fn new(name: &String, session: Session) -> Self {
+        session.set_blocking(false);
+        Self { name: name.clone(), session }
+    }
+
+    // returns a socket that is connected to hostname:port through this bastion
+    pub fn tunnel(&self, hostname: &String, port: i64) -> Result<UnixStream, String> {
+        let port16 = match u16::try_from(port) {
+            Ok(x) => x,
+            Err(_) => { return Err(format!("invalid SSH port: {}", port)); }
+        };
+
+        let started = Instant::now();
+        let channel = loop {
+            match self.session.channel_direct_tcpip(hostname, port16, None) {
+                Ok(x) => break x,
+                Err(y) => {
+                    if y.code() != ErrorCode::Session(LIBSSH2_ERROR_EAGAIN) {
+                        return Err(format!("unable to reach {}:{} through jump host {}: {}", hostname, port, self.name, y));
+                    }
+                    if started.elapsed() > Duration::from_secs(10) {
+                        return Err(format!("timed out reaching {}:{} through jump host {}", hostname, port, self.name));
+                    }
+                    thread::sleep(Duration::from_millis(5));
+                }
+            }
+        };
+
+        let (ours, theirs) = match UnixStream::pair() {
+            Ok(x) => x,
+            Err(y) => { return Err(format!("unable to create socket pair for jump host tunnel: {}", y)); }
+        };
+        match ours.set_nonblocking(true) {
+            Ok(_) => {},
+            Err(y) => { return Err(format!("unable to configure jump host tunnel: {}", y)); }
+        };
+        let session_fd = self.session.as_raw_fd();
+        thread::spawn(move || { pump(channel, ours, session_fd); });
+        return Ok(theirs);
+    }
+}
+
+// ==============================================================================================================
+// PRIVATE INTERNALS
+// ==============================================================================================================
+
+// when both ends of a tunnel are idle, wait for either socket to become ready.  The wait is capped
+// because tunnels share the bastion session, and a read made for one tunnel can leave data for
+// another buffered inside libssh2 where poll() cannot see it.
+
+const PUMP_WAIT_MS: i32 = 20;
+
+// shuffles data in both directions until either side closes.  Both ends are non-blocking, so this
+// polls the bastion socket and the local one whenever there was nothing to do.
+
+fn pump(mut channel: Channel, mut socket: UnixStream, session_fd: RawFd) {
+    let mut buf = [0u8; 32768];
+    let mut to_socket : Vec<u8> = Vec::new();
+    let mut to_channel : Vec<u8> = Vec::new();
+
+    loop {
+        let mut idle = true;
+
+        if to_socket.is_empty() {
+            match channel.read(&mut buf) {
+                Ok(0) => { if channel.eof() { break; } },
+                Ok(n) => { to_socket.extend_from_slice(&buf[0..n]); idle = false; },
+                Err(y) if y.kind() == ErrorKind::WouldBlock => {},
+                Err(_) => { break; }
+            }
+        }
+        if ! to_socket.is_empty() {
+            match socket.write(&to_socket) {
+                Ok(n) => { to_socket.drain(0..n); idle = false; },
+                Err(y) if y.kind() == ErrorKind::WouldBlock => {},
+                Err(_) => { break; }
+            }
+        }
+
+        if to_channel.is_empty() {
+            match socket.read(&mut buf) {
+                // the session on the other end went away
+                Ok(0) => { break; },
+                Ok(n) => { to_channel.extend_from_slice(&buf[0..n]); idle = false; },
+                Err(y) if y.kind() == ErrorKind::WouldBlock => {},
+                Err(_) => { break; }
+            }
+        }
+        if ! to_channel.is_empty() {
+            match channel.write(&to_channel) {
+                Ok(n) => { to_channel.drain(0..n); idle = false; },
+                Err(y) if y.kind() == ErrorKind::WouldBlock => {},
+                Err(_) => { break; }
+            }
+        }
+
+        if idle {
+            wait_for_sockets(session_fd, &socket, ! to_channel.is_empty(), ! to_socket.is_empty());
+        }
+    }
+    let _ = channel.close();
+}
+
+fn wait_for_sockets(session_fd: RawFd, socket: &UnixStream, channel_writes: bool, socket_writes: bool) {
+    let session_events = libc::POLLIN | match channel_writes { true => libc::POLLOUT, false => 0 };
+    let socket_events = match socket_writes { true => libc::POLLOUT, false => libc::POLLIN };
+    let mut fds = [
+        libc::pollfd { fd: session_fd, events: session_events, revents: 0 },
+        libc::pollfd { fd: socket.as_raw_fd(), events: socket_events, revents: 0 },
+    ];
+    // errors and hangups come back as readiness, and the next read or write reports them
+    unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, PUMP_WAIT_MS); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proxy_jump() {
+        let hops = parse_proxy_jump(&String::from("bastion1, deploy@bastion2:2222,ssh://[2001:db8::1]:22")).unwrap();
+        assert_eq!(hops.len(), 3);
+        assert_eq!(hops[0], JumpSpec { user: None, hostname: String::from("bastion1"), port: None });
+        assert_eq!(hops[1], JumpSpec { user: Some(String::from("deploy")), hostname: String::from("bastion2"), port: Some(2222) });
+        assert_eq!(hops[2], JumpSpec { user: None, hostname: String::from("2001:db8::1"), port: Some(22) });
+        assert!(parse_proxy_jump(&String::from("a,,b")).is_err());
+        assert!(parse_proxy_jump(&String::from("a:notaport")).is_err());
+    }
+}
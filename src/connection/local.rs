@@ -146,6 +146,14 @@ impl Connection for LocalConnection {
         }
     }
 
+    fn fetch_file(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, remote_path: &String, dest: &Path) -> Result<(), Arc<TaskResponse>> {
+        let result = std::fs::copy(Path::new(remote_path), dest);
+        return match result {
+            Ok(_x) => Ok(()),
+            Err(e) => { return Err(response.is_failed(&request, &format!("fetch failed: {:?}", e))) }
+        }
+    }
+
     fn write_data(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, data: &String, remote_path: &String) -> Result<(),Arc<TaskResponse>> {
         let path = Path::new(&remote_path);
         if path.exists() {
@@ -17,6 +17,8 @@
 pub mod connection;
 pub mod factory;
 pub mod ssh;
+pub mod ssh_config;
+pub mod jump;
 pub mod local;
 pub mod no;
 pub mod command;
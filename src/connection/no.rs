@@ -89,4 +89,9 @@ impl Connection for NoConnection {
        return Ok(());
    }
 
+   fn fetch_file(&self, _response: &Arc<Response>, _request: &Arc<TaskRequest>, _remote_path: &String, _dest: &Path) -> Result<(), Arc<TaskResponse>> {
+       // no data is transferred, as per above
+       return Ok(());
+   }
+
 }
\ No newline at end of file
@@ -25,20 +25,42 @@ use crate::Inventory;
 use crate::handle::response::Response;
 use crate::connection::command::Forward;
 use crate::connection::local::convert_out;
+use crate::connection::jump::{SshJumpHost,parse_proxy_jump};
 use std::process::Command;
 use std::sync::{Arc,Mutex,RwLock};
-use ssh2::Session;
+use ssh2::{Session,CheckResult,KnownHostFileKind,KnownHosts,HostKeyType};
 use std::io::{Read,Write};
 use std::net::TcpStream;
-use std::path::Path;
+use std::path::{Path,PathBuf};
 use std::time::Duration;
 use std::net::ToSocketAddrs;
-use std::fs::File;
+use std::fs::{File,OpenOptions};
+use std::ffi::CString;
 //use std::io;
 use std::io;
 
 // implementation for both Ssh Connections and the Ssh Connection factory
 
+// how to treat the host key presented by the remote end, compared against the known_hosts file.
+// strict refuses unknown keys, accept-new records unknown keys (but still refuses changed ones)
+// and off skips verification entirely.
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum HostKeyChecking {
+    Strict,
+    AcceptNew,
+    Off
+}
+
+pub fn host_key_checking_from_string(s: &String) -> Result<HostKeyChecking, String> {
+    return match s.as_str() {
+        "strict"     => Ok(HostKeyChecking::Strict),
+        "accept-new" => Ok(HostKeyChecking::AcceptNew),
+        "off"        => Ok(HostKeyChecking::Off),
+        _ => Err(format!("invalid host key checking policy: {}, expecting strict, accept-new, or off", s))
+    }
+}
+
 pub struct SshFactory {
     local_factory: LocalFactory,
     localhost: Arc<RwLock<Host>>,
@@ -87,15 +109,26 @@ impl ConnectionFactory for SshFactory {
         // how we connect to a host depends on some settings of the play (ssh_port, ssh_user), the CLI (--user) and
         // possibly magic variables on the host.  The context contains all of this logic.
         let (hostname2, user, port, key, passphrase, key_comment) = ctx.get_ssh_connection_details(host);
-        if hostname2.eq("localhost") { 
+        let (host_key_checking, known_hosts) = ctx.get_ssh_host_key_details(host);
+        let (identity_files, identities_only) = ctx.get_ssh_identity_files(host);
+        let proxy_jump = ctx.get_ssh_proxy_jump(host);
+        if hostname2.eq("localhost") && proxy_jump.is_none() { 
             // jet_ssh_hostname was set to localhost, which doesn't make a lot of sense but could happen in testing
             // contrived playbooks when we don't want a lot of real remote hosts
             let conn : Arc<Mutex<dyn Connection>> = self.local_factory.get_connection(context, &self.localhost)?;
             return Ok(conn); 
         }
 
+        let jump_host = match &proxy_jump {
+            Some(spec) => Some(self.get_jump_host(&ctx, spec, host_key_checking, &known_hosts)?),
+            None => None
+        };
+
         // actually connect here
-        let mut conn = SshConnection::new(Arc::clone(&host), &user, port, hostname2, self.forward_agent, self.login_password.clone(), key, passphrase, key_comment);
+        let mut conn = SshConnection::new(Arc::clone(&host), &user, port, hostname2, self.forward_agent, self.login_password.clone(), key, passphrase, key_comment,
+            host_key_checking, known_hosts, identity_files, identities_only);
+        conn.proxy_jump = proxy_jump;
+        conn.jump_host = jump_host;
         return match conn.connect() {
             Ok(_)  => { 
                 let conn2 : Arc<Mutex<dyn Connection>> = Arc::new(Mutex::new(conn));
@@ -107,7 +140,63 @@ impl ConnectionFactory for SshFactory {
         }
     }
 }
- 
+
+impl SshFactory {
+
+    // bastions named in jet_ssh_proxy_jump are connected to in order, each through the one before it.  Every
+    // partial chain is kept in the connection cache so that all hosts behind the same bastion share one session.
+    // Bastion keys are checked with the policy and known_hosts file of the host being reached, so hosts only
+    // share a session when those match, see get_jump_host_slot_key.  The host's key passphrase is not used,
+    // bastions authenticate with the agent or the identity files from the SSH config.
+
+    fn get_jump_host(&self, ctx: &PlaybookContext, spec: &String, host_key_checking: HostKeyChecking, known_hosts: &PathBuf)
+        -> Result<Arc<SshJumpHost>, String> {
+
+        let hops = parse_proxy_jump(spec)?;
+
+        let mut previous : Option<Arc<SshJumpHost>> = None;
+        let mut chain : Vec<String> = Vec::new();
+
+        for hop in hops.iter() {
+            // bastions are configured through the SSH config (if at all) rather than inventory, as with OpenSSH
+            let config = ctx.ssh_config.lookup(&hop.hostname, &ctx.ssh_user);
+            let hostname = config.hostname.clone().unwrap_or(hop.hostname.clone());
+            let port = hop.port.unwrap_or(config.port.unwrap_or(22));
+            let user = hop.user.clone().unwrap_or(config.user.clone().unwrap_or(ctx.ssh_user.clone()));
+            chain.push(format!("{}@{}:{}", user, hostname, port));
+            let key = get_jump_host_slot_key(&chain, host_key_checking, known_hosts);
+
+            // the cache itself is only locked long enough to find the slot for this hop
+            let slot = ctx.connection_cache.write().expect("connection cache write").get_jump_host_slot(&key);
+            let mut slot = slot.lock().expect("jump host lock");
+            if let Some(existing) = slot.as_ref() {
+                previous = Some(Arc::clone(existing));
+                continue;
+            }
+
+            let bastion = Arc::new(RwLock::new(Host::new(&hop.hostname)));
+            let mut conn = SshConnection::new(bastion, &user, port, hostname, false, None, None, None, None,
+                host_key_checking, known_hosts.clone(), config.identity_files, config.identities_only);
+            conn.jump_host = previous;
+            let session = match conn.establish_session() {
+                Ok(x) => x,
+                Err(y) => { return Err(format!("jump host {}: {}", hop.hostname, y)); }
+            };
+            let jump_host = Arc::new(SshJumpHost::new(&hop.hostname, session));
+            *slot = Some(Arc::clone(&jump_host));
+            previous = Some(jump_host);
+        }
+        return Ok(previous.expect("at least one jump host"));
+    }
+}
+
+// a session to a bastion whose key was checked less strictly, or against another known_hosts file, must not
+// be handed to a host that asks for more, so both are part of the cache key along with the chain of hops
+
+fn get_jump_host_slot_key(chain: &Vec<String>, host_key_checking: HostKeyChecking, known_hosts: &PathBuf) -> String {
+    return format!("{} ({:?}, {})", chain.join(","), host_key_checking, known_hosts.display());
+}
+
 pub struct SshConnection {
     pub host: Arc<RwLock<Host>>,
     pub username: String,
@@ -119,11 +208,19 @@ pub struct SshConnection {
     pub key: Option<String>,
     pub passphrase: Option<String>,
     pub key_comment: Option<String>,
+    pub host_key_checking: HostKeyChecking,
+    pub known_hosts: PathBuf,
+    pub identity_files: Vec<String>,
+    pub identities_only: bool,
+    pub proxy_jump: Option<String>,
+    pub jump_host: Option<Arc<SshJumpHost>>,
 }
 
 impl SshConnection {
-    pub fn new(host: Arc<RwLock<Host>>, username: &String, port: i64, hostname: String, forward_agent: bool, login_password: Option<String>, key: Option<String>, passphrase: Option<String>, key_comment: Option<String>) -> Self {
-        Self { host: Arc::clone(&host), username: username.clone(), port, hostname, session: None, forward_agent, login_password, key, passphrase, key_comment }
+    pub fn new(host: Arc<RwLock<Host>>, username: &String, port: i64, hostname: String, forward_agent: bool, login_password: Option<String>, key: Option<String>, passphrase: Option<String>, key_comment: Option<String>,
+        host_key_checking: HostKeyChecking, known_hosts: PathBuf, identity_files: Vec<String>, identities_only: bool) -> Self {
+        Self { host: Arc::clone(&host), username: username.clone(), port, hostname, session: None, forward_agent, login_password, key, passphrase, key_comment,
+            host_key_checking, known_hosts, identity_files, identities_only, proxy_jump: None, jump_host: None }
     }
 }
 
@@ -142,6 +239,154 @@ impl Connection for SshConnection {
             return Ok(());
         }
 
+        assert!(!self.host.read().expect("host read").name.eq("localhost"));
+        let sess = self.establish_session()?;
+      
+        // OS detection -- always run uname -a on first connect so we know the OS type, which will allow the command library and facts
+        // module to work correctly.
+
+        self.session = Some(sess);
+
+        let uname_result = self.run_command_low_level(&String::from("uname -a"));
+        match uname_result {
+            Ok((_rc,out)) => {
+                {
+                    match self.host.write().unwrap().set_os_info(&out.clone()) {
+                        Ok(_x) => {},
+                        Err(_y) => return Err(format!("failed to set OS info"))
+                    }
+                }
+                //match result2 { Ok(_) => {}, Err(s) => { return Err(s.to_string()) } }
+            },
+            Err((rc,out)) => return Err(format!("uname -a command failed: rc={}, out={}", rc,out))
+        }
+
+
+        return Ok(());
+    }
+
+    fn run_command(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &String, forward: Forward) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        let result = match forward {   
+            Forward::Yes => match self.forward_agent {
+                false => self.run_command_low_level(cmd),
+                true  => self.run_command_with_ssh_a(cmd)
+            },
+            Forward::No => self.run_command_low_level(cmd)
+        };
+
+        match result {
+            Ok((rc,s)) => {
+                // note that non-zero return codes are "ok" to the connection plugin, handle elsewhere!
+                return Ok(response.command_ok(request, &Arc::new(Some(CommandResult { cmd: cmd.clone(), out: s.clone(), rc: rc }))));
+            }, 
+            Err((rc,s)) => {
+                return Err(response.command_failed(request, &Arc::new(Some(CommandResult { cmd: cmd.clone(), out: s.clone(), rc: rc }))));
+            }
+        }
+    }
+
+    fn write_data(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, data: &String, remote_path: &String) -> Result<(),Arc<TaskResponse>> {
+
+        // SFTP writing does not allow root to overwrite files root does not own, and does not support sudo. 
+        // as such this is a pretty low level write (as is copy_file) and logic around tempfiles and permissions is handled in remote.rs
+
+        // write_data writes a string and is really meant for small files like the template module. Large files should use copy_file instead.
+
+        let session = self.session.as_ref().expect("session not established");
+        let sftp_result = session.sftp();
+        let sftp = match sftp_result {
+            Ok(x) => x,
+            Err(y) => { return Err(response.is_failed(request, &format!("sftp connection failed: {y}"))); }
+        };
+        let sftp_path = Path::new(&remote_path);
+        let fh_result = sftp.create(sftp_path);
+        let mut fh = match fh_result {
+            Ok(x) => x,
+            Err(y) => { return Err(response.is_failed(request, &format!("sftp open failed: {y}"))) }
+        };
+        let bytes = data.as_bytes();
+        match fh.write_all(bytes) {
+            Ok(_x) => {},
+            Err(y) => { return Err(response.is_failed(request, &format!("sftp write failed: {y}"))); }
+        }
+
+        return Ok(());
+    }
+
+    fn copy_file(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, src: &Path, remote_path: &String) -> Result<(), Arc<TaskResponse>> {
+
+        // this is a streaming copy that should be fine with large files.
+
+        let src_open_result = File::open(src);
+        let src = match src_open_result {
+            Ok(x) => x,
+            Err(y) => { return Err(response.is_failed(request, &format!("failed to open source file: {y}"))); }
+        };
+
+        let session = self.session.as_ref().expect("session not established");
+        let sftp_result = session.sftp();
+        let sftp = match sftp_result {
+            Ok(x) => x,
+            Err(y) => { return Err(response.is_failed(request, &format!("sftp connection failed: {y}"))); }
+        };
+        let sftp_path = Path::new(&remote_path);
+        let fh_result = sftp.create(sftp_path);
+        let fh = match fh_result {
+            Ok(x) => x,
+            Err(y) => { return Err(response.is_failed(request, &format!("sftp write failed (1): {y}"))) }
+        };
+
+        let mut src2 = std::io::BufReader::with_capacity(1000000, src);
+        let mut fh2 = std::io::BufWriter::with_capacity(1000000, fh);
+
+        match io::copy(&mut src2, &mut fh2) {
+            Ok(_) => {},
+            Err(y) => { return Err(response.is_failed(request, &format!("sftp copy failed (1): {y}"))) }
+        };
+
+        return Ok(());
+    }
+
+    fn fetch_file(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, remote_path: &String, dest: &Path) -> Result<(), Arc<TaskResponse>> {
+
+        // the streaming reverse of copy_file.  As with copy_file, sudo is not available here, so remote.rs
+        // first copies files to a location the connecting user can read.
+
+        let session = self.session.as_ref().expect("session not established");
+        let sftp_result = session.sftp();
+        let sftp = match sftp_result {
+            Ok(x) => x,
+            Err(y) => { return Err(response.is_failed(request, &format!("sftp connection failed: {y}"))); }
+        };
+        let sftp_path = Path::new(&remote_path);
+        let fh = match sftp.open(sftp_path) {
+            Ok(x) => x,
+            Err(y) => { return Err(response.is_failed(request, &format!("sftp read failed (1): {y}"))) }
+        };
+        let dest_file = match File::create(dest) {
+            Ok(x) => x,
+            Err(y) => { return Err(response.is_failed(request, &format!("failed to create destination file: {y}"))); }
+        };
+
+        let mut fh2 = std::io::BufReader::with_capacity(1000000, fh);
+        let mut dest2 = std::io::BufWriter::with_capacity(1000000, dest_file);
+
+        match io::copy(&mut fh2, &mut dest2) {
+            Ok(_) => {},
+            Err(y) => { return Err(response.is_failed(request, &format!("sftp copy failed (2): {y}"))) }
+        };
+
+        return Ok(());
+    }
+}
+
+impl SshConnection {
+
+    // everything needed to get an authenticated session, shared by regular connections and by jump hosts,
+    // which don't need the OS detection in connect()
+
+    fn establish_session(&self) -> Result<Session, String> {
+
         // derived from docs at https://docs.rs/ssh2/latest/ssh2/
         let session = match Session::new() { Ok(x) => x, Err(_y) => { return Err(String::from("failed to attach to session")); } };
         match session.agent() { 
@@ -165,27 +410,26 @@ impl Connection for SshConnection {
         // currently we don't do anything with listing the identities in SSH agent.  It might be helpful to provide a nice error
         // if none were detected
 
-        // Connect to the local SSH server - need to get socketaddrs first in order to use Duration for timeout
-        let seconds = Duration::from_secs(10);
-        assert!(!self.host.read().expect("host read").name.eq("localhost"));
-        let connect_str = format!("{host}:{port}", host=self.hostname, port=self.port.to_string());
-        // connect with timeout requires SocketAddr objects instead of just connection strings
-        let addrs_iter = connect_str.as_str().to_socket_addrs();
-        
-        // check for errors
-        let mut addrs_iter2 = match addrs_iter { Err(_x) => { return Err(String::from("unable to resolve")); }, Ok(y) => y };
-        let addr = addrs_iter2.next();
-        if ! addr.is_some() { return Err(String::from("unable to resolve(2)"));  }
-        
-        // actually connect (finally) here
-        let tcp = match TcpStream::connect_timeout(&addr.unwrap(), seconds) { Ok(x) => x, _ => { 
-            return Err(format!("SSH connection attempt failed for {}:{}", self.hostname, self.port)); } };
-        
         // new session & handshake
         let mut sess = match Session::new() { Ok(x) => x, _ => { return Err(String::from("SSH session failed")); } };
-        sess.set_tcp_stream(tcp);
+
+        match &self.jump_host {
+            Some(jump_host) => {
+                // the host is only reachable through a bastion, so tunnel through the (already authenticated) session to it
+                let stream = jump_host.tunnel(&self.hostname, self.port)?;
+                sess.set_tcp_stream(stream);
+            },
+            None => {
+                let tcp = self.connect_tcp()?;
+                sess.set_tcp_stream(tcp);
+            }
+        };
+
         match sess.handshake() { Ok(_) => {}, _ => { return Err(String::from("SSH handshake failed")); } } ;
-        
+
+        // make sure we are talking to the host we think we are before sending any credentials
+        self.verify_host_key(&sess)?;
+
         if self.login_password.is_some() {
             match sess.userauth_password(&self.username.clone(), self.login_password.clone().unwrap().as_str()) {
                 Ok(_) => {},
@@ -250,126 +494,82 @@ impl Connection for SshConnection {
                 if !found {
                     return Err(format!("specified SSH key not found with comment {}", self.key_comment.clone().unwrap()));
                 }
+            } else if self.identities_only && !self.identity_files.is_empty() {
+                // the SSH config says to only use the listed keys, so the agent is not consulted
+                self.authenticate_with_identity_files(&sess)?;
             } else {
                 // no key comment specified, do not use a specific key
                 match sess.userauth_agent(&self.username) { 
                     Ok(_) => {}, 
                     Err(x) => { 
-                        return Err(format!("SSH agent authentication failed for user {}: {}", self.username, x));
+                        // like OpenSSH, fall back to any IdentityFile entries from the SSH config
+                        if self.identity_files.is_empty() {
+                            return Err(format!("SSH agent authentication failed for user {}: {}", self.username, x));
+                        }
+                        self.authenticate_with_identity_files(&sess)?;
                     }
                 };
             }
         }
 
         if !(sess.authenticated()) { return Err("failed to authenticate".to_string()); };
-      
-        // OS detection -- always run uname -a on first connect so we know the OS type, which will allow the command library and facts
-        // module to work correctly.
-
-        self.session = Some(sess);
-
-        let uname_result = self.run_command_low_level(&String::from("uname -a"));
-        match uname_result {
-            Ok((_rc,out)) => {
-                {
-                    match self.host.write().unwrap().set_os_info(&out.clone()) {
-                        Ok(_x) => {},
-                        Err(_y) => return Err(format!("failed to set OS info"))
-                    }
-                }
-                //match result2 { Ok(_) => {}, Err(s) => { return Err(s.to_string()) } }
-            },
-            Err((rc,out)) => return Err(format!("uname -a command failed: rc={}, out={}", rc,out))
-        }
-
-
-        return Ok(());
+        return Ok(sess);
     }
 
-    fn run_command(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, cmd: &String, forward: Forward) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
-        let result = match forward {   
-            Forward::Yes => match self.forward_agent {
-                false => self.run_command_low_level(cmd),
-                true  => self.run_command_with_ssh_a(cmd)
-            },
-            Forward::No => self.run_command_low_level(cmd)
-        };
-
-        match result {
-            Ok((rc,s)) => {
-                // note that non-zero return codes are "ok" to the connection plugin, handle elsewhere!
-                return Ok(response.command_ok(request, &Arc::new(Some(CommandResult { cmd: cmd.clone(), out: s.clone(), rc: rc }))));
-            }, 
-            Err((rc,s)) => {
-                return Err(response.command_failed(request, &Arc::new(Some(CommandResult { cmd: cmd.clone(), out: s.clone(), rc: rc }))));
-            }
-        }
+    fn connect_tcp(&self) -> Result<TcpStream, String> {
+        // Connect to the local SSH server - need to get socketaddrs first in order to use Duration for timeout
+        let seconds = Duration::from_secs(10);
+        let connect_str = format!("{host}:{port}", host=self.hostname, port=self.port.to_string());
+        // connect with timeout requires SocketAddr objects instead of just connection strings
+        let addrs_iter = connect_str.as_str().to_socket_addrs();
+        
+        // check for errors
+        let mut addrs_iter2 = match addrs_iter { Err(_x) => { return Err(String::from("unable to resolve")); }, Ok(y) => y };
+        let addr = addrs_iter2.next();
+        if ! addr.is_some() { return Err(String::from("unable to resolve(2)"));  }
+        
+        // actually connect (finally) here
+        let tcp = match TcpStream::connect_timeout(&addr.unwrap(), seconds) { Ok(x) => x, _ => { 
+            return Err(format!("SSH connection attempt failed for {}:{}", self.hostname, self.port)); } };
+        return Ok(tcp);
     }
 
-    fn write_data(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, data: &String, remote_path: &String) -> Result<(),Arc<TaskResponse>> {
-
-        // SFTP writing does not allow root to overwrite files root does not own, and does not support sudo. 
-        // as such this is a pretty low level write (as is copy_file) and logic around tempfiles and permissions is handled in remote.rs
+    fn verify_host_key(&self, sess: &Session) -> Result<(), String> {
 
-        // write_data writes a string and is really meant for small files like the template module. Large files should use copy_file instead.
-
-        let session = self.session.as_ref().expect("session not established");
-        let sftp_result = session.sftp();
-        let sftp = match sftp_result {
-            Ok(x) => x,
-            Err(y) => { return Err(response.is_failed(request, &format!("sftp connection failed: {y}"))); }
-        };
-        let sftp_path = Path::new(&remote_path);
-        let fh_result = sftp.create(sftp_path);
-        let mut fh = match fh_result {
-            Ok(x) => x,
-            Err(y) => { return Err(response.is_failed(request, &format!("sftp open failed: {y}"))) }
-        };
-        let bytes = data.as_bytes();
-        match fh.write_all(bytes) {
-            Ok(_x) => {},
-            Err(y) => { return Err(response.is_failed(request, &format!("sftp write failed: {y}"))); }
+        if self.host_key_checking == HostKeyChecking::Off {
+            return Ok(());
         }
 
-        return Ok(());
-    }
-
-    fn copy_file(&self, response: &Arc<Response>, request: &Arc<TaskRequest>, src: &Path, remote_path: &String) -> Result<(), Arc<TaskResponse>> {
-
-        // this is a streaming copy that should be fine with large files.
-
-        let src_open_result = File::open(src);
-        let src = match src_open_result {
-            Ok(x) => x,
-            Err(y) => { return Err(response.is_failed(request, &format!("failed to open source file: {y}"))); }
+        let (key, key_type) = match sess.host_key() {
+            Some(x) => x,
+            None => { return Err(format!("SSH server at {}:{} did not present a host key", self.hostname, self.port)); }
         };
-
-        let session = self.session.as_ref().expect("session not established");
-        let sftp_result = session.sftp();
-        let sftp = match sftp_result {
+        let known_hosts = load_known_hosts(sess, &self.known_hosts)?;
+        let port = match u16::try_from(self.port) {
             Ok(x) => x,
-            Err(y) => { return Err(response.is_failed(request, &format!("sftp connection failed: {y}"))); }
-        };
-        let sftp_path = Path::new(&remote_path);
-        let fh_result = sftp.create(sftp_path);
-        let fh = match fh_result {
-            Ok(x) => x,
-            Err(y) => { return Err(response.is_failed(request, &format!("sftp write failed (1): {y}"))) }
-        };
-
-        let mut src2 = std::io::BufReader::with_capacity(1000000, src);
-        let mut fh2 = std::io::BufWriter::with_capacity(1000000, fh);
-
-        match io::copy(&mut src2, &mut fh2) {
-            Ok(_) => {},
-            Err(y) => { return Err(response.is_failed(request, &format!("sftp copy failed (1): {y}"))) }
+            Err(_) => { return Err(format!("invalid SSH port: {}", self.port)); }
         };
-
-        return Ok(());
+        return check_host_key(&known_hosts, sess, &self.hostname, port, key, key_type, self.host_key_checking, &self.known_hosts);
     }
-}
 
-impl SshConnection {
+    fn authenticate_with_identity_files(&self, sess: &Session) -> Result<(), String> {
+        let mut errors : Vec<String> = Vec::new();
+        for identity_file in self.identity_files.iter() {
+            let keypath = Path::new(identity_file);
+            if ! keypath.exists() {
+                continue;
+            }
+            match sess.userauth_pubkey_file(&self.username.clone(), None, keypath, self.passphrase.as_deref()) {
+                Ok(_) => { return Ok(()); },
+                Err(x) => { errors.push(format!("{}: {}", identity_file, x)); }
+            };
+        }
+        if errors.is_empty() {
+            return Err(format!("SSH key authentication failed for user {}: none of the configured identity files exist ({})", 
+                self.username, self.identity_files.join(", ")));
+        }
+        return Err(format!("SSH key authentication failed for user {}: {}", self.username, errors.join("; ")));
+    }
 
     fn trim_newlines(&self, s: &mut String) {
         if s.ends_with('\n') {
@@ -407,6 +607,9 @@ impl SshConnection {
         let hostname = &self.host.read().unwrap().name;
         let port = format!("{}", self.port);
         let cmd2 = format!("LANG=C {} 2>&1", cmd);
+        if self.proxy_jump.is_some() {
+            base.arg("-J").arg(self.proxy_jump.as_ref().unwrap());
+        }
         let command = base.arg(hostname).arg("-p").arg(port).arg("-l").arg(self.username.clone()).arg("-A").arg(cmd2);
         match command.output() {
             Ok(x) => {
@@ -428,3 +631,172 @@ impl SshConnection {
     }
 
 }
+
+// a missing known_hosts file is the same as an empty one, accept-new will create it
+
+fn load_known_hosts(sess: &Session, known_hosts_path: &PathBuf) -> Result<KnownHosts, String> {
+    let mut known_hosts = match sess.known_hosts() {
+        Ok(x) => x,
+        Err(y) => { return Err(format!("unable to initialize known hosts: {}", y)); }
+    };
+    if known_hosts_path.exists() {
+        match known_hosts.read_file(known_hosts_path.as_path(), KnownHostFileKind::OpenSSH) {
+            Ok(_) => {},
+            Err(y) => { return Err(format!("unable to read known hosts file {}: {}", known_hosts_path.display(), y)); }
+        }
+    }
+    return Ok(known_hosts);
+}
+
+// compares the key presented by hostname:port with the entries already loaded into known_hosts, and
+// under accept-new records it in the known_hosts file if there is no entry for the host yet
+
+fn check_host_key(known_hosts: &KnownHosts, sess: &Session, hostname: &String, port: u16, key: &[u8], key_type: HostKeyType,
+    host_key_checking: HostKeyChecking, known_hosts_path: &PathBuf) -> Result<(), String> {
+
+    return match known_hosts.check_port(hostname, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => Err(format!("SSH host key verification failed: the key presented by {}:{} does not match the entry in {}. \
+            The host may have been reinstalled, or someone may be intercepting the connection. \
+            If the change is expected, remove the old entry and try again.", hostname, port, known_hosts_path.display())),
+        CheckResult::Failure => Err(format!("SSH host key verification failed for {}:{}: unable to check {}", hostname, port, known_hosts_path.display())),
+        CheckResult::NotFound => match host_key_checking {
+            HostKeyChecking::AcceptNew => record_host_key(sess, hostname, port, key, key_type, known_hosts_path),
+            _ => Err(format!("SSH host key verification failed: no entry for {}:{} in {}. \
+                Connect once with ssh to record it, or use --host-key-checking accept-new", hostname, port, known_hosts_path.display()))
+        }
+    };
+}
+
+// OpenSSH only uses the bracketed form for non-standard ports
+
+fn get_known_hosts_entry_name(hostname: &String, port: u16) -> String {
+    return match port {
+        22 => hostname.clone(),
+        _  => format!("[{}]:{}", hostname, port)
+    };
+}
+
+fn record_host_key(sess: &Session, hostname: &String, port: u16, key: &[u8], key_type: HostKeyType, known_hosts_path: &PathBuf) -> Result<(), String> {
+
+    // the new entry is appended rather than rewriting the whole file through libssh2, which would
+    // lose any comments and formatting the user has in there
+
+    let entry_name = get_known_hosts_entry_name(hostname, port);
+    let mut new_hosts = match sess.known_hosts() {
+        Ok(x) => x,
+        Err(y) => { return Err(format!("unable to initialize known hosts: {}", y)); }
+    };
+    // libssh2 copies the comment including the NUL after it, which a Rust "" does not have
+    let no_comment = CString::new("").unwrap();
+    match new_hosts.add(&entry_name, key, no_comment.to_str().unwrap(), key_type.into()) {
+        Ok(_) => {},
+        Err(y) => { return Err(format!("unable to add host key for {}: {}", entry_name, y)); }
+    };
+    let mut line = String::new();
+    for entry in new_hosts.hosts().unwrap_or(Vec::new()).iter() {
+        match new_hosts.write_string(entry, KnownHostFileKind::OpenSSH) {
+            Ok(x) => { line.push_str(&x); },
+            Err(y) => { return Err(format!("unable to format host key for {}: {}", entry_name, y)); }
+        }
+    }
+
+    match known_hosts_path.parent() {
+        Some(parent) => { let _ = std::fs::create_dir_all(parent); },
+        None => {}
+    };
+    let mut fh = match OpenOptions::new().create(true).append(true).open(known_hosts_path) {
+        Ok(x) => x,
+        Err(y) => { return Err(format!("unable to open known hosts file {}: {}", known_hosts_path.display(), y)); }
+    };
+    return match fh.write_all(line.as_bytes()) {
+        Ok(_) => Ok(()),
+        Err(y) => Err(format!("unable to write known hosts file {}: {}", known_hosts_path.display(), y))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jump_host_slot_key() {
+        let chain = vec![String::from("deploy@bastion:22")];
+        let known_hosts = PathBuf::from("/home/deploy/.ssh/known_hosts");
+        let strict = get_jump_host_slot_key(&chain, HostKeyChecking::Strict, &known_hosts);
+
+        // two hosts behind the same bastion with the same checking share one session
+        assert_eq!(strict, get_jump_host_slot_key(&chain.clone(), HostKeyChecking::Strict, &known_hosts.clone()));
+
+        // but not when either asks for less checking or trusts another known_hosts file
+        assert_ne!(strict, get_jump_host_slot_key(&chain, HostKeyChecking::Off, &known_hosts));
+        assert_ne!(strict, get_jump_host_slot_key(&chain, HostKeyChecking::AcceptNew, &known_hosts));
+        assert_ne!(strict, get_jump_host_slot_key(&chain, HostKeyChecking::Strict, &PathBuf::from("/tmp/known_hosts")));
+
+        // nor do different chains that end at the same bastion
+        let longer = vec![String::from("deploy@outer:22"), String::from("deploy@bastion:22")];
+        assert_ne!(strict, get_jump_host_slot_key(&longer, HostKeyChecking::Strict, &known_hosts));
+    }
+
+    #[test]
+    fn test_host_key_checking_from_string() {
+        assert_eq!(host_key_checking_from_string(&String::from("strict")), Ok(HostKeyChecking::Strict));
+        assert_eq!(host_key_checking_from_string(&String::from("accept-new")), Ok(HostKeyChecking::AcceptNew));
+        assert_eq!(host_key_checking_from_string(&String::from("off")), Ok(HostKeyChecking::Off));
+        assert!(host_key_checking_from_string(&String::from("yes")).is_err());
+        assert!(host_key_checking_from_string(&String::from("Strict")).is_err());
+    }
+
+    #[test]
+    fn test_known_hosts_entry_name() {
+        assert_eq!(get_known_hosts_entry_name(&String::from("web1.example.com"), 22), String::from("web1.example.com"));
+        assert_eq!(get_known_hosts_entry_name(&String::from("web1.example.com"), 2222), String::from("[web1.example.com]:2222"));
+    }
+
+    // an ssh-rsa public key blob.  libssh2 only compares the bytes, so they do not need to be a real key.
+    fn rsa_key(fill: u8) -> Vec<u8> {
+        let mut key : Vec<u8> = Vec::new();
+        key.extend_from_slice(&[0, 0, 0, 7]);
+        key.extend_from_slice(b"ssh-rsa");
+        key.extend_from_slice(&[0, 0, 0, 3, 1, 0, 1, 0, 0, 0, 64]);
+        key.extend_from_slice(&[fill; 64]);
+        return key;
+    }
+
+    fn check(sess: &Session, hostname: &str, port: u16, key: &[u8], policy: HostKeyChecking, path: &PathBuf) -> Result<(), String> {
+        let known_hosts = load_known_hosts(sess, path)?;
+        return check_host_key(&known_hosts, sess, &String::from(hostname), port, key, HostKeyType::Rsa, policy, path);
+    }
+
+    #[test]
+    fn test_check_host_key() {
+        // no connection is needed to work with known_hosts entries
+        let sess = Session::new().unwrap();
+        let dir = std::env::temp_dir().join(format!("jetp-known-hosts-test-{}", guid_create::GUID::rand().to_string()));
+        let path = dir.join("known_hosts");
+        let (key, other_key) = (rsa_key(1), rsa_key(2));
+
+        // with no entry (and no file yet), strict refuses and accept-new records the key
+        assert!(check(&sess, "web1", 22, &key, HostKeyChecking::Strict, &path).unwrap_err().contains("no entry for web1:22"));
+        assert!(! path.exists());
+        assert_eq!(check(&sess, "web1", 22, &key, HostKeyChecking::AcceptNew, &path), Ok(()));
+        assert_eq!(check(&sess, "web2", 2222, &key, HostKeyChecking::AcceptNew, &path), Ok(()));
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines : Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("web1 ssh-rsa "));
+        assert!(lines[1].starts_with("[web2]:2222 ssh-rsa "));
+
+        // once recorded the key matches, even under strict
+        assert_eq!(check(&sess, "web1", 22, &key, HostKeyChecking::Strict, &path), Ok(()));
+        assert_eq!(check(&sess, "web2", 2222, &key, HostKeyChecking::Strict, &path), Ok(()));
+
+        // a changed key is refused under every policy, and not recorded
+        assert!(check(&sess, "web1", 22, &other_key, HostKeyChecking::Strict, &path).unwrap_err().contains("does not match"));
+        assert!(check(&sess, "web1", 22, &other_key, HostKeyChecking::AcceptNew, &path).unwrap_err().contains("does not match"));
+        assert!(check(&sess, "web2", 2222, &other_key, HostKeyChecking::AcceptNew, &path).unwrap_err().contains("does not match"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), contents);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
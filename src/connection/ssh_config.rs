@@ -0,0 +1,332 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::path::Path;
+use std::env;
+use expanduser::expanduser;
+use crate::util::io::read_local_file;
+use crate::util::wildcard::wildcard_match;
+
+// a small reader for OpenSSH client configuration (~/.ssh/config) so that host aliases, ports, users
+// and keys already set up for the ssh command line also work with jetp. Only the keywords we can act
+// on are kept, everything else in the file is ignored.  As with OpenSSH, the first value obtained for
+// a keyword wins, except for IdentityFile where all values are collected.
+
+pub struct SshConfig {
+    blocks: Vec<SshConfigBlock>
+}
+
+// the resolved settings for one host
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct SshConfigHost {
+    pub hostname: Option<String>,
+    pub port: Option<i64>,
+    pub user: Option<String>,
+    pub identity_files: Vec<String>,
+    pub identities_only: bool,
+}
+
+enum Criteria {
+    // options before the first Host or Match line apply to everything
+    Always,
+    Host(Vec<String>),
+    Match(Vec<MatchCriterion>),
+}
+
+struct MatchCriterion {
+    negated: bool,
+    keyword: String,
+    patterns: Vec<String>
+}
+
+struct SshConfigBlock {
+    criteria: Criteria,
+    options: Vec<(String,String)>
+}
+
+// ==============================================================================================================
+// PUBLIC API
+// ==============================================================================================================
+
+impl SshConfig {
+
+    pub fn new() -> Self {
+        Self { blocks: Vec::new() }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = read_local_file(path)?;
+        return match SshConfig::parse(&contents) {
+            Ok(x) => Ok(x),
+            Err(y) => Err(format!("{}: {}", path.display(), y))
+        };
+    }
+
+    pub fn parse(contents: &String) -> Result<Self, String> {
+        let mut blocks : Vec<SshConfigBlock> = Vec::new();
+        let mut current = SshConfigBlock { criteria: Criteria::Always, options: Vec::new() };
+
+        for (line_number, raw_line) in contents.lines().enumerate() {
+            let line = raw_line.trim();
+            if line.is_empty() || line.starts_with("#") {
+                continue;
+            }
+            let (keyword, args) = split_line(line);
+            if args.is_empty() {
+                return Err(format!("line {}: missing argument for {}", line_number + 1, keyword));
+            }
+            match keyword.as_str() {
+                "host" => {
+                    blocks.push(current);
+                    current = SshConfigBlock { criteria: Criteria::Host(args), options: Vec::new() };
+                },
+                "match" => {
+                    blocks.push(current);
+                    current = SshConfigBlock { criteria: Criteria::Match(parse_match_criteria(&args, line_number + 1)?), options: Vec::new() };
+                },
+                "hostname" | "port" | "user" | "identityfile" | "identitiesonly" => {
+                    if keyword.eq("port") && args[0].parse::<i64>().is_err() {
+                        return Err(format!("line {}: invalid port: {}", line_number + 1, args[0]));
+                    }
+                    current.options.push((keyword.clone(), args[0].clone()));
+                },
+                _ => {}
+            }
+        }
+        blocks.push(current);
+        return Ok(Self { blocks });
+    }
+
+    // resolve the settings for a host as it is named in inventory.  default_user is the user jetp would
+    // otherwise connect as, which "Match user" blocks are tested against if no User has been set yet.
+
+    pub fn lookup(&self, alias: &String, default_user: &String) -> SshConfigHost {
+        let mut result = SshConfigHost { hostname: None, port: None, user: None, identity_files: Vec::new(), identities_only: false };
+        let mut identities_only_set = false;
+
+        for block in self.blocks.iter() {
+            let current_host = result.hostname.clone().unwrap_or(alias.clone());
+            let current_user = result.user.clone().unwrap_or(default_user.clone());
+            if ! block.matches(alias, &current_host, &current_user) {
+                continue;
+            }
+            for (keyword, value) in block.options.iter() {
+                match keyword.as_str() {
+                    "hostname" => if result.hostname.is_none() {
+                        result.hostname = Some(expand_tokens(value, alias, &current_host, &current_user));
+                    },
+                    "port" => if result.port.is_none() {
+                        result.port = value.parse::<i64>().ok();
+                    },
+                    "user" => if result.user.is_none() {
+                        result.user = Some(value.clone());
+                    },
+                    "identitiesonly" => if ! identities_only_set {
+                        identities_only_set = true;
+                        result.identities_only = value.eq_ignore_ascii_case("yes");
+                    },
+                    "identityfile" => {
+                        let file = expand_tokens(value, alias, &current_host, &current_user);
+                        let expanded = match expanduser(file.clone()) {
+                            Ok(x) => x.display().to_string(),
+                            Err(_) => file
+                        };
+                        if ! result.identity_files.contains(&expanded) {
+                            result.identity_files.push(expanded);
+                        }
+                    },
+                    _ => {}
+                }
+            }
+        }
+        return result;
+    }
+}
+
+// ==============================================================================================================
+// PRIVATE INTERNALS
+// ==============================================================================================================
+
+impl SshConfigBlock {
+
+    fn matches(&self, alias: &String, current_host: &String, current_user: &String) -> bool {
+        return match &self.criteria {
+            Criteria::Always => true,
+            Criteria::Host(patterns) => pattern_list_matches(patterns, alias),
+            Criteria::Match(criteria) => {
+                for criterion in criteria.iter() {
+                    let result = match criterion.keyword.as_str() {
+                        // we only read the configuration once, so there is no separate final or canonical pass
+                        "all" | "canonical" | "final" => true,
+                        "host"         => pattern_list_matches(&criterion.patterns, current_host),
+                        "originalhost" => pattern_list_matches(&criterion.patterns, alias),
+                        "user"         => pattern_list_matches(&criterion.patterns, current_user),
+                        "localuser"    => pattern_list_matches(&criterion.patterns, &local_user()),
+                        // exec and anything we do not understand never matches, we don't run commands from here
+                        _ => false
+                    };
+                    if result == criterion.negated {
+                        return false;
+                    }
+                }
+                true
+            }
+        };
+    }
+}
+
+// splits "Keyword value", "Keyword=value" and "Keyword = value" lines, handling quoted arguments
+
+fn split_line(line: &str) -> (String, Vec<String>) {
+    let keyword_end = line.find(|c: char| c.is_whitespace() || c == '=').unwrap_or(line.len());
+    let keyword = line[0..keyword_end].to_lowercase();
+    let mut rest = line[keyword_end..].trim_start();
+    if rest.starts_with("=") {
+        rest = rest[1..].trim_start();
+    }
+
+    let mut args : Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in rest.chars() {
+        match c {
+            '"' => { in_quotes = !in_quotes; },
+            x if x.is_whitespace() && !in_quotes => {
+                if !current.is_empty() { args.push(current.clone()); current.clear(); }
+            },
+            x => current.push(x)
+        }
+    }
+    if !current.is_empty() { args.push(current); }
+    return (keyword, args);
+}
+
+fn parse_match_criteria(args: &Vec<String>, line_number: usize) -> Result<Vec<MatchCriterion>, String> {
+    let mut results : Vec<MatchCriterion> = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let mut keyword = args[i].to_lowercase();
+        let negated = keyword.starts_with("!");
+        if negated {
+            keyword = keyword[1..].to_string();
+        }
+        i = i + 1;
+        let patterns = match keyword.as_str() {
+            "all" | "canonical" | "final" => Vec::new(),
+            _ => {
+                if i >= args.len() {
+                    return Err(format!("line {}: Match {} requires an argument", line_number, keyword));
+                }
+                i = i + 1;
+                args[i-1].split(",").map(|x| String::from(x)).collect()
+            }
+        };
+        results.push(MatchCriterion { negated, keyword, patterns });
+    }
+    return Ok(results);
+}
+
+// a list matches if any pattern matches and no negated pattern does
+
+fn pattern_list_matches(patterns: &Vec<String>, value: &String) -> bool {
+    let mut found = false;
+    for pattern in patterns.iter() {
+        if pattern.starts_with("!") {
+            if wildcard_match(&pattern[1..], value) {
+                return false;
+            }
+        } else if wildcard_match(pattern, value) {
+            found = true;
+        }
+    }
+    return found;
+}
+
+fn local_user() -> String {
+    return match env::var("USER") {
+        Ok(x) => x,
+        Err(_) => String::from("root")
+    };
+}
+
+fn expand_tokens(value: &String, alias: &String, host: &String, user: &String) -> String {
+    let mut result = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('h') => result.push_str(host),
+            Some('n') => result.push_str(alias),
+            Some('r') => result.push_str(user),
+            Some('u') => result.push_str(&local_user()),
+            Some('%') => result.push('%'),
+            Some(x)   => { result.push('%'); result.push(x); },
+            None      => result.push('%')
+        }
+    }
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_value_wins_and_identity_files_accumulate() {
+        let config = SshConfig::parse(&String::from("
+Host web*
+    HostName %h.example.com
+    Port 2222
+    IdentityFile /keys/web
+
+Host *
+    User deploy
+    Port 22
+    IdentityFile /keys/default
+    IdentitiesOnly yes
+")).unwrap();
+        let result = config.lookup(&String::from("web1"), &String::from("me"));
+        assert_eq!(result.hostname, Some(String::from("web1.example.com")));
+        assert_eq!(result.port, Some(2222));
+        assert_eq!(result.user, Some(String::from("deploy")));
+        assert_eq!(result.identity_files, vec![String::from("/keys/web"), String::from("/keys/default")]);
+        assert!(result.identities_only);
+        let other = config.lookup(&String::from("db1"), &String::from("me"));
+        assert_eq!(other.hostname, None);
+        assert_eq!(other.port, Some(22));
+    }
+
+    #[test]
+    fn test_negation_and_match_blocks() {
+        let config = SshConfig::parse(&String::from("
+Host *.internal !bastion.internal
+    User=internal
+Match host 10.0.* user admin
+    Port 2200
+Host db1
+    HostName 10.0.0.5
+")).unwrap();
+        assert_eq!(config.lookup(&String::from("bastion.internal"), &String::from("me")).user, None);
+        assert_eq!(config.lookup(&String::from("app.internal"), &String::from("me")).user, Some(String::from("internal")));
+        // the match block comes before HostName is set, so it does not apply
+        assert_eq!(config.lookup(&String::from("db1"), &String::from("admin")).port, None);
+        assert!(SshConfig::parse(&String::from("Match host")).is_err());
+    }
+}
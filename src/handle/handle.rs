@@ -19,6 +19,7 @@ use crate::connection::connection::Connection;
 use crate::tasks::request::TaskRequest;
 use crate::inventory::hosts::Host;
 use crate::playbooks::traversal::RunState;
+use crate::playbooks::visitor::PlaybookVisitor;
 
 use crate::handle::local::Local;
 use crate::handle::remote::Remote;
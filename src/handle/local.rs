@@ -95,6 +95,13 @@ impl Local {
         };
     }
 
+    pub fn read_bytes(&self, request: &Arc<TaskRequest>, path: &Path) -> Result<Vec<u8>, Arc<TaskResponse>> {
+        return match std::fs::read(path) {
+            Ok(x) => Ok(x),
+            Err(y) => Err(self.response.is_failed(request, &format!("unable to read file: {}, {:?}", path.display(), y)))
+        };
+    }
+
     fn internal_sha512(&self, request: &Arc<TaskRequest>, path: &String) -> Result<String,Arc<TaskResponse>> {
         let localhost = self.get_localhost();
         let os_type = localhost.read().unwrap().os_type.expect("unable to detect host OS type");
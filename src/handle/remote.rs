@@ -22,6 +22,7 @@ use crate::tasks::request::{TaskRequest, TaskRequestType};
 use crate::tasks::response::TaskResponse;
 use crate::inventory::hosts::{Host,HostOSType};
 use crate::playbooks::traversal::RunState;
+use crate::playbooks::visitor::PlaybookVisitor;
 use crate::tasks::fields::Field;
 use crate::tasks::FileAttributesEvaluated;
 use crate::connection::command::Forward;
@@ -30,14 +31,23 @@ use crate::handle::handle::CheckRc;
 use crate::handle::template::Safety;
 use crate::handle::response::Response;
 use crate::handle::template::Template;
-use crate::tasks::files::Recurse;
+use crate::tasks::files::{Recurse,ArchiveFormat};
+use crate::tasks::diff::{unified_diff,attribute_diff};
 use std::path::PathBuf;
+use std::env;
+use std::fs;
+use std::fs::DirBuilder;
+use std::os::unix::fs::DirBuilderExt;
+use guid_create::GUID;
 
 // contains all code that eventually reaches out and touches systems to be configured.
 // this includes the local system (somewhat confusingly) in 'local' mode, and of course
 // SSH-based remotes. 'Remote' should be thought of as 'for the system being configured'
 // as opposed to from the perspective of the control machine.
 
+// files read back with read_file are held in memory, so anything larger is refused
+const READ_FILE_LIMIT: u64 = 16 * 1024 * 1024;
+
 pub struct Remote {
     run_state: Arc<RunState>, 
     connection: Arc<Mutex<dyn Connection>>,
@@ -87,16 +97,20 @@ impl Remote {
     // various files need to store things in tmp locations, mainly because SFTP does not support sudo or give the root
     // user the ability to replace unowned files
 
-    pub fn make_temp_path(&self, who: &String, request: &Arc<TaskRequest>) -> Result<(PathBuf, PathBuf), Arc<TaskResponse>> {
-        let mut pb = PathBuf::new();
-        let tmpdir = match who.eq("root") {
+    fn get_jet_dir(&self, who: &String) -> String {
+        return match who.eq("root") {
             false => match self.host.read().unwrap().os_type {
-                Some(HostOSType::MacOS) => format!("/Users/{}/.jet/tmp", who),
-                _ => format!("/home/{}/.jet/tmp", who),
+                Some(HostOSType::MacOS) => format!("/Users/{}/.jet", who),
+                _ => format!("/home/{}/.jet", who),
             }
-            true => String::from("/root/.jet/tmp")
+            true => String::from("/root/.jet")
         };
-        pb.push(tmpdir);
+    }
+
+    pub fn make_temp_path(&self, who: &String, request: &Arc<TaskRequest>) -> Result<(PathBuf, PathBuf), Arc<TaskResponse>> {
+        let mut pb = PathBuf::new();
+        pb.push(self.get_jet_dir(who));
+        pb.push("tmp");
         let mut pb2 = pb.clone();
         let guid = self.run_state.context.read().unwrap().get_guid();
         pb2.push(guid.as_str());
@@ -191,6 +205,23 @@ impl Remote {
         return Ok((Some(p1.clone()), Some(f1.clone())))
     }
 
+    // modules that need to remember something between runs (like the checksum of an extracted archive) keep it
+    // under ~/.jet/state rather than next to the files they manage
+
+    pub fn make_state_path(&self, request: &Arc<TaskRequest>, name: &String) -> Result<String, Arc<TaskResponse>> {
+        let whoami = match self.get_whoami() {
+            Ok(x) => x,
+            Err(y) => { return Err(self.response.is_failed(request, &format!("cannot determine current user: {}", y))) }
+        };
+        let mut pb = PathBuf::new();
+        pb.push(self.get_jet_dir(&whoami));
+        pb.push("state");
+        let create_state_dir = format!("mkdir -p '{}'", pb.display());
+        self.run_no_sudo(request, &create_state_dir, CheckRc::Checked)?;
+        pb.push(name.as_str());
+        return Ok(pb.display().to_string());
+    }
+
     // supporting code for file transfer using temp files
 
     fn get_effective_filename(&self, temp_dir: Option<PathBuf>, temp_path: Option<PathBuf>, path: &String) -> String {
@@ -245,6 +276,35 @@ impl Remote {
         return Ok(xfer_result);
     }
 
+    // fetches a remote file to a path on the control machine.  The file is first copied (with sudo, if in use)
+    // to the transfer location and handed to the connecting user, as the connection itself cannot sudo.
+
+    pub fn fetch_file(&self, request: &Arc<TaskRequest>, remote_path: &String, dest: &Path) -> Result<(), Arc<TaskResponse>> {
+        let (_temp_dir, temp_path) = self.get_transfer_location(request)?;
+        let temp = temp_path.as_ref().unwrap().display().to_string();
+        // the copy may hold a file only the sudo user could read, so it is removed whichever step fails.
+        // An error from the fetch is more useful than one from the cleanup, so it is returned first.
+        let fetch_result = self.fetch_file_via(request, remote_path, &temp, dest);
+        let delete_result = self.delete_file(request, &temp);
+        fetch_result?;
+        delete_result?;
+        return Ok(());
+    }
+
+    fn fetch_file_via(&self, request: &Arc<TaskRequest>, remote_path: &String, temp: &String, dest: &Path) -> Result<(), Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::get_copy_command(self.get_os_type(), remote_path, temp);
+        let cmd = self.unwrap_string_result(&request, &get_cmd_result)?;
+        self.run(request, &cmd, CheckRc::Checked)?;
+        if request.is_sudoing() {
+            let whoami = match self.get_whoami() {
+                Ok(x) => x,
+                Err(y) => { return Err(self.response.is_failed(request, &format!("cannot determine current user: {}", y))) }
+            };
+            self.set_owner(request, temp, &whoami, Recurse::No)?;
+        }
+        return self.connection.lock().unwrap().fetch_file(&self.response, &request, temp, dest);
+    }
+
     // gets the octal string mode of a remote file
 
     pub fn get_mode(&self, request: &Arc<TaskRequest>, path: &String) -> Result<Option<String>,Arc<TaskResponse>> {
@@ -304,6 +364,99 @@ impl Remote {
         return self.run(request, &cmd, CheckRc::Checked);  
     }
 
+    // copies a file to a timestamped name alongside it before it is edited, returning the backup path
+
+    pub fn backup_file(&self, request: &Arc<TaskRequest>, path: &String) -> Result<String,Arc<TaskResponse>> {
+        let backup_path = format!("{}.{}~", path, chrono::Local::now().format("%Y%m%d%H%M%S"));
+        let get_cmd_result = crate::tasks::cmd_library::get_backup_command(self.get_os_type(), path, &backup_path);
+        let cmd = self.unwrap_string_result(&request, &get_cmd_result)?;
+        self.run(request, &cmd, CheckRc::Checked)?;
+        return Ok(backup_path);
+    }
+
+    // returns the target of a symbolic link, or None if the path is not a symbolic link
+
+    pub fn get_link_target(&self, request: &Arc<TaskRequest>, path: &String) -> Result<Option<String>,Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::get_link_target_command(self.get_os_type(), path);
+        let cmd = self.unwrap_string_result(&request, &get_cmd_result)?;
+        let result = self.run(request, &cmd, CheckRc::Unchecked)?;
+        let (rc, out) = cmd_info(&result);
+        return match rc {
+            0 => Ok(Some(out.trim().to_string())),
+            _ => Ok(None),
+        }
+    }
+
+    // are two remote paths the same file, as in hard links?  False if either does not exist.
+
+    pub fn get_is_same_file(&self, request: &Arc<TaskRequest>, path1: &String, path2: &String) -> Result<bool,Arc<TaskResponse>> {
+        let mut inodes : Vec<String> = Vec::new();
+        for path in [path1, path2] {
+            let get_cmd_result = crate::tasks::cmd_library::get_inode_command(self.get_os_type(), path);
+            let cmd = self.unwrap_string_result(&request, &get_cmd_result)?;
+            let result = self.run(request, &cmd, CheckRc::Unchecked)?;
+            let (rc, out) = cmd_info(&result);
+            if rc != 0 {
+                return Ok(false);
+            }
+            inodes.push(out.trim().to_string());
+        }
+        return Ok(inodes[0].eq(&inodes[1]));
+    }
+
+    pub fn create_link(&self, request: &Arc<TaskRequest>, target: &String, path: &String, symbolic: bool, force: bool) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::get_create_link_command(self.get_os_type(), target, path, symbolic, force);
+        let cmd = self.unwrap_string_result(&request, &get_cmd_result)?;
+        return self.run(request, &cmd, CheckRc::Checked);
+    }
+
+    // returns the (directories, other files) below a remote path, relative to that path and sorted
+
+    pub fn list_tree(&self, request: &Arc<TaskRequest>, path: &String) -> Result<(Vec<String>,Vec<String>),Arc<TaskResponse>> {
+        let prefix = format!("{}/", path.trim_end_matches('/'));
+        let mut results : Vec<Vec<String>> = Vec::new();
+        for directories in [true, false] {
+            let get_cmd_result = crate::tasks::cmd_library::get_find_command(self.get_os_type(), path, directories);
+            let cmd = self.unwrap_string_result(&request, &get_cmd_result)?;
+            let result = self.run(request, &cmd, CheckRc::Checked)?;
+            let (_rc, out) = cmd_info(&result);
+            let mut paths : Vec<String> = out.lines().filter_map(|x| x.strip_prefix(&prefix)).map(|x| x.to_string()).collect();
+            paths.sort();
+            results.push(paths);
+        }
+        let files = results.pop().unwrap();
+        let directories = results.pop().unwrap();
+        return Ok((directories, files));
+    }
+
+    pub fn move_file(&self, request: &Arc<TaskRequest>, path: &String, dest: &String) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::get_move_command(self.get_os_type(), path, dest);
+        let cmd = self.unwrap_string_result(&request, &get_cmd_result)?;
+        return self.run(request, &cmd, CheckRc::Checked);
+    }
+
+    // has anything at or below path been modified since the reference file was?
+
+    pub fn get_is_newer(&self, request: &Arc<TaskRequest>, path: &String, reference: &String) -> Result<bool,Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::get_find_newer_command(self.get_os_type(), path, reference);
+        let cmd = self.unwrap_string_result(&request, &get_cmd_result)?;
+        let result = self.run(request, &cmd, CheckRc::Checked)?;
+        let (_rc, out) = cmd_info(&result);
+        return Ok(! out.trim().is_empty());
+    }
+
+    pub fn extract_archive(&self, request: &Arc<TaskRequest>, format: ArchiveFormat, archive: &String, dest: &String, strip_components: u64) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::get_extract_command(self.get_os_type(), format, archive, dest, strip_components);
+        let cmd = self.unwrap_string_result(&request, &get_cmd_result)?;
+        return self.run(request, &cmd, CheckRc::Checked);
+    }
+
+    pub fn create_archive(&self, request: &Arc<TaskRequest>, format: ArchiveFormat, dest: &String, paths: &Vec<String>) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::get_create_archive_command(self.get_os_type(), format, dest, paths);
+        let cmd = self.unwrap_string_result(&request, &get_cmd_result)?;
+        return self.run(request, &cmd, CheckRc::Checked);
+    }
+
     pub fn delete_directory(&self, request: &Arc<TaskRequest>, path: &String, recurse: Recurse) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
         let get_cmd_result = crate::tasks::cmd_library::get_delete_directory_command(self.get_os_type(), path, recurse);
         let cmd = self.unwrap_string_result(&request, &get_cmd_result)?;
@@ -357,6 +510,18 @@ impl Remote {
         return self.run(request,&cmd,CheckRc::Checked);
     }
 
+    pub fn set_link_owner(&self, request: &Arc<TaskRequest>, remote_path: &String, owner: &String) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::set_link_owner_command(self.get_os_type(), remote_path, owner);
+        let cmd = self.unwrap_string_result(&request, &get_cmd_result)?;
+        return self.run(request,&cmd,CheckRc::Checked);
+    }
+
+    pub fn set_link_group(&self, request: &Arc<TaskRequest>, remote_path: &String, group: &String) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::set_link_group_command(self.get_os_type(), remote_path, group);
+        let cmd = self.unwrap_string_result(&request, &get_cmd_result)?;
+        return self.run(request,&cmd,CheckRc::Checked);
+    }
+
     pub fn set_mode(&self, request: &Arc<TaskRequest>, remote_path: &String, mode: &String, recurse: Recurse) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
         let get_cmd_result = crate::tasks::cmd_library::set_mode_command(self.get_os_type(), remote_path, mode, recurse);
         let cmd = self.unwrap_string_result(&request, &get_cmd_result)?;
@@ -367,6 +532,89 @@ impl Remote {
         return self.internal_sha512(request, path);
     }
 
+    pub fn get_size(&self, request: &Arc<TaskRequest>, path: &String) -> Result<u64,Arc<TaskResponse>> {
+        let get_cmd_result = crate::tasks::cmd_library::get_size_command(self.get_os_type(), path);
+        let cmd = self.unwrap_string_result(&request, &get_cmd_result)?;
+        let result = self.run(request, &cmd, CheckRc::Checked)?;
+        let (_rc, out) = cmd_info(&result);
+        return match out.trim().parse::<u64>() {
+            Ok(x) => Ok(x),
+            Err(_) => Err(self.response.is_failed(request, &format!("unable to get the size of {}: {}", path, out)))
+        };
+    }
+
+    // reads back the contents of a remote file, used by --diff and by modules that edit files in place.
+    // The file is fetched rather than printed by a command, since command output goes to the log and
+    // reports, where masking could not catch secrets in it.
+
+    pub fn read_file(&self, request: &Arc<TaskRequest>, path: &String) -> Result<Vec<u8>,Arc<TaskResponse>> {
+        let size = self.get_size(request, path)?;
+        if size > READ_FILE_LIMIT {
+            return Err(self.response.is_failed(request, &format!("{} is too large to read ({} bytes, the limit is {})", path, size, READ_FILE_LIMIT)));
+        }
+        let local_dir = env::temp_dir().join(format!("jetp-read-{}", GUID::rand().to_string()));
+        if let Err(y) = DirBuilder::new().mode(0o700).create(&local_dir) {
+            return Err(self.response.is_failed(request, &format!("unable to create {}: {}", local_dir.display(), y)));
+        }
+        let local_path = local_dir.join("contents");
+        let result = match self.fetch_file(request, path, &local_path) {
+            Ok(_) => match fs::read(&local_path) {
+                Ok(x) => Ok(x),
+                Err(y) => Err(self.response.is_failed(request, &format!("unable to read contents of {}: {}", path, y)))
+            },
+            Err(x) => Err(x)
+        };
+        let _ = fs::remove_dir_all(&local_dir);
+        return result;
+    }
+
+    // with --diff, shows what a check mode run would change about a file: a unified diff against the
+    // new content if the content differs, and the before and after of any attributes that differ.
+    // remote_mode and changes are the results of query_common_file_attributes plus any content check.
+
+    pub fn show_file_diff<F>(&self, request: &Arc<TaskRequest>, remote_path: &String, remote_mode: &Option<String>,
+        attributes: &Option<FileAttributesEvaluated>, changes: &Vec<Field>, new_content: F) -> Result<(), Arc<TaskResponse>>
+        where F: FnOnce() -> Result<Vec<u8>, Arc<TaskResponse>> {
+
+        if ! self.response.get_visitor().read().expect("read visitor").is_diff_mode() {
+            return Ok(());
+        }
+
+        let mut text = String::new();
+        if changes.contains(&Field::Content) {
+            let after = new_content()?;
+            let before = match remote_mode {
+                Some(_) => Some(self.read_file(request, remote_path)?),
+                None => None
+            };
+            text.push_str(&unified_diff(remote_path, before.as_deref(), &after));
+        }
+
+        if let Some(attributes) = attributes {
+            let (owner, group) = match (remote_mode, changes.contains(&Field::Owner) || changes.contains(&Field::Group)) {
+                (Some(_), true) => match self.get_ownership(request, remote_path)? {
+                    Some((o,g)) => (Some(o), Some(g)),
+                    None => (None, None)
+                },
+                (_, _) => (None, None)
+            };
+            if let Some(x) = &attributes.owner {
+                if remote_mode.is_none() || changes.contains(&Field::Owner) { text.push_str(&attribute_diff("owner", &owner, x)); }
+            }
+            if let Some(x) = &attributes.group {
+                if remote_mode.is_none() || changes.contains(&Field::Group) { text.push_str(&attribute_diff("group", &group, x)); }
+            }
+            if let Some(x) = &attributes.mode {
+                if remote_mode.is_none() || changes.contains(&Field::Mode) { text.push_str(&attribute_diff("mode", remote_mode, x)); }
+            }
+        }
+
+        if ! text.is_empty() {
+            self.response.get_visitor().read().expect("read visitor").on_host_diff(&self.response.get_context(), &Arc::clone(&self.host), remote_path, &text);
+        }
+        return Ok(());
+    }
+
     // right now we assume there's a good way to run SHA-512 preinstalled on all platforms.
 
     fn internal_sha512(&self, request: &Arc<TaskRequest>, path: &String) -> Result<String,Arc<TaskResponse>> {
@@ -22,7 +22,7 @@ use crate::playbooks::traversal::RunState;
 use crate::tasks::fields::Field;
 use crate::connection::command::CommandResult;
 use crate::playbooks::context::PlaybookContext;
-use crate::playbooks::visitor::PlaybookVisitor;
+use crate::playbooks::visitor::{PlaybookVisitor,MultiVisitor};
 use std::sync::RwLock;
 
 // response mostly contains shortcuts for returning objects that are appropriate for module returns
@@ -48,7 +48,7 @@ impl Response {
         return Arc::clone(&self.run_state.context);
     }
 
-    pub fn get_visitor(&self) -> Arc<RwLock<PlaybookVisitor>> {
+    pub fn get_visitor(&self) -> Arc<RwLock<MultiVisitor>> {
         return Arc::clone(&self.run_state.visitor);
     }
 
@@ -59,7 +59,8 @@ impl Response {
             msg: Some(msg.clone()), 
             command_result: Arc::new(None), 
             with: Arc::new(None), 
-            and: Arc::new(None)
+            and: Arc::new(None),
+            data: None
         });
     }
 
@@ -78,7 +79,8 @@ impl Response {
             msg: Some(String::from("command failed")), 
             command_result: Arc::clone(&result), 
             with: Arc::new(None), 
-            and: Arc::new(None)
+            and: Arc::new(None),
+            data: None
         });
     }
 
@@ -87,7 +89,7 @@ impl Response {
         self.get_visitor().read().expect("read visitor").on_command_ok(&self.get_context(), &Arc::clone(&self.host), &Arc::clone(result));
         return Arc::new(TaskResponse {
             status: TaskStatus::IsExecuted,
-            changes: Vec::new(), msg: None, command_result: Arc::clone(&result), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::clone(&result), with: Arc::new(None), and: Arc::new(None), data: None
         });
     }
 
@@ -96,7 +98,7 @@ impl Response {
         assert!(request.request_type == TaskRequestType::Validate, "is_skipped response can only be returned for a validation request");
         return Arc::new(TaskResponse { 
             status: TaskStatus::IsSkipped, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), data: None
         });
     }
 
@@ -107,7 +109,7 @@ impl Response {
             "is_matched response can only be returned for a query request, was {:?}", request.request_type);
         return Arc::new(TaskResponse { 
             status: TaskStatus::IsMatched, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), data: None
         });
     }
 
@@ -116,7 +118,7 @@ impl Response {
         assert!(request.request_type == TaskRequestType::Create, "is_executed response can only be returned for a creation request");
         return Arc::new(TaskResponse { 
             status: TaskStatus::IsCreated, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), data: None
         });
     }
     
@@ -126,7 +128,7 @@ impl Response {
         assert!(request.request_type == TaskRequestType::Execute, "is_executed response can only be returned for a creation request");
         return Arc::new(TaskResponse { 
             status: TaskStatus::IsExecuted, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), data: None
         });
     }
     
@@ -136,7 +138,7 @@ impl Response {
         return Arc::new(TaskResponse { 
             status: TaskStatus::IsRemoved, 
             changes: Vec::new(), 
-            msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), data: None
         });
     }
 
@@ -145,7 +147,7 @@ impl Response {
         assert!(request.request_type == TaskRequestType::Passive || request.request_type == TaskRequestType::Execute, "is_passive response can only be returned for a passive or execute request");
         return Arc::new(TaskResponse { 
             status: TaskStatus::IsPassive, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), data: None
         });
     }
     
@@ -155,7 +157,7 @@ impl Response {
         return Arc::new(TaskResponse { 
             status: TaskStatus::IsModified, 
             changes: changes, 
-            msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), data: None
         });
     }
 
@@ -164,7 +166,7 @@ impl Response {
         assert!(request.request_type == TaskRequestType::Query, "needs_creation response can only be returned for a query request");
         return Arc::new(TaskResponse { 
             status: TaskStatus::NeedsCreation, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), 
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), data: None
         });
     }
     
@@ -175,7 +177,7 @@ impl Response {
         return Arc::new(TaskResponse { 
             status: TaskStatus::NeedsModification, 
             changes: changes.clone(), 
-            msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None) 
+            msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), data: None
         });
     }
     
@@ -184,7 +186,7 @@ impl Response {
         assert!(request.request_type == TaskRequestType::Query, "needs_removal response can only be returned for a query request");
         return Arc::new(TaskResponse { 
             status: TaskStatus::NeedsRemoval, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), data: None
         });
     }
 
@@ -194,7 +196,7 @@ impl Response {
         assert!(request.request_type == TaskRequestType::Query, "needs_execution response can only be returned for a query request");
         return Arc::new(TaskResponse { 
             status: TaskStatus::NeedsExecution, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None),and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None),and: Arc::new(None), data: None
         });
     }
     
@@ -203,8 +205,35 @@ impl Response {
         assert!(request.request_type == TaskRequestType::Query, "needs_passive response can only be returned for a query request");
         return Arc::new(TaskResponse { 
             status: TaskStatus::NeedsPassive, 
-            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None)
+            changes: Vec::new(), msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), data: None
         });
     }
 
-}
\ No newline at end of file
+    pub fn with_data(&self, response: &Arc<TaskResponse>, data: serde_yaml::Mapping) -> Arc<TaskResponse> {
+        // attaches module specific results to any response, these are saved along with the status
+        // when the task uses 'save', see TaskResponse::to_saved_mapping
+        return Arc::new(TaskResponse {
+            status: response.status.clone(),
+            changes: response.changes.clone(),
+            msg: response.msg.clone(),
+            command_result: Arc::clone(&response.command_result),
+            with: Arc::clone(&response.with),
+            and: Arc::clone(&response.and),
+            data: Some(data)
+        });
+    }
+
+    pub fn with_status(&self, response: &Arc<TaskResponse>, status: TaskStatus, msg: Option<String>) -> Arc<TaskResponse> {
+        // used by and/failed_when and and/changed_when to change the outcome of a task after it has run
+        return Arc::new(TaskResponse {
+            status: status,
+            changes: response.changes.clone(),
+            msg: msg,
+            command_result: Arc::clone(&response.command_result),
+            with: Arc::clone(&response.with),
+            and: Arc::clone(&response.and),
+            data: response.data.clone()
+        });
+    }
+
+}
@@ -354,7 +354,7 @@ impl Template {
     pub fn find_template_path(&self, request: &Arc<TaskRequest>, tm: TemplateMode, field: &String, str_path: &String) -> Result<PathBuf, Arc<TaskResponse>> {
         // templates a string and then looks for the resulting file in the logical templates/ locations (if not an absolute path)
         // raises errors if the source files are not found
-        return self.find_sub_path(&String::from("templates"), request, tm, field, str_path);
+        return self.find_sub_path(&String::from("templates"), request, tm, field, str_path, false);
     }
 
     pub fn find_module_path(&self, request: &Arc<TaskRequest>, _tm: TemplateMode, _field: &String, str_path: &String) -> Result<PathBuf, Arc<TaskResponse>> {
@@ -376,14 +376,21 @@ impl Template {
 
     pub fn find_file_path(&self, request: &Arc<TaskRequest>, tm: TemplateMode, field: &String, str_path: &String) -> Result<PathBuf, Arc<TaskResponse>> {
         // simialr to find_template_path, this one assumes a 'files/' directory for relative paths.
-        return self.find_sub_path(&String::from("files"), request, tm, field, str_path);
+        return self.find_sub_path(&String::from("files"), request, tm, field, str_path, false);
     }
 
-    fn find_sub_path(&self, prefix: &String, request: &Arc<TaskRequest>, tm: TemplateMode, field: &String, str_path: &String) -> Result<PathBuf, Arc<TaskResponse>> {
-        // supporting code for find_template_path and find_file_path
+    pub fn find_directory_path(&self, request: &Arc<TaskRequest>, tm: TemplateMode, field: &String, str_path: &String) -> Result<PathBuf, Arc<TaskResponse>> {
+        // the same as find_file_path, but for a whole tree under 'files/', as used by the sync module
+        return self.find_sub_path(&String::from("files"), request, tm, field, str_path, true);
+    }
+
+    fn find_sub_path(&self, prefix: &String, request: &Arc<TaskRequest>, tm: TemplateMode, field: &String, str_path: &String, directory: bool) -> Result<PathBuf, Arc<TaskResponse>> {
+        // supporting code for find_template_path, find_file_path, and find_directory_path
         if tm == TemplateMode::Off {
             return Ok(PathBuf::new());
         }
+        let found = |x: &PathBuf| -> bool { if directory { x.is_dir() } else { x.is_file() } };
+        let kind = match directory { true => "directory", false => "file" };
         let prelim = match screen_path(str_path) {
             Ok(x) => x, 
             Err(y) => { return Err(self.response.is_failed(request, &format!("{}, for field: {}", y, field))) }
@@ -391,19 +398,19 @@ impl Template {
         let mut path = PathBuf::new();
         path.push(prelim);
         if path.is_absolute() {
-            if path.is_file() {
+            if found(&path) {
                 return Ok(path);
             } else {
-                return Err(self.response.is_failed(request, &format!("field ({}): no such file: {}", field, str_path)));
+                return Err(self.response.is_failed(request, &format!("field ({}): no such {}: {}", field, kind, str_path)));
             }
         } else {
             let mut path2 = PathBuf::new();
             path2.push(prefix);
             path2.push(str_path);
-            if path2.is_file() {
+            if found(&path2) {
                 return Ok(path2);
             } else {
-                return Err(self.response.is_failed(request, &format!("field ({}): no such file: {}", field, str_path)));
+                return Err(self.response.is_failed(request, &format!("field ({}): no such {}: {}", field, kind, str_path)));
             }
         }
     }
@@ -0,0 +1,121 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::path::{Path,PathBuf};
+use std::env;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::{Duration,SystemTime};
+use expanduser::expanduser;
+use crate::tasks::checksum::sha512;
+
+// an on-disk cache of dynamic inventory script output.  Scripts that talk to cloud APIs can be slow
+// or flaky, so the last good output is kept around and can be reused for a while (the TTL) or as a
+// fallback whenever the script fails.  Cache files live in $JET_INVENTORY_CACHE or ~/.cache/jetp/inventory.
+
+#[derive(Debug,Copy,Clone)]
+pub struct InventoryCacheOptions {
+    // seconds cached output may be used for without running the script at all, 0 to always run it
+    pub ttl: u64,
+    // run the script even if the cache is fresh
+    pub refresh: bool,
+}
+
+// ==============================================================================================================
+// PUBLIC API
+// ==============================================================================================================
+
+// one cache file per script
+
+pub fn get_cache_path(script: &Path) -> PathBuf {
+    // the same script given as a relative or absolute path should share a cache
+    let script_path = match fs::canonicalize(script) {
+        Ok(x) => x,
+        Err(_) => script.to_path_buf()
+    };
+    let key = script_path.display().to_string();
+    let dir = match env::var("JET_INVENTORY_CACHE") {
+        Ok(x) => PathBuf::from(x),
+        Err(_) => match expanduser("~/.cache/jetp/inventory") {
+            Ok(x) => x,
+            Err(_) => PathBuf::from(".jetp_inventory_cache")
+        }
+    };
+    return dir.join(format!("{}.json", &sha512(&key)[0..32]));
+}
+
+// returns the cached output if there is some no older than max_age, any age if max_age is None
+
+pub fn read_cache(path: &Path, max_age: Option<u64>) -> Option<(String, Duration)> {
+    let age = match get_cache_age(path) {
+        Some(x) => x,
+        None => { return None; }
+    };
+    if max_age.is_some() && age > Duration::from_secs(max_age.unwrap()) {
+        return None;
+    }
+    return match fs::read_to_string(path) {
+        Ok(x) => Some((x, age)),
+        Err(_) => None
+    };
+}
+
+pub fn write_cache(path: &Path, output: &String) -> Result<(), String> {
+    match path.parent() {
+        Some(parent) => match fs::create_dir_all(parent) {
+            Ok(_) => {},
+            Err(y) => { return Err(format!("unable to create inventory cache directory {}: {}", parent.display(), y)); }
+        },
+        None => {}
+    };
+    // write then rename, so an interrupted run never leaves a truncated cache behind.  Scripts often
+    // return hostvars, which may include secrets, so the cache is only readable by the user.
+    let tmp_path = path.with_extension("tmp");
+    let _ = fs::remove_file(&tmp_path);
+    let written = match OpenOptions::new().write(true).create_new(true).mode(0o600).open(&tmp_path) {
+        Ok(mut file) => file.write_all(output.as_bytes()),
+        Err(y) => Err(y)
+    };
+    match written {
+        Ok(_) => {},
+        Err(y) => { return Err(format!("unable to write inventory cache {}: {}", tmp_path.display(), y)); }
+    };
+    return match fs::rename(&tmp_path, path) {
+        Ok(_) => Ok(()),
+        Err(y) => Err(format!("unable to write inventory cache {}: {}", path.display(), y))
+    };
+}
+
+// ==============================================================================================================
+// PRIVATE INTERNALS
+// ==============================================================================================================
+
+fn get_cache_age(path: &Path) -> Option<Duration> {
+    let modified = match fs::metadata(path) {
+        Ok(metadata) => match metadata.modified() {
+            Ok(x) => x,
+            Err(_) => { return None; }
+        },
+        Err(_) => { return None; }
+    };
+    return match SystemTime::now().duration_since(modified) {
+        Ok(x) => Some(x),
+        // the clock went backwards, so the file is as fresh as it gets
+        Err(_) => Some(Duration::from_secs(0))
+    };
+}
@@ -0,0 +1,367 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::path::{Path,PathBuf};
+use std::sync::Arc;
+use std::sync::RwLock;
+use crate::inventory::inventory::Inventory;
+use crate::inventory::source::InventorySource;
+use crate::inventory::loading::{load_inventory_json,load_sibling_vars};
+use crate::util::io::read_local_file;
+use crate::util::vault::load_vars_file;
+
+// single file inventory sources, selected by file extension.  All of them allow group_vars/ and host_vars/
+// directories next to the file, the same as dynamic inventory scripts.
+
+pub struct YamlInventorySource {
+    pub path: PathBuf
+}
+
+pub struct JsonInventorySource {
+    pub path: PathBuf
+}
+
+pub struct IniInventorySource {
+    pub path: PathBuf
+}
+
+// ==============================================================================================================
+// YAML
+// ==============================================================================================================
+// nested groups in the same layout Ansible uses:
+//
+// all:
+//   children:
+//     webservers:
+//       hosts:
+//         www1:
+//           jet_ssh_port: 2222
+//         www2:
+//       vars:
+//         http_port: 80
+
+impl InventorySource for YamlInventorySource {
+    fn load(&self, inventory: &Arc<RwLock<Inventory>>) -> Result<(), String> {
+        // inventory variables may be !vault encrypted, as in group_vars/
+        let mapping = load_vars_file(&self.path.as_path())?;
+        load_yaml_inventory(inventory, &mapping, &self.path.as_path())?;
+        return load_sibling_vars(inventory, &self.path.as_path());
+    }
+}
+
+pub fn load_yaml_inventory(inventory: &Arc<RwLock<Inventory>>, mapping: &serde_yaml::Mapping, path: &Path) -> Result<(), String> {
+    for (k,v) in mapping.iter() {
+        let group_name = yaml_key(k, path)?;
+        add_yaml_group(inventory, &group_name, v, path)?;
+    }
+    return Ok(());
+}
+
+fn add_yaml_group(inventory: &Arc<RwLock<Inventory>>, group_name: &String, value: &serde_yaml::Value, path: &Path) -> Result<(), String> {
+
+    inventory.write().unwrap().store_group(group_name);
+
+    let mapping = match value {
+        serde_yaml::Value::Null => { return Ok(()); },
+        serde_yaml::Value::Mapping(x) => x,
+        _ => { return Err(format!("{}: group {} must be a mapping", path.display(), group_name)); }
+    };
+
+    for (k,v) in mapping.iter() {
+        let key = yaml_key(k, path)?;
+        match key.as_str() {
+            "hosts" => match v {
+                serde_yaml::Value::Null => {},
+                // hosts can be a list of names, or a mapping of names to their variables
+                serde_yaml::Value::Sequence(hosts) => {
+                    for host in hosts.iter() {
+                        let host_name = yaml_key(host, path)?;
+                        inventory.write().unwrap().store_host(group_name, &host_name);
+                    }
+                },
+                serde_yaml::Value::Mapping(hosts) => {
+                    for (hk, hv) in hosts.iter() {
+                        let host_name = yaml_key(hk, path)?;
+                        let mut inv = inventory.write().unwrap();
+                        inv.store_host(group_name, &host_name);
+                        match hv {
+                            serde_yaml::Value::Null => {},
+                            serde_yaml::Value::Mapping(vars) => {
+                                inv.get_host(&host_name).write().unwrap().update_variables_from(vars.clone(), &path.display().to_string());
+                            },
+                            _ => { return Err(format!("{}: variables for host {} must be a mapping", path.display(), host_name)); }
+                        }
+                    }
+                },
+                _ => { return Err(format!("{}: hosts in group {} must be a list or mapping", path.display(), group_name)); }
+            },
+            "vars" => match v {
+                serde_yaml::Value::Null => {},
+                serde_yaml::Value::Mapping(vars) => {
+                    inventory.read().unwrap().get_group(group_name).write().unwrap().update_variables_from(vars.clone(), &path.display().to_string());
+                },
+                _ => { return Err(format!("{}: vars in group {} must be a mapping", path.display(), group_name)); }
+            },
+            "children" | "subgroups" => match v {
+                serde_yaml::Value::Null => {},
+                serde_yaml::Value::Sequence(children) => {
+                    for child in children.iter() {
+                        let child_name = yaml_key(child, path)?;
+                        add_child_group(inventory, group_name, &child_name)?;
+                    }
+                },
+                serde_yaml::Value::Mapping(children) => {
+                    for (ck, cv) in children.iter() {
+                        let child_name = yaml_key(ck, path)?;
+                        add_child_group(inventory, group_name, &child_name)?;
+                        add_yaml_group(inventory, &child_name, cv, path)?;
+                    }
+                },
+                _ => { return Err(format!("{}: children of group {} must be a list or mapping", path.display(), group_name)); }
+            },
+            _ => { return Err(format!("{}: unknown key in group {}: {}", path.display(), group_name, key)); }
+        }
+    }
+    return Ok(());
+}
+
+fn yaml_key(value: &serde_yaml::Value, path: &Path) -> Result<String, String> {
+    return match value {
+        serde_yaml::Value::String(x) => Ok(x.clone()),
+        serde_yaml::Value::Number(x) => Ok(x.to_string()),
+        _ => Err(format!("{}: expecting a group or host name, got: {:?}", path.display(), value))
+    };
+}
+
+fn add_child_group(inventory: &Arc<RwLock<Inventory>>, group_name: &String, child_name: &String) -> Result<(), String> {
+    if group_name.eq(child_name) {
+        return Err(format!("group {} cannot be a child of itself", group_name));
+    }
+    // everything is already under "all", so saying so again is harmless
+    if group_name.eq("all") {
+        inventory.write().unwrap().store_group(child_name);
+    } else {
+        inventory.write().unwrap().store_subgroup(group_name, child_name);
+    }
+    return Ok(());
+}
+
+// ==============================================================================================================
+// JSON
+// ==============================================================================================================
+// the same schema dynamic inventory scripts print, including _meta/hostvars
+
+impl InventorySource for JsonInventorySource {
+    fn load(&self, inventory: &Arc<RwLock<Inventory>>) -> Result<(), String> {
+        let contents = read_local_file(&self.path.as_path())?;
+        load_inventory_json(inventory, &contents, &self.path.as_path())?;
+        return load_sibling_vars(inventory, &self.path.as_path());
+    }
+}
+
+// ==============================================================================================================
+// INI
+// ==============================================================================================================
+// Ansible style:
+//
+// loose.example.com
+//
+// [webservers]
+// www1 http_port=8080
+//
+// [webservers:vars]
+// ntp_server=ntp.example.com
+//
+// [production:children]
+// webservers
+
+impl InventorySource for IniInventorySource {
+    fn load(&self, inventory: &Arc<RwLock<Inventory>>) -> Result<(), String> {
+        let contents = read_local_file(&self.path.as_path())?;
+        load_ini_inventory(inventory, &contents, &self.path.as_path())?;
+        return load_sibling_vars(inventory, &self.path.as_path());
+    }
+}
+
+#[derive(PartialEq)]
+enum IniSection {
+    Hosts,
+    Vars,
+    Children
+}
+
+pub fn load_ini_inventory(inventory: &Arc<RwLock<Inventory>>, contents: &String, path: &Path) -> Result<(), String> {
+
+    // hosts listed before any section are "ungrouped", as in Ansible
+    let mut group_name = String::from("ungrouped");
+    let mut section = IniSection::Hosts;
+
+    for (line_number, raw_line) in contents.lines().enumerate() {
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with("#") || line.starts_with(";") {
+            continue;
+        }
+        let location = format!("{}, line {}", path.display(), line_number + 1);
+
+        if line.starts_with("[") {
+            if ! line.ends_with("]") {
+                return Err(format!("{}: invalid section header: {}", location, line));
+            }
+            let header = &line[1..line.len()-1];
+            let (name, kind) = match header.rsplit_once(":") {
+                Some((n, "vars"))     => (n, IniSection::Vars),
+                Some((n, "children")) => (n, IniSection::Children),
+                Some((_, other))      => { return Err(format!("{}: unknown section type: {}", location, other)); },
+                None                  => (header, IniSection::Hosts)
+            };
+            if name.is_empty() {
+                return Err(format!("{}: missing group name", location));
+            }
+            group_name = String::from(name);
+            section = kind;
+            inventory.write().unwrap().store_group(&group_name);
+            continue;
+        }
+
+        match section {
+            IniSection::Hosts => {
+                let tokens = split_ini_tokens(line);
+                let host_name = tokens[0].clone();
+                let mut vars = serde_yaml::Mapping::new();
+                for token in tokens[1..].iter() {
+                    let (k, v) = parse_ini_assignment(token, &location)?;
+                    vars.insert(serde_yaml::Value::String(k), v);
+                }
+                let mut inv = inventory.write().unwrap();
+                inv.store_host(&group_name, &host_name);
+                if ! vars.is_empty() {
+                    inv.get_host(&host_name).write().unwrap().update_variables_from(vars, &path.display().to_string());
+                }
+            },
+            IniSection::Vars => {
+                let (k, v) = parse_ini_assignment(line, &location)?;
+                let mut vars = serde_yaml::Mapping::new();
+                vars.insert(serde_yaml::Value::String(k), v);
+                inventory.read().unwrap().get_group(&group_name).write().unwrap().update_variables_from(vars, &path.display().to_string());
+            },
+            IniSection::Children => {
+                add_child_group(inventory, &group_name, &String::from(line))?;
+            }
+        }
+    }
+    return Ok(());
+}
+
+// host lines are whitespace separated, but quoted values may contain spaces
+
+fn split_ini_tokens(line: &str) -> Vec<String> {
+    let mut tokens : Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut quote : Option<char> = None;
+    for c in line.chars() {
+        match quote {
+            Some(q) => { if c == q { quote = None; } current.push(c); },
+            None => {
+                if c == '"' || c == '\'' {
+                    quote = Some(c);
+                    current.push(c);
+                } else if c.is_whitespace() {
+                    if !current.is_empty() { tokens.push(current.clone()); current.clear(); }
+                } else {
+                    current.push(c);
+                }
+            }
+        }
+    }
+    if !current.is_empty() { tokens.push(current); }
+    return tokens;
+}
+
+// values are read as YAML scalars so numbers and booleans keep their types, quoted values stay strings
+
+fn parse_ini_assignment(token: &str, location: &String) -> Result<(String, serde_yaml::Value), String> {
+    let (k, v) = match token.split_once("=") {
+        Some(x) => x,
+        None => { return Err(format!("{}: expecting key=value, got: {}", location, token)); }
+    };
+    let key = k.trim();
+    let value = v.trim();
+    if key.is_empty() {
+        return Err(format!("{}: missing variable name: {}", location, token));
+    }
+    let parsed = match serde_yaml::from_str::<serde_yaml::Value>(value) {
+        Ok(serde_yaml::Value::Mapping(_)) | Ok(serde_yaml::Value::Sequence(_)) | Err(_) => serde_yaml::Value::String(String::from(value)),
+        Ok(serde_yaml::Value::Null) => serde_yaml::Value::String(String::from(value)),
+        Ok(x) => x
+    };
+    return Ok((String::from(key), parsed));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ini_inventory() {
+        let inventory = Arc::new(RwLock::new(Inventory::new()));
+        inventory.write().unwrap().store_group(&String::from("all"));
+        let contents = String::from("
+loose1
+[web]
+www1 port=8080 motd=\"hello world\"
+[web:vars]
+region = east
+[prod:children]
+web
+");
+        load_ini_inventory(&inventory, &contents, Path::new("test.ini")).unwrap();
+        let inv = inventory.read().unwrap();
+        assert!(inv.get_host(&String::from("loose1")).read().unwrap().has_group(&String::from("ungrouped")));
+        let www1 = inv.get_host(&String::from("www1"));
+        let vars = www1.read().unwrap().get_blended_variables();
+        assert_eq!(vars.get("port").unwrap().as_i64(), Some(8080));
+        assert_eq!(vars.get("motd").unwrap().as_str(), Some("hello world"));
+        assert_eq!(vars.get("region").unwrap().as_str(), Some("east"));
+        assert!(www1.read().unwrap().has_ancestor_group(&String::from("prod")));
+        assert!(load_ini_inventory(&inventory, &String::from("[web:bogus]"), Path::new("test.ini")).is_err());
+    }
+
+    #[test]
+    fn test_yaml_inventory() {
+        let inventory = Arc::new(RwLock::new(Inventory::new()));
+        inventory.write().unwrap().store_group(&String::from("all"));
+        let mapping : serde_yaml::Mapping = serde_yaml::from_str("
+all:
+  children:
+    web:
+      hosts:
+        www1:
+          port: 8080
+        www2:
+      vars:
+        region: east
+      children:
+        canary:
+          hosts: [ www3 ]
+").unwrap();
+        load_yaml_inventory(&inventory, &mapping, Path::new("test.yml")).unwrap();
+        let inv = inventory.read().unwrap();
+        assert!(inv.has_host(&String::from("www2")));
+        let www3 = inv.get_host(&String::from("www3"));
+        assert!(www3.read().unwrap().has_ancestor_group(&String::from("web")));
+        assert_eq!(www3.read().unwrap().get_blended_variables().get("region").unwrap().as_str(), Some("east"));
+    }
+}
@@ -15,10 +15,11 @@
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::collections::HashMap;
-use crate::util::yaml::blend_variables;
+use crate::util::yaml::{blend_variables,record_variable_sources};
 use std::sync::Arc;
 use crate::inventory::hosts::Host;
 use std::sync::RwLock;
+use std::sync::atomic::{AtomicUsize,Ordering};
 use serde_yaml;
 
 pub struct Group {
@@ -27,6 +28,21 @@ pub struct Group {
     pub parents : HashMap<String, Arc<RwLock<Self>>>,
     pub hosts : HashMap<String, Arc<RwLock<Host>>>,
     pub variables : serde_yaml::Mapping,
+    // which file each variable was loaded from, for show-vars
+    pub variable_sources : HashMap<String,String>,
+}
+
+// bumped whenever a group or host joins a group, so that hosts know when the order of their groups
+// they have cached (see Host::get_ordered_ancestor_groups) is out of date
+
+static GROUP_TREE_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+pub fn get_group_tree_generation() -> usize {
+    return GROUP_TREE_GENERATION.load(Ordering::SeqCst);
+}
+
+pub fn bump_group_tree_generation() {
+    GROUP_TREE_GENERATION.fetch_add(1, Ordering::SeqCst);
 }
 
 impl Group {
@@ -38,11 +54,13 @@ impl Group {
             parents : HashMap::new(),
             hosts : HashMap::new(),
             variables : serde_yaml::Mapping::new(),
+            variable_sources : HashMap::new(),
         }
     }
 
     pub fn add_subgroup(&mut self, name: &String, subgroup: Arc<RwLock<Group>>) {
         assert!(!name.eq(&self.name));
+        bump_group_tree_generation();
         self.subgroups.insert(
             name.clone(), 
             Arc::clone(&subgroup)
@@ -58,6 +76,7 @@ impl Group {
 
     pub fn add_parent(&mut self, name: &String, parent: Arc<RwLock<Group>>) {
         assert!(!name.eq(&self.name));
+        bump_group_tree_generation();
         self.parents.insert(
             name.clone(), 
             Arc::clone(&parent)
@@ -175,6 +194,7 @@ impl Group {
 
     pub fn set_variables(&mut self, variables: serde_yaml::Mapping) {
         self.variables = variables.clone();
+        self.variable_sources.clear();
     }
 
     pub fn update_variables(&mut self, mapping: serde_yaml::Mapping) {
@@ -183,11 +203,22 @@ impl Group {
         }
     }
 
+    // the same as above, but remembering the file the variables came from
+
+    pub fn set_variables_from(&mut self, variables: serde_yaml::Mapping, source: &String) {
+        self.set_variables(variables);
+        record_variable_sources(&mut self.variable_sources, &self.variables, source);
+    }
+
+    pub fn update_variables_from(&mut self, mapping: serde_yaml::Mapping, source: &String) {
+        record_variable_sources(&mut self.variable_sources, &mapping, source);
+        self.update_variables(mapping);
+    }
+
     pub fn get_blended_variables(&self) -> serde_yaml::Mapping {
         let mut blended : serde_yaml::Value = serde_yaml::Value::from(serde_yaml::Mapping::new());
-        let ancestors = self.get_ancestor_groups(20);
-        for (_k,v) in ancestors.iter() {
-            let theirs : serde_yaml::Value = serde_yaml::Value::from(v.read().expect("group read").get_variables());
+        for group in sort_groups_by_depth(self.get_ancestor_groups(20)).iter() {
+            let theirs : serde_yaml::Value = serde_yaml::Value::from(group.read().expect("group read").get_variables());
             blend_variables(&mut blended, theirs);
         }
         let mine = serde_yaml::Value::from(self.get_variables());
@@ -217,10 +248,15 @@ impl Group {
 
 }
 
-
-
-
-
-
-
-
+// orders groups so that parents always come before their children, which is the order their
+// variables are blended in, so that more specific groups win.  Ties are broken by name so the
+// result does not depend on hash ordering.
+
+pub fn sort_groups_by_depth(groups: HashMap<String, Arc<RwLock<Group>>>) -> Vec<Arc<RwLock<Group>>> {
+    let mut keyed : Vec<(usize, String, Arc<RwLock<Group>>)> = groups.into_iter().map(|(k,v)| {
+        let depth = v.read().expect("group read").get_ancestor_groups(20).len();
+        (depth, k, v)
+    }).collect();
+    keyed.sort_by(|a,b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+    return keyed.into_iter().map(|(_d,_k,v)| v).collect();
+}
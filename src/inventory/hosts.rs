@@ -15,10 +15,10 @@
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::collections::HashMap;
-use crate::util::yaml::blend_variables;
+use crate::util::yaml::{blend_variables,LayerSources,record_variable_sources};
 use std::sync::Arc;
-use crate::inventory::groups::Group;
-use std::sync::RwLock;
+use crate::inventory::groups::{Group,sort_groups_by_depth,get_group_tree_generation,bump_group_tree_generation};
+use std::sync::{Mutex,RwLock};
 use std::collections::HashSet;
 use serde_yaml;
 
@@ -40,12 +40,16 @@ pub struct Host {
     pub name               : String,
     pub groups             : HashMap<String, Arc<RwLock<Group>>>,
     pub variables          : serde_yaml::Mapping,
+    // which file each variable was loaded from, for show-vars
+    pub variable_sources   : HashMap<String,String>,
     pub os_type            : Option<HostOSType>,
     checksum_cache         : HashMap<String,String>,
     checksum_cache_task_id : usize,
     facts                  : serde_yaml::Value,
     pub package_preference : Option<PackagePreference>,
-    notified_handlers      : HashMap<usize, HashSet<String>>
+    notified_handlers      : HashMap<usize, HashSet<String>>,
+    // ancestor groups in blending order, along with the group tree generation they were sorted at
+    group_order            : Mutex<Option<(usize, Vec<Arc<RwLock<Group>>>)>>
 }
 
 impl Host {
@@ -54,13 +58,15 @@ impl Host {
         Self {
             name: name.clone(),
             variables : serde_yaml::Mapping::new(),
+            variable_sources: HashMap::new(),
             groups: HashMap::new(),
             os_type: None,
             checksum_cache: HashMap::new(),
             checksum_cache_task_id: 0,
             facts: serde_yaml::Value::from(serde_yaml::Mapping::new()),
             notified_handlers: HashMap::new(),
-            package_preference: None
+            package_preference: None,
+            group_order: Mutex::new(None)
         }
     }
 
@@ -151,6 +157,7 @@ impl Host {
     }
 
     pub fn add_group(&mut self, name: &String, group: Arc<RwLock<Group>>) {
+        bump_group_tree_generation();
         self.groups.insert(name.clone(), Arc::clone(&group));
     }
 
@@ -176,6 +183,7 @@ impl Host {
 
     pub fn set_variables(&mut self, variables: serde_yaml::Mapping) {
         self.variables = variables.clone();
+        self.variable_sources.clear();
     }
 
     pub fn update_variables(&mut self, mapping: serde_yaml::Mapping) {
@@ -184,16 +192,53 @@ impl Host {
         }
     }
 
+    // the same as above, but remembering the file the variables came from
+
+    pub fn set_variables_from(&mut self, variables: serde_yaml::Mapping, source: &String) {
+        self.set_variables(variables);
+        record_variable_sources(&mut self.variable_sources, &self.variables, source);
+    }
+
+    pub fn update_variables_from(&mut self, mapping: serde_yaml::Mapping, source: &String) {
+        record_variable_sources(&mut self.variable_sources, &mapping, source);
+        self.update_variables(mapping);
+    }
+
+    // ancestor groups in the order their variables are blended, see sort_groups_by_depth.  Variables are
+    // blended for every template, so the order is kept until the inventory changes rather than sorted each time.
+
+    pub fn get_ordered_ancestor_groups(&self) -> Vec<Arc<RwLock<Group>>> {
+        let generation = get_group_tree_generation();
+        let mut cached = self.group_order.lock().expect("group order lock");
+        if let Some((cached_generation, groups)) = cached.as_ref() {
+            if *cached_generation == generation {
+                return groups.clone();
+            }
+        }
+        let groups = sort_groups_by_depth(self.get_ancestor_groups(20));
+        *cached = Some((generation, groups.clone()));
+        return groups;
+    }
+
+    // inventory variables in the order they are blended: every ancestor group, parents first,
+    // then the host itself, then any facts
+
+    pub fn visit_variable_layers<F>(&self, visit: &mut F) where F: FnMut(String, &serde_yaml::Mapping, LayerSources) {
+        for group in self.get_ordered_ancestor_groups().iter() {
+            let group = group.read().expect("group read");
+            visit(format!("group {}", group.name), &group.variables, LayerSources::PerVariable(&group.variable_sources));
+        }
+        visit(format!("host {}", self.name), &self.variables, LayerSources::PerVariable(&self.variable_sources));
+        if let serde_yaml::Value::Mapping(facts) = &self.facts {
+            visit(String::from("facts"), facts, LayerSources::All(&String::from("set while running")));
+        }
+    }
+
     pub fn get_blended_variables(&self) -> serde_yaml::Mapping {
-        let mut blended : serde_yaml::Value = serde_yaml::Value::from(serde_yaml::Mapping::new());
-        let ancestors = self.get_ancestor_groups(20);
-        for (_k,v) in ancestors.iter() {
-            let theirs : serde_yaml::Value = serde_yaml::Value::from(v.read().unwrap().get_variables());
-            blend_variables(&mut blended, theirs);
-        }
-        let mine = serde_yaml::Value::from(self.get_variables());
-        blend_variables(&mut blended, mine);
-        blend_variables(&mut blended, self.facts.clone());
+        let mut blended = serde_yaml::Value::from(serde_yaml::Mapping::new());
+        self.visit_variable_layers(&mut |_name, variables, _sources| {
+            blend_variables(&mut blended, serde_yaml::Value::Mapping(variables.clone()));
+        });
         return match blended {
             serde_yaml::Value::Mapping(x) => x,
             _ => panic!("get_blended_variables produced a non-mapping (1)")
@@ -226,3 +271,29 @@ impl Host {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(yaml: &str) -> serde_yaml::Mapping {
+        return serde_yaml::from_str(yaml).unwrap();
+    }
+
+    #[test]
+    fn test_group_order_follows_inventory_changes() {
+        let host = Arc::new(RwLock::new(Host::new(&String::from("web1"))));
+        let web = Arc::new(RwLock::new(Group::new(&String::from("web"))));
+        web.write().unwrap().set_variables(mapping("port: 80"));
+        host.write().unwrap().add_group(&String::from("web"), Arc::clone(&web));
+        assert_eq!(host.read().unwrap().get_blended_variables().get("port").unwrap().as_i64(), Some(80));
+
+        // a parent added after the order was cached is blended first, so the child group still wins
+        let prod = Arc::new(RwLock::new(Group::new(&String::from("prod"))));
+        prod.write().unwrap().set_variables(mapping("port: 443\nenv: prod"));
+        web.write().unwrap().add_parent(&String::from("prod"), Arc::clone(&prod));
+        let vars = host.read().unwrap().get_blended_variables();
+        assert_eq!(vars.get("port").unwrap().as_i64(), Some(80));
+        assert_eq!(vars.get("env").unwrap().as_str(), Some("prod"));
+    }
+}
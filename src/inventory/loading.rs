@@ -27,6 +27,11 @@ use std::collections::HashMap;
 use std::process::Command;
 use crate::connection::local::convert_out;
 use crate::util::io::directory_as_string;
+use crate::inventory::source::InventorySource;
+use crate::inventory::files::{YamlInventorySource,JsonInventorySource,IniInventorySource};
+use crate::inventory::cache::{InventoryCacheOptions,get_cache_path,read_cache,write_cache};
+use crate::inventory::patterns::expand_ranges;
+use crate::util::vault::load_vars_file;
 
 // ==============================================================================================================
 // YAML SPEC
@@ -41,12 +46,6 @@ pub struct YamlGroup {
     subgroups : Option<Vec<String>>,
 }
 
-#[derive(Debug,Deserialize)]
-#[serde(deny_unknown_fields)]
-pub enum DynamicInventoryJson {
-    Entry(HashMap<String, DynamicInventoryJsonEntry>)
-}
-
 /* groups named _meta are not real groups */
 #[derive(Debug,Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -61,7 +60,7 @@ pub struct DynamicInventoryJsonEntry {
 // PUBLIC API
 // ==============================================================================================================
 
-pub fn load_inventory(inventory: &Arc<RwLock<Inventory>>, inventory_paths: Arc<RwLock<Vec<PathBuf>>>) -> Result<(), String> {
+pub fn load_inventory(inventory: &Arc<RwLock<Inventory>>, inventory_paths: Arc<RwLock<Vec<PathBuf>>>, cache_options: InventoryCacheOptions) -> Result<(), String> {
 
     {
         let mut inv_obj = inventory.write().unwrap();
@@ -69,28 +68,120 @@ pub fn load_inventory(inventory: &Arc<RwLock<Inventory>>, inventory_paths: Arc<R
     }
 
     for inventory_path_buf in inventory_paths.read().unwrap().iter() {
-        let inventory_path = inventory_path_buf.as_path();
-        if inventory_path.is_dir() {
-            let groups_pathbuf      = inventory_path_buf.join("groups");
-            let groups_path         = groups_pathbuf.as_path();
-
-            if groups_path.exists() && groups_path.is_dir() {
-                load_on_disk_inventory_tree(inventory, true, &inventory_path)?;
-            } else {
-                return Err(format!("missing groups/ in --inventory path parameter ({})", inventory_path.display()))
-            }
+        let source = get_inventory_source(inventory_path_buf.as_path(), cache_options)?;
+        source.load(inventory)?;
+    }
+    return Ok(())
+}
+
+// directories are on-disk inventory trees, files are picked by extension, and anything else
+// that is executable is a dynamic inventory script
+
+pub fn get_inventory_source(inventory_path: &Path, cache_options: InventoryCacheOptions) -> Result<Box<dyn InventorySource>, String> {
+    if inventory_path.is_dir() {
+        return Ok(Box::new(DirectoryInventorySource { path: inventory_path.to_path_buf() }));
+    }
+    let extension = match inventory_path.extension() {
+        Some(x) => x.to_string_lossy().to_lowercase(),
+        None => String::from("")
+    };
+    return match extension.as_str() {
+        "yml" | "yaml" => Ok(Box::new(YamlInventorySource { path: inventory_path.to_path_buf() })),
+        "json"         => Ok(Box::new(JsonInventorySource { path: inventory_path.to_path_buf() })),
+        "ini"          => Ok(Box::new(IniInventorySource { path: inventory_path.to_path_buf() })),
+        _ => match is_executable(&inventory_path) {
+            true  => Ok(Box::new(DynamicInventorySource { path: inventory_path.to_path_buf(), cache_options })),
+            false => Err(format!("non-directory path to --inventory ({}) is not executable or a .yml, .json, or .ini file", inventory_path.display()))
+        }
+    };
+}
+
+// inventory/groups/*, inventory/group_vars/*, inventory/host_vars/*
+
+pub struct DirectoryInventorySource {
+    pub path: PathBuf
+}
+
+impl InventorySource for DirectoryInventorySource {
+    fn load(&self, inventory: &Arc<RwLock<Inventory>>) -> Result<(), String> {
+        let groups_pathbuf = self.path.join("groups");
+        let groups_path    = groups_pathbuf.as_path();
+        if groups_path.exists() && groups_path.is_dir() {
+            return load_on_disk_inventory_tree(inventory, true, &self.path.as_path());
         } else {
-            if is_executable(&inventory_path) {
-                load_dynamic_inventory(inventory, &inventory_path)?;
-                let dirname = directory_as_string(&inventory_path);
-                let dir = Path::new(&dirname);
-                load_on_disk_inventory_tree(inventory, false, &dir)?;
-            } else {
-                return Err(format!("non-directory path to --inventory ({}) is not executable", inventory_path.display()))
-            }    
+            return Err(format!("missing groups/ in --inventory path parameter ({})", self.path.display()))
         }
     }
-    return Ok(())
+}
+
+// an executable that prints JSON, with group_vars/ and host_vars/ possibly alongside it
+
+pub struct DynamicInventorySource {
+    pub path: PathBuf,
+    pub cache_options: InventoryCacheOptions
+}
+
+impl InventorySource for DynamicInventorySource {
+    fn load(&self, inventory: &Arc<RwLock<Inventory>>) -> Result<(), String> {
+        let output = self.get_output()?;
+        load_inventory_json(inventory, &output, &self.path.as_path())?;
+        return load_sibling_vars(inventory, &self.path.as_path());
+    }
+}
+
+impl DynamicInventorySource {
+
+    // script output comes from the cache if it is within the TTL, otherwise from running the script.
+    // If the script fails, the last good output is used regardless of age.
+
+    fn get_output(&self) -> Result<String, String> {
+        return self.get_output_cached_at(&get_cache_path(&self.path.as_path()));
+    }
+
+    fn get_output_cached_at(&self, cache_path: &Path) -> Result<String, String> {
+        if self.cache_options.ttl > 0 && ! self.cache_options.refresh {
+            match read_cache(&cache_path, Some(self.cache_options.ttl)) {
+                Some((output, _age)) => { return Ok(output); },
+                None => {}
+            };
+        }
+
+        let result = match run_dynamic_inventory(&self.path.as_path()) {
+            // output that does not parse is as bad as the script failing, and should not replace a good cache
+            Ok(output) => match parse_inventory_json(&output, &self.path.as_path()) {
+                Ok(_) => Ok(output),
+                Err(y) => Err(y)
+            },
+            Err(y) => Err(y)
+        };
+
+        return match result {
+            Ok(output) => {
+                match write_cache(&cache_path, &output) {
+                    Ok(_) => {},
+                    Err(y) => { println!("warning: {}", y); }
+                };
+                Ok(output)
+            },
+            Err(msg) => match read_cache(&cache_path, None) {
+                Some((output, age)) => {
+                    println!("warning: {}", msg);
+                    println!("warning: using cached inventory for {} from {} seconds ago", self.path.display(), age.as_secs());
+                    Ok(output)
+                },
+                None => Err(msg)
+            }
+        };
+    }
+}
+
+// single file inventories (and dynamic inventory scripts) can still have group_vars/ and host_vars/ directories
+// next to them, which are applied after the file is loaded
+
+pub fn load_sibling_vars(inventory: &Arc<RwLock<Inventory>>, path: &Path) -> Result<(), String> {
+    let dirname = directory_as_string(&path);
+    let dir = Path::new(&dirname);
+    return load_on_disk_inventory_tree(inventory, false, &dir);
 }
 
 // ==============================================================================================================
@@ -141,20 +232,27 @@ fn load_groups_directory(inventory: &Arc<RwLock<Inventory>>, path: &Path) -> Res
             return Err(format!("edit the file and try again?"));
         }   
         let yaml_result = groups_file_parse_result.unwrap();
-        add_group_file_contents_to_inventory(inventory, group_name.clone(), &yaml_result);
-        Ok(())
+        match add_group_file_contents_to_inventory(inventory, group_name.clone(), &yaml_result) {
+            Ok(_) => Ok(()),
+            Err(y) => Err(format!("{}: {}", groups_file_path.display(), y))
+        }
     })?;
     Ok(())
 }
 
 
 // for inventory/groups/* files
-fn add_group_file_contents_to_inventory(inventory: &Arc<RwLock<Inventory>>, group_name: String, yaml_group: &YamlGroup) {
+fn add_group_file_contents_to_inventory(inventory: &Arc<RwLock<Inventory>>, group_name: String, yaml_group: &YamlGroup) -> Result<(), String> {
     let mut inventory = inventory.write().unwrap();
     let hosts = &yaml_group.hosts;
     if hosts.is_some() {
         let hosts = hosts.as_ref().unwrap();
-        for hostname in hosts { inventory.store_host(&group_name.clone(), &hostname.clone()); }
+        // entries may be ranges like app[001:200].dc1 or node-[a:f] to describe many hosts at once
+        for host_entry in hosts {
+            for hostname in expand_ranges(host_entry)?.iter() {
+                inventory.store_host(&group_name.clone(), &hostname.clone());
+            }
+        }
     }
     let subgroups = &yaml_group.subgroups;
     if subgroups.is_some() {
@@ -166,7 +264,7 @@ fn add_group_file_contents_to_inventory(inventory: &Arc<RwLock<Inventory>>, grou
             }
         }
     }
-
+    return Ok(());
 }
             
 // this is used by both on-disk and dynamic inventory sources to load group_vars/ and host_vars/ directories
@@ -195,13 +293,8 @@ fn load_vars_directory(inventory: &Arc<RwLock<Inventory>>, path: &Path, is_group
             }
         }
         
-        let file = jet_file_open(&vars_path)?;
-        let file_parse_result: Result<serde_yaml::Mapping, serde_yaml::Error> = serde_yaml::from_reader(file);
-        if file_parse_result.is_err() {
-             show_yaml_error_in_context(&file_parse_result.unwrap_err(), &vars_path);
-             return Err(format!("edit the file and try again?"));
-        } 
-        let yaml_result = file_parse_result.unwrap();
+        // the file, or values inside it, may be vault encrypted
+        let yaml_result = load_vars_file(&vars_path)?;
         
         // serialize the vars again just to make them easier to store/output elsewhere
         // this will also remove any comments and shorten things up
@@ -209,11 +302,11 @@ fn load_vars_directory(inventory: &Arc<RwLock<Inventory>>, path: &Path, is_group
         match is_group {
             true  => {
                 let group = inv.get_group(&effective_name.clone());
-                group.write().unwrap().set_variables(yaml_result);
+                group.write().unwrap().set_variables_from(yaml_result, &vars_path.display().to_string());
             }
             false => {
                 let host = inv.get_host(&effective_name);
-                host.write().unwrap().set_variables(yaml_result);
+                host.write().unwrap().set_variables_from(yaml_result, &vars_path.display().to_string());
             }
         }
         Ok(())
@@ -221,27 +314,35 @@ fn load_vars_directory(inventory: &Arc<RwLock<Inventory>>, path: &Path, is_group
     Ok(())
 }
 
-// TODO: implement
-fn load_dynamic_inventory(inv: &Arc<RwLock<Inventory>>, path: &Path) -> Result<(), String> {
-
-    let mut inventory = inv.write().unwrap();
+fn run_dynamic_inventory(path: &Path) -> Result<String, String> {
 
     let mut command = Command::new(format!("{}", path.display()));
-    let output = match command.output() {
+    return match command.output() {
         Ok(x) => {
             match x.status.code() {
-                Some(_rc) => convert_out(&x.stdout,&x.stderr),
-                None => { return Err(format!("unable to get status code from process: {}", path.display())) }
+                Some(0) => Ok(convert_out(&x.stdout,&x.stderr)),
+                Some(rc) => Err(format!("inventory script failed: {}, rc={}, out={}", path.display(), rc, convert_out(&x.stdout,&x.stderr).trim())),
+                None => Err(format!("unable to get status code from process: {}", path.display()))
             }
         },
-        Err(y) => { return Err(format!("inventory script failed: {}, {}", path.display(), y)); }
+        Err(y) => Err(format!("inventory script failed: {}, {}", path.display(), y))
     };
+}
 
-    let file_parse_result: Result<HashMap<String, DynamicInventoryJsonEntry>, serde_json::Error> = serde_json::from_str(&output);
-    if file_parse_result.is_err() {
-       return Err(format!("error parsing dynamic inventory source: {:?}: {:?}", path.display(), &file_parse_result.unwrap_err()));
-    } 
-    let json_result = file_parse_result.unwrap();
+fn parse_inventory_json(output: &String, path: &Path) -> Result<HashMap<String, DynamicInventoryJsonEntry>, String> {
+    let file_parse_result: Result<HashMap<String, DynamicInventoryJsonEntry>, serde_json::Error> = serde_json::from_str(output);
+    return match file_parse_result {
+        Ok(x) => Ok(x),
+        Err(y) => Err(format!("error parsing dynamic inventory source: {:?}: {:?}", path.display(), y))
+    };
+}
+
+// the JSON format printed by dynamic inventory scripts, also accepted as a static .json inventory file
+
+pub fn load_inventory_json(inv: &Arc<RwLock<Inventory>>, output: &String, path: &Path) -> Result<(), String> {
+
+    let json_result = parse_inventory_json(output, path)?;
+    let mut inventory = inv.write().unwrap();
 
     for (possible_group_name, entry) in json_result.iter() {
         let group_name = match possible_group_name.eq("_meta") {
@@ -262,7 +363,7 @@ fn load_dynamic_inventory(inv: &Arc<RwLock<Inventory>>, path: &Path) -> Result<(
                 let host = inventory.get_host(&host_name);
                 let vars = convert_json_vars(&values);
                 let mut hst = host.write().unwrap();
-                hst.update_variables(vars);
+                hst.update_variables_from(vars, &path.display().to_string());
             }
         }
         if entry.hosts.is_some() {
@@ -281,7 +382,7 @@ fn load_dynamic_inventory(inv: &Arc<RwLock<Inventory>>, path: &Path) -> Result<(
         if entry.vars.as_ref().is_some() {
             let mut grp = group.write().unwrap();
             let vars = convert_json_vars(&serde_json::Value::Object(entry.vars.clone().unwrap()));
-            grp.update_variables(vars);
+            grp.update_variables_from(vars, &path.display().to_string());
         }
     }
 
@@ -296,3 +397,74 @@ pub fn convert_json_vars(input: &serde_json::Value) -> serde_yaml::Mapping {
        Err(y) => panic!("unable to load JSON back to YAML (1), this shouldn't happen: {}", y)
     } 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use guid_create::GUID;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration,SystemTime};
+
+    // the script prints whatever is in output.json next to it, and fails if that file is missing
+    fn make_script(dir: &Path) -> PathBuf {
+        let script = dir.join("inventory.sh");
+        fs::write(&script, format!("#!/bin/sh\ncat {}\n", dir.join("output.json").display())).unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        return script;
+    }
+
+    fn set_output(dir: &Path, output: Option<&str>) {
+        let path = dir.join("output.json");
+        match output {
+            Some(x) => fs::write(&path, x).unwrap(),
+            None => { let _ = fs::remove_file(&path); }
+        };
+    }
+
+    #[test]
+    fn test_dynamic_inventory_cache() {
+        let dir = std::env::temp_dir().join(format!("jetp-inventory-test-{}", GUID::rand().to_string()));
+        fs::create_dir_all(&dir).unwrap();
+        let cache_path = dir.join("cache").join("inventory.json");
+        let first = String::from("{\"web\":{\"hosts\":[\"www1\"]}}");
+        let second = String::from("{\"web\":{\"hosts\":[\"www2\"]}}");
+        let source = |ttl: u64, refresh: bool| DynamicInventorySource {
+            path: make_script(&dir),
+            cache_options: InventoryCacheOptions { ttl, refresh }
+        };
+
+        // no cache and a failing script is an error
+        set_output(&dir, None);
+        assert!(source(60, false).get_output_cached_at(&cache_path).is_err());
+
+        // the first good run is cached, privately
+        set_output(&dir, Some(&first));
+        assert_eq!(source(60, false).get_output_cached_at(&cache_path).unwrap(), first);
+        assert_eq!(fs::metadata(&cache_path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        // within the TTL the script is not run, unless asked to refresh
+        set_output(&dir, Some(&second));
+        assert_eq!(source(60, false).get_output_cached_at(&cache_path).unwrap(), first);
+        assert_eq!(source(0, false).get_output_cached_at(&cache_path).unwrap(), second);
+        set_output(&dir, Some(&first));
+        assert_eq!(source(60, true).get_output_cached_at(&cache_path).unwrap(), first);
+
+        // past the TTL the script runs again
+        let file = fs::File::options().write(true).open(&cache_path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(120)).unwrap();
+        set_output(&dir, Some(&second));
+        assert_eq!(source(60, false).get_output_cached_at(&cache_path).unwrap(), second);
+
+        // a failing script or one printing garbage falls back to the cache however old it is,
+        // and garbage does not replace it
+        let file = fs::File::options().write(true).open(&cache_path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(86400)).unwrap();
+        set_output(&dir, Some("not json"));
+        assert_eq!(source(60, false).get_output_cached_at(&cache_path).unwrap(), second);
+        set_output(&dir, None);
+        assert_eq!(source(0, false).get_output_cached_at(&cache_path).unwrap(), second);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
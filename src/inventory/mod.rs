@@ -17,4 +17,8 @@
 pub mod groups;
 pub mod hosts;
 pub mod loading;
+pub mod source;
+pub mod files;
+pub mod cache;
+pub mod patterns;
 pub mod inventory;
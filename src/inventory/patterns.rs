@@ -0,0 +1,324 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::sync::{Arc,RwLock};
+use regex::Regex;
+use crate::inventory::inventory::Inventory;
+use crate::inventory::hosts::Host;
+use crate::util::wildcard::wildcard_match;
+
+// host patterns select hosts out of inventory for --limit-hosts, --limit-groups, play groups and delegate_to.
+//
+//    web1                      an exact name
+//    web*, db?.example.com     globs
+//    db[01:12].example.com     numeric ranges, zero padded if the start is, also [a:f] and [1:20:2]
+//    ~web\d+\.prod             regexes (unanchored, as with grep)
+//    webservers:&prod:!web07   terms are unioned, then '&' terms intersected, then '!' terms excluded
+//
+// terms are separated by ':' or ','.  A pattern made only of '&' and '!' terms starts from every host.
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum PatternScope {
+    // terms are tested against host names only
+    Hosts,
+    // terms are tested against group names only, a matching group selects all hosts in it
+    Groups,
+    // terms are tested against host names and group names, a matching group selects all hosts in it
+    Any
+}
+
+pub struct HostPattern {
+    terms: Vec<PatternTerm>
+}
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+enum TermOp {
+    Union,
+    Intersect,
+    Exclude
+}
+
+enum Matcher {
+    Exact(Vec<String>),
+    Glob(Vec<String>),
+    Regex(Regex)
+}
+
+struct PatternTerm {
+    op: TermOp,
+    matcher: Matcher
+}
+
+// ==============================================================================================================
+// PUBLIC API
+// ==============================================================================================================
+
+// true if the value uses any pattern syntax and is not just a plain name
+
+pub fn is_host_pattern(value: &String) -> bool {
+    return value.contains(|c: char| "*?[~&!:,".contains(c));
+}
+
+// splits a pattern into terms on ':' and ',' but not inside [ranges] or regex {n,m} repetitions
+
+pub fn split_pattern(value: &String) -> Vec<String> {
+    let mut results : Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    for c in value.chars() {
+        match c {
+            '[' | '{' => { depth = depth + 1; current.push(c); },
+            ']' | '}' => { if depth > 0 { depth = depth - 1; } current.push(c); },
+            ':' | ',' if depth == 0 => {
+                if ! current.trim().is_empty() { results.push(String::from(current.trim())); }
+                current.clear();
+            },
+            _ => current.push(c)
+        }
+    }
+    if ! current.trim().is_empty() { results.push(String::from(current.trim())); }
+    return results;
+}
+
+// "app[01:03].dc[1:2]" -> app01.dc1, app01.dc2, app02.dc1 ...
+
+pub fn expand_ranges(value: &String) -> Result<Vec<String>, String> {
+    let start = match value.find('[') {
+        Some(x) => x,
+        None => { return Ok(vec![value.clone()]); }
+    };
+    let end = match value[start..].find(']') {
+        Some(x) => start + x,
+        None => { return Err(format!("unterminated range in: {}", value)); }
+    };
+    let prefix = &value[0..start];
+    let suffix = String::from(&value[end+1..]);
+    let items = expand_range(&value[start+1..end], value)?;
+    let rests = expand_ranges(&suffix)?;
+    let mut results : Vec<String> = Vec::new();
+    for item in items.iter() {
+        for rest in rests.iter() {
+            results.push(format!("{}{}{}", prefix, item, rest));
+        }
+    }
+    return Ok(results);
+}
+
+impl HostPattern {
+
+    pub fn parse(value: &String) -> Result<Self, String> {
+        return HostPattern::from_list(&vec![value.clone()]);
+    }
+
+    // each entry may itself be a pattern, as in a play's list of groups
+    pub fn from_list(values: &Vec<String>) -> Result<Self, String> {
+        let mut terms : Vec<PatternTerm> = Vec::new();
+        for value in values.iter() {
+            for term in split_pattern(value).iter() {
+                terms.push(PatternTerm::parse(term)?);
+            }
+        }
+        if terms.is_empty() {
+            return Err(String::from("empty host pattern"));
+        }
+        return Ok(Self { terms });
+    }
+
+    // returns the selected hosts.  Plain names that are not in inventory are an error, as those are almost
+    // always typos, but globs, ranges and regexes are allowed to match nothing.
+
+    pub fn select(&self, inventory: &Inventory, scope: PatternScope) -> Result<HashMap<String, Arc<RwLock<Host>>>, String> {
+        let mut results : HashMap<String, Arc<RwLock<Host>>> = HashMap::new();
+
+        let has_unions = self.terms.iter().any(|t| t.op == TermOp::Union);
+        if ! has_unions {
+            for (k,v) in inventory.hosts.iter() {
+                results.insert(k.clone(), Arc::clone(v));
+            }
+        }
+        for op in [TermOp::Union, TermOp::Intersect, TermOp::Exclude] {
+            for term in self.terms.iter().filter(|t| t.op == op) {
+                let matched = term.select(inventory, scope)?;
+                match op {
+                    TermOp::Union     => { for (k,v) in matched.into_iter() { results.insert(k, v); } },
+                    TermOp::Intersect => { results.retain(|k,_| matched.contains_key(k)); },
+                    TermOp::Exclude   => { results.retain(|k,_| !matched.contains_key(k)); }
+                }
+            }
+        }
+        return Ok(results);
+    }
+
+    pub fn select_names(&self, inventory: &Inventory, scope: PatternScope) -> Result<Vec<String>, String> {
+        let mut names : Vec<String> = self.select(inventory, scope)?.into_keys().collect();
+        names.sort();
+        return Ok(names);
+    }
+}
+
+// ==============================================================================================================
+// PRIVATE INTERNALS
+// ==============================================================================================================
+
+impl PatternTerm {
+
+    fn parse(term: &String) -> Result<Self, String> {
+        let (op, text) = match term.chars().next() {
+            Some('&') => (TermOp::Intersect, String::from(&term[1..])),
+            Some('!') => (TermOp::Exclude, String::from(&term[1..])),
+            _ => (TermOp::Union, term.clone())
+        };
+        if text.is_empty() {
+            return Err(format!("invalid host pattern term: {}", term));
+        }
+        let matcher = match text.starts_with("~") {
+            true => match Regex::new(&text[1..]) {
+                Ok(x) => Matcher::Regex(x),
+                Err(y) => { return Err(format!("invalid regex in host pattern ({}): {}", text, y)); }
+            },
+            false => {
+                // a range describes a set of names that need not all exist, so only plain names are exact
+                let names = expand_ranges(&text)?;
+                match text.contains(|c: char| c == '*' || c == '?' || c == '[') {
+                    true => Matcher::Glob(names),
+                    false => Matcher::Exact(names)
+                }
+            }
+        };
+        return Ok(Self { op, matcher });
+    }
+
+    fn matches(&self, name: &String) -> bool {
+        return match &self.matcher {
+            Matcher::Exact(names) => names.contains(name),
+            Matcher::Glob(globs) => globs.iter().any(|g| wildcard_match(g, name)),
+            Matcher::Regex(re) => re.is_match(name)
+        };
+    }
+
+    fn select(&self, inventory: &Inventory, scope: PatternScope) -> Result<HashMap<String, Arc<RwLock<Host>>>, String> {
+        let mut results : HashMap<String, Arc<RwLock<Host>>> = HashMap::new();
+        if scope != PatternScope::Groups {
+            for (k,v) in inventory.hosts.iter() {
+                if self.matches(k) {
+                    results.insert(k.clone(), Arc::clone(v));
+                }
+            }
+        }
+        if scope != PatternScope::Hosts {
+            for (k,v) in inventory.groups.iter() {
+                if self.matches(k) {
+                    for (hk,hv) in v.read().unwrap().get_descendant_hosts().into_iter() {
+                        results.insert(hk, hv);
+                    }
+                }
+            }
+        }
+        if let Matcher::Exact(names) = &self.matcher {
+            for name in names.iter() {
+                match scope {
+                    PatternScope::Hosts => if ! inventory.has_host(name) {
+                        return Err(format!("at least one referenced host ({}) is not found in inventory", name));
+                    },
+                    PatternScope::Groups => if ! inventory.has_group(name) {
+                        return Err(format!("at least one referenced group ({}) is not found in inventory", name));
+                    },
+                    PatternScope::Any => if ! (inventory.has_host(name) || inventory.has_group(name)) {
+                        return Err(format!("at least one referenced host or group ({}) is not found in inventory", name));
+                    }
+                }
+            }
+        }
+        return Ok(results);
+    }
+}
+
+// the inside of one [start:end] or [start:end:step] range
+
+fn expand_range(range: &str, value: &String) -> Result<Vec<String>, String> {
+    let parts : Vec<&str> = range.split(':').collect();
+    if parts.len() < 2 || parts.len() > 3 || parts[0].is_empty() || parts[1].is_empty() {
+        return Err(format!("invalid range [{}] in: {}", range, value));
+    }
+    let step = match parts.len() {
+        3 => match parts[2].parse::<usize>() {
+            Ok(x) if x > 0 => x,
+            _ => { return Err(format!("invalid range step [{}] in: {}", range, value)); }
+        },
+        _ => 1
+    };
+    let mut results : Vec<String> = Vec::new();
+    match (parts[0].parse::<u64>(), parts[1].parse::<u64>()) {
+        (Ok(start), Ok(end)) => {
+            if start > end {
+                return Err(format!("invalid range [{}] in: {}, the start is past the end", range, value));
+            }
+            // [01:12] keeps the leading zero
+            let width = match parts[0].starts_with('0') { true => parts[0].len(), false => 0 };
+            for n in (start..=end).step_by(step) {
+                results.push(format!("{:0width$}", n, width = width));
+            }
+        },
+        _ => {
+            let start : Vec<char> = parts[0].chars().collect();
+            let end : Vec<char> = parts[1].chars().collect();
+            if start.len() != 1 || end.len() != 1 || !start[0].is_ascii_alphabetic() || !end[0].is_ascii_alphabetic() || start[0] > end[0] {
+                return Err(format!("invalid range [{}] in: {}", range, value));
+            }
+            for c in (start[0]..=end[0]).step_by(step) {
+                results.push(c.to_string());
+            }
+        }
+    }
+    return Ok(results);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_ranges() {
+        assert_eq!(expand_ranges(&String::from("db[01:03].example.com")).unwrap(),
+            vec!["db01.example.com", "db02.example.com", "db03.example.com"]);
+        assert_eq!(expand_ranges(&String::from("r[1:2]n[a:b]")).unwrap(), vec!["r1na", "r1nb", "r2na", "r2nb"]);
+        assert_eq!(expand_ranges(&String::from("n[0:6:3]")).unwrap(), vec!["n0", "n3", "n6"]);
+        assert!(expand_ranges(&String::from("n[5:1]")).is_err());
+        assert!(expand_ranges(&String::from("n[1:")).is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        let mut inventory = Inventory::new();
+        for (group, host) in [("webservers","web06"), ("webservers","web07"), ("webservers","web10"), ("prod","web06"), ("prod","web07"), ("prod","db01")] {
+            inventory.store_host(&String::from(group), &String::from(host));
+        }
+        let select = |p: &str, scope| HostPattern::parse(&String::from(p)).unwrap().select_names(&inventory, scope);
+        assert_eq!(select("webservers:&prod:!web07", PatternScope::Any).unwrap(), vec!["web06"]);
+        assert_eq!(select("web*:!web1?", PatternScope::Hosts).unwrap(), vec!["web06", "web07"]);
+        assert_eq!(select("~^web0[67]$,db01", PatternScope::Hosts).unwrap(), vec!["db01", "web06", "web07"]);
+        assert_eq!(select("!prod", PatternScope::Any).unwrap(), vec!["web10"]);
+        assert_eq!(select("web[06:08]", PatternScope::Hosts).unwrap(), vec!["web06", "web07"]);
+        assert!(select("web99", PatternScope::Hosts).is_err());
+        assert!(select("nope*", PatternScope::Any).unwrap().is_empty());
+        // groups only, a host name is not a group
+        assert_eq!(select("prod:!webservers", PatternScope::Groups).unwrap(), vec!["db01"]);
+        assert_eq!(select("web*", PatternScope::Groups).unwrap(), vec!["web06", "web07", "web10"]);
+        assert!(select("prod:&web06", PatternScope::Groups).is_err());
+        assert!(select("~^web0", PatternScope::Groups).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,29 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::inventory::inventory::Inventory;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+// the trait behind every kind of path that can be given to --inventory: the on-disk directory tree,
+// dynamic inventory scripts, and single file YAML, JSON, and INI inventories.  See get_inventory_source
+// in loading.rs for how one is chosen.
+
+pub trait InventorySource {
+
+    fn load(&self, inventory: &Arc<RwLock<Inventory>>) -> Result<(), String>;
+
+}
@@ -27,8 +27,12 @@ mod handle;
 use crate::util::io::{quit};
 use crate::inventory::inventory::Inventory;
 use crate::inventory::loading::{load_inventory};
-use crate::cli::show::{show_inventory_group,show_inventory_host};
+use crate::inventory::cache::InventoryCacheOptions;
+use crate::cli::show::{show_inventory_group,show_inventory_host,show_inventory_pattern,show_host_variables};
 use crate::cli::parser::{CliParser};
+use crate::cli::vault::handle_vault;
+use crate::cli::roles::handle_roles;
+use crate::util::vault::set_vault_password;
 use crate::cli::playbooks::{playbook_ssh,playbook_local,playbook_check_ssh,playbook_check_local,playbook_simulate}; // FIXME: check modes coming
 use std::sync::{Arc,RwLock};
 use std::process;
@@ -52,11 +56,17 @@ fn liftoff() -> Result<(),String> {
         return Ok(());
     }
 
+    // vaulted vars files and !vault values are decrypted as they are loaded
+    if let Some(password) = &cli_parser.vault_password {
+        set_vault_password(password);
+    }
+
     let inventory : Arc<RwLock<Inventory>> = Arc::new(RwLock::new(Inventory::new()));
 
     match cli_parser.mode {
-        cli::parser::CLI_MODE_SSH | cli::parser::CLI_MODE_CHECK_SSH | cli::parser::CLI_MODE_SHOW | cli::parser::CLI_MODE_SIMULATE => {
-            load_inventory(&inventory, Arc::clone(&cli_parser.inventory_paths))?;
+        cli::parser::CLI_MODE_SSH | cli::parser::CLI_MODE_CHECK_SSH | cli::parser::CLI_MODE_SHOW | cli::parser::CLI_MODE_SHOW_VARS | cli::parser::CLI_MODE_SIMULATE => {
+            let cache_options = InventoryCacheOptions { ttl: cli_parser.inventory_cache_ttl, refresh: cli_parser.refresh_inventory };
+            load_inventory(&inventory, Arc::clone(&cli_parser.inventory_paths), cache_options)?;
             if ! cli_parser.inventory_set {
                 return Err(String::from("--inventory is required"));
             }
@@ -71,6 +81,9 @@ fn liftoff() -> Result<(),String> {
 
     match cli_parser.mode {
         cli::parser::CLI_MODE_SHOW => {},
+        cli::parser::CLI_MODE_SHOW_VARS => {},
+        cli::parser::CLI_MODE_VAULT => {},
+        cli::parser::CLI_MODE_ROLES => {},
         _ => {
             if ! cli_parser.playbook_set {
                 return Err(String::from("--playbook is required"));
@@ -90,6 +103,27 @@ fn liftoff() -> Result<(),String> {
                 1
             }
         }
+        cli::parser::CLI_MODE_SHOW_VARS   => match handle_show_vars(&inventory, &cli_parser) {
+            Ok(_) => 0,
+            Err(s) => {
+                println!("{}", s);
+                1
+            }
+        }
+        cli::parser::CLI_MODE_VAULT   => match handle_vault(&cli_parser) {
+            Ok(_) => 0,
+            Err(s) => {
+                println!("{}", s);
+                1
+            }
+        }
+        cli::parser::CLI_MODE_ROLES   => match handle_roles(&cli_parser) {
+            Ok(_) => 0,
+            Err(s) => {
+                println!("{}", s);
+                1
+            }
+        }
         cli::parser::CLI_MODE_SSH         => playbook_ssh(&inventory, &cli_parser),
         cli::parser::CLI_MODE_CHECK_SSH   => playbook_check_ssh(&inventory, &cli_parser),
         cli::parser::CLI_MODE_LOCAL       => playbook_local(&inventory, &cli_parser),
@@ -108,7 +142,8 @@ pub fn handle_show(inventory: &Arc<RwLock<Inventory>>, parser: &CliParser) -> Re
     // jetp show -i inventory
     // jetp show -i inventory --groups g1:g2
     // jetp show -i inventory --hosts h1:h2
-    if parser.show_groups.is_empty() && parser.show_hosts.is_empty() {
+    // jetp show -i inventory --show-pattern 'web*:&prod'
+    if parser.show_groups.is_empty() && parser.show_hosts.is_empty() && parser.show_pattern.is_none() {
         show_inventory_group(inventory, &String::from("all"))?;
     }
     for group_name in parser.show_groups.iter() {
@@ -117,6 +152,20 @@ pub fn handle_show(inventory: &Arc<RwLock<Inventory>>, parser: &CliParser) -> Re
     for host_name in parser.show_hosts.iter() {
         show_inventory_host(inventory, &host_name.clone())?;
     }
+    if parser.show_pattern.is_some() {
+        show_inventory_pattern(inventory, &parser.show_pattern.as_ref().unwrap())?;
+    }
     return Ok(());
 }
 
+pub fn handle_show_vars(inventory: &Arc<RwLock<Inventory>>, parser: &CliParser) -> Result<(), String> {
+    // jetp show-vars -i inventory --show-hosts h1:h2
+    // jetp show-vars -i inventory --show-hosts h1 -p playbook.yml -e @extra.yml
+    if parser.show_hosts.is_empty() {
+        return Err(String::from("show-vars requires --show-hosts"));
+    }
+    for host_name in parser.show_hosts.iter() {
+        show_host_variables(inventory, parser, &host_name.clone())?;
+    }
+    return Ok(());
+}
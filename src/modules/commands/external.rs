@@ -17,10 +17,9 @@
 use crate::tasks::*;
 use crate::handle::handle::TaskHandle;
 use serde::{Deserialize};
-use std::sync::{Arc,RwLock};
+use std::sync::Arc;
 use serde_json;
 use std::path::PathBuf;
-use crate::inventory::hosts::Host;
 
 const MODULE: &str = "External";
 
@@ -40,9 +39,6 @@ pub struct ExternalTask {
 struct ExternalAction {
     pub use_module: PathBuf,
     pub params: String,
-    pub save: Option<String>, 
-    pub failed_when: Option<String>,
-    pub changed_when: Option<String>,
 }
 
 
@@ -73,12 +69,9 @@ impl IsTask for ExternalTask {
                             }
                         }
                     },
-                    save: handle.template.string_option_no_spaces(&request, tm, &String::from("save"), &self.save)?,
-                    failed_when: handle.template.string_option_unsafe_for_shell(&request, tm, &String::from("failed_when"), &self.failed_when)?,
-                    changed_when: handle.template.string_option_unsafe_for_shell(&request, tm, &String::from("changed_when"), &self.changed_when)?,
                 }),
                 with: Arc::new(PreLogicInput::template(&handle, &request, tm, &self.with)?),
-                and: Arc::new(PostLogicInput::template(&handle, &request, tm, &self.and)?),
+                and: Arc::new(PostLogicInput::template_with_shorthand(&handle, &request, tm, &self.and, &self.save, &self.failed_when, &self.changed_when)?),
             }
         );
     }
@@ -126,32 +119,10 @@ impl IsAction for ExternalAction {
 
                 let map_data = build_results_map(handle, request, rc, &out)?;
 
-                let should_fail = match self.failed_when.is_none() {
-                    true => match rc { 0 => false, _ => true },
-                    false => {
-                        let condition = self.failed_when.as_ref().unwrap();
-                        handle.template.test_condition_with_extra_data(request, TemplateMode::Strict, condition, &handle.host, map_data.clone())?
-                    }
-                };
-
-                let should_mark_changed = match self.changed_when.is_none() {
-                    true => true,
-                    false => {
-                        let condition = self.changed_when.as_ref().unwrap();
-                        handle.template.test_condition_with_extra_data(request, TemplateMode::Strict, condition, &handle.host, map_data.clone())?
-                    }
-                };
-
-                if self.save.is_some() {
-                    save_results(&handle.host, self.save.as_ref().unwrap(), map_data);
-                }
-
-                return match should_fail {
-                    true => Err(handle.response.command_failed(request, &Arc::clone(&task_result.command_result))),
-                    false => match should_mark_changed {
-                        true => Ok(task_result),
-                        false => Ok(handle.response.is_passive(request))
-                    }
+                // failed_when and changed_when are applied afterwards, see task_fsm
+                return match rc {
+                    0 => Ok(handle.response.with_data(&task_result, map_data)),
+                    _ => Err(handle.response.with_data(&handle.response.command_failed(request, &Arc::clone(&task_result.command_result)), map_data))
                 };
                 
             },
@@ -182,10 +153,3 @@ fn build_results_map(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, rc: i
     }
     return Ok(result);
 }
-
-fn save_results(host: &Arc<RwLock<Host>>, key: &String, map_data: serde_yaml::Mapping) {
-    let mut result = serde_yaml::Mapping::new();
-    result.insert(serde_yaml::Value::String(key.clone()), serde_yaml::Value::Mapping(map_data.clone()));
-    host.write().unwrap().update_variables(result);
-}
-
@@ -0,0 +1,88 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::registry::list::Task;
+use serde::Deserialize;
+use std::sync::Arc;
+
+const MODULE: &str = "block";
+
+// a block groups tasks so they can share a 'with' section (condition, sudo, tags, delegate_to, no_log)
+// and so failures can be handled.  Hosts that fail in 'tasks' run 'rescue', and if the rescue tasks
+// succeed the host carries on as if nothing failed.  'always' runs on every host that entered the
+// block, failed or not.  Blocks are walked by playbooks/traversal.rs and are never dispatched.
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BlockTask {
+    pub name: Option<String>,
+    pub tasks: Vec<Task>,
+    pub rescue: Option<Vec<Task>>,
+    pub always: Option<Vec<Task>>,
+    pub with: Option<PreLogicInput>
+}
+
+impl IsTask for BlockTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, _tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        return Err(handle.response.is_failed(request, &String::from("a block cannot be run as a task")));
+    }
+
+}
+
+impl BlockTask {
+
+    // the parts of 'with' that would have to be applied to the block as a whole are not supported
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(with) = &self.with {
+            if with.items.is_some() {
+                return Err(format!("block {}: with/items is not supported on blocks, use it on the tasks inside", self.get_display_name()));
+            }
+            if with.subscribe.is_some() {
+                return Err(format!("block {}: with/subscribe is not supported on blocks, use it on the handlers inside", self.get_display_name()));
+            }
+        }
+        return Ok(());
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_parsing() {
+        let yaml = "- !block\n  name: b\n  tasks:\n    - !echo\n      msg: hi\n  rescue:\n    - !echo\n      msg: rescued\n  with:\n    items: [ a ]\n";
+        let tasks : Vec<Task> = serde_yaml::from_str(yaml).unwrap();
+        match &tasks[0] {
+            Task::Block(block) => {
+                assert_eq!(block.tasks.len(), 1);
+                assert_eq!(block.rescue.as_ref().unwrap().len(), 1);
+                assert!(block.always.is_none());
+                assert!(block.validate().is_err());
+            },
+            _ => panic!("expected a block")
+        }
+    }
+}
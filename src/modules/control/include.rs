@@ -0,0 +1,66 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use serde::Deserialize;
+use std::sync::Arc;
+
+const MODULE: &str = "include";
+
+// include runs the tasks from another YAML file, so plays and roles can share task files.
+// Relative paths are found next to the playbook, or in the tasks/ (or handlers/) directory
+// of the current role.  The file name may use variables, in which case each host runs the
+// file its variables select.  'vars' are visible only to the included tasks, and the 'with'
+// section applies to all of them, the same as for a block.  Includes are walked by
+// playbooks/traversal.rs and are never dispatched.
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct IncludeTask {
+    pub name: Option<String>,
+    pub file: String,
+    pub vars: Option<serde_yaml::Mapping>,
+    pub with: Option<PreLogicInput>
+}
+
+impl IsTask for IncludeTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, _tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        return Err(handle.response.is_failed(request, &String::from("an include cannot be run as a task")));
+    }
+
+}
+
+impl IncludeTask {
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(with) = &self.with {
+            if with.items.is_some() {
+                return Err(format!("include {}: with/items is not supported on includes", self.file));
+            }
+            if with.subscribe.is_some() {
+                return Err(format!("include {}: with/subscribe is not supported on includes, use it on the handlers inside", self.file));
+            }
+        }
+        return Ok(());
+    }
+
+}
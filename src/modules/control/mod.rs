@@ -17,8 +17,10 @@
 /** ADD MODULES HERE, KEEP ALPHABETIZED **/
 
 pub mod assert;
+pub mod block;
 pub mod debug;
 pub mod echo;
 pub mod fail;
 pub mod facts;
+pub mod include;
 pub mod set;
\ No newline at end of file
@@ -0,0 +1,160 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::tasks::fields::Field;
+use crate::tasks::files::{ArchiveFormat,Recurse};
+use serde::{Deserialize};
+use std::sync::Arc;
+use std::vec::Vec;
+
+const MODULE: &str = "archive";
+
+// packs remote paths into a .tar, .tar.gz or .tar.xz file on the same host, the counterpart of unarchive.
+// The tarball is rebuilt when anything in paths is newer than it.
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ArchiveTask {
+    pub name: Option<String>,
+    pub paths: Vec<String>,
+    pub dest: String,
+    pub attributes: Option<FileAttributesInput>,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+struct ArchiveAction {
+    pub paths: Vec<String>,
+    pub dest: String,
+    pub format: ArchiveFormat,
+    pub attributes: Option<FileAttributesEvaluated>,
+}
+
+impl IsTask for ArchiveTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let dest = handle.template.path(&request, tm, &String::from("dest"), &self.dest)?;
+        let mut paths : Vec<String> = Vec::new();
+        for path in self.paths.iter() {
+            paths.push(handle.template.path(&request, tm, &String::from("paths"), path)?);
+        }
+        let format = match tm {
+            TemplateMode::Off => ArchiveFormat::Tar,
+            _ => match ArchiveFormat::from_path(&dest) {
+                Ok(ArchiveFormat::Zip) => { return Err(handle.response.is_failed(request, &String::from("the archive module only creates tarballs"))); },
+                Ok(x) => x,
+                Err(y) => { return Err(handle.response.is_failed(request, &y)); }
+            }
+        };
+        if tm != TemplateMode::Off {
+            if paths.is_empty() {
+                return Err(handle.response.is_failed(request, &String::from("paths cannot be empty")));
+            }
+            // each path is added from its parent directory, and relative ones would pile up
+            if let Some(x) = paths.iter().find(|x| ! x.starts_with("/")) {
+                return Err(handle.response.is_failed(request, &format!("paths must be absolute: {}", x)));
+            }
+        }
+        return Ok(
+            EvaluatedTask {
+                action: Arc::new(ArchiveAction {
+                    paths:      paths,
+                    dest:       dest,
+                    format:     format,
+                    attributes: FileAttributesInput::template(&handle, &request, tm, &self.attributes)?
+                }),
+                with: Arc::new(PreLogicInput::template(&handle, &request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(&handle, &request, tm, &self.and)?),
+            }
+        );
+    }
+
+}
+
+impl IsAction for ArchiveAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+
+        match request.request_type {
+
+            TaskRequestType::Query => {
+                for path in self.paths.iter() {
+                    if handle.remote.get_mode(request, path)?.is_none() {
+                        return Err(handle.response.is_failed(request, &format!("{} does not exist", path)));
+                    }
+                }
+                let mut changes : Vec<Field> = Vec::new();
+                let remote_mode = handle.remote.query_common_file_attributes(request, &self.dest, &self.attributes, &mut changes, Recurse::No)?;
+                if remote_mode.is_none() {
+                    return Ok(handle.response.needs_creation(request));
+                }
+                for path in self.paths.iter() {
+                    if handle.remote.get_is_newer(request, path, &self.dest)? {
+                        changes.push(Field::Content);
+                        break;
+                    }
+                }
+                if ! changes.is_empty() {
+                    return Ok(handle.response.needs_modification(request, &changes));
+                }
+                return Ok(handle.response.is_matched(request));
+            },
+
+            TaskRequestType::Create => {
+                self.do_archive(handle, request)?;
+                handle.remote.process_all_common_file_attributes(request, &self.dest, &self.attributes, Recurse::No)?;
+                return Ok(handle.response.is_created(request));
+            },
+
+            TaskRequestType::Modify => {
+                if request.changes.contains(&Field::Content) {
+                    self.do_archive(handle, request)?;
+                    handle.remote.process_all_common_file_attributes(request, &self.dest, &self.attributes, Recurse::No)?;
+                }
+                else {
+                    handle.remote.process_common_file_attributes(request, &self.dest, &self.attributes, &request.changes, Recurse::No)?;
+                }
+                return Ok(handle.response.is_modified(request, request.changes.clone()));
+            },
+
+            _ => { return Err(handle.response.not_supported(request)); }
+
+        }
+    }
+
+}
+
+impl ArchiveAction {
+
+    // the tarball is written next to dest and moved into place, so a failed run does not leave a
+    // partial file behind that looks newer than everything in it
+
+    fn do_archive(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+        let partial = format!("{}.part", self.dest);
+        if let Err(x) = handle.remote.create_archive(request, self.format, &partial, &self.paths) {
+            let _ = handle.remote.delete_file(request, &partial);
+            return Err(x);
+        }
+        handle.remote.move_file(request, &partial, &self.dest)?;
+        return Ok(());
+    }
+
+}
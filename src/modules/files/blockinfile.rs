@@ -0,0 +1,103 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::tasks::lines::{Anchor,EditInPlace,ensure_block};
+use crate::modules::files::lineinfile::template_anchor;
+use serde::{Deserialize};
+use std::sync::Arc;
+
+const MODULE: &str = "blockinfile";
+
+// manages several lines of a file between two marker lines, leaving the rest of the file alone.
+// The marker must contain {mark}, which becomes BEGIN and END.
+
+const DEFAULT_MARKER: &str = "# {mark} MANAGED BY JETP";
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BlockInFileTask {
+    pub name: Option<String>,
+    pub path: String,
+    pub block: Option<String>,
+    pub marker: Option<String>,
+    pub insert_after: Option<String>,
+    pub insert_before: Option<String>,
+    pub create: Option<String>,
+    pub backup: Option<String>,
+    pub remove: Option<String>,
+    pub attributes: Option<FileAttributesInput>,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+
+struct BlockInFileAction {
+    pub file: EditInPlace,
+    pub block: String,
+    pub marker_begin: String,
+    pub marker_end: String,
+    pub anchor: Anchor,
+}
+
+impl IsTask for BlockInFileTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let remove = handle.template.boolean_option_default_false(&request, tm, &String::from("remove"), &self.remove)?;
+        // with templates off (the first pass over a task) remove is not known yet
+        if tm != TemplateMode::Off && ! remove && self.block.is_none() {
+            return Err(handle.response.is_failed(request, &String::from("block is required unless remove is set")));
+        }
+        let marker = handle.template.string_option_unsafe_for_shell(&request, tm, &String::from("marker"), &self.marker)?.unwrap_or(String::from(DEFAULT_MARKER));
+        if tm != TemplateMode::Off && marker.find("{mark}").is_none() {
+            return Err(handle.response.is_failed(request, &String::from("marker must contain {mark}")));
+        }
+        return Ok(
+            EvaluatedTask {
+                action: Arc::new(BlockInFileAction {
+                    file: EditInPlace {
+                        path:       handle.template.path(&request, tm, &String::from("path"), &self.path)?,
+                        create:     handle.template.boolean_option_default_false(&request, tm, &String::from("create"), &self.create)?,
+                        backup:     handle.template.boolean_option_default_false(&request, tm, &String::from("backup"), &self.backup)?,
+                        remove:     remove,
+                        attributes: FileAttributesInput::template(&handle, &request, tm, &self.attributes)?
+                    },
+                    block:        handle.template.string_option_unsafe_for_shell(&request, tm, &String::from("block"), &self.block)?.unwrap_or(String::new()),
+                    marker_begin: marker.replace("{mark}", "BEGIN"),
+                    marker_end:   marker.replace("{mark}", "END"),
+                    anchor:       template_anchor(handle, request, tm, &self.insert_after, &self.insert_before)?,
+                }),
+                with: Arc::new(PreLogicInput::template(&handle, &request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(&handle, &request, tm, &self.and)?),
+            }
+        );
+    }
+
+}
+
+impl IsAction for BlockInFileAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+        return self.file.dispatch(handle, request, |content| {
+            ensure_block(content, &self.marker_begin, &self.marker_end, &self.block, self.file.remove, &self.anchor)
+        });
+    }
+
+}
@@ -75,6 +75,7 @@ impl IsAction for CopyAction {
                 let mut changes : Vec<Field> = Vec::new();
                 let remote_mode = handle.remote.query_common_file_attributes(request, &self.dest, &self.attributes, &mut changes, Recurse::No)?;                   
                 if remote_mode.is_none() {
+                    handle.remote.show_file_diff(request, &self.dest, &remote_mode, &self.attributes, &changes, || handle.local.read_bytes(request, &self.src))?;
                     return Ok(handle.response.needs_creation(request));
                 }
                 // this query leg is (at least originally) the same as the template module query except these two lines
@@ -86,6 +87,7 @@ impl IsAction for CopyAction {
                     changes.push(Field::Content); 
                 }
                 if ! changes.is_empty() {
+                    handle.remote.show_file_diff(request, &self.dest, &remote_mode, &self.attributes, &changes, || handle.local.read_bytes(request, &self.src))?;
                     return Ok(handle.response.needs_modification(request, &changes));
                 }
                 return Ok(handle.response.is_matched(request));
@@ -0,0 +1,194 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::tasks::checksum::sha512_bytes;
+use crate::tasks::fields::Field;
+use std::path::{Component,Path,PathBuf};
+use serde::{Deserialize};
+use std::sync::Arc;
+
+const MODULE: &str = "fetch";
+
+// copies a file from the remote host back to the control machine.  Files are stored by host name
+// and full remote path below dest, so fetching /etc/hosts from web1 into 'fetched' writes
+// fetched/web1/etc/hosts.  Unlike the other file modules this changes the control machine, not the host.
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FetchTask {
+    pub name: Option<String>,
+    pub src: String,
+    pub dest: String,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+struct FetchAction {
+    pub src: String,
+    pub dest: PathBuf,
+}
+
+impl IsTask for FetchTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let src = handle.template.path(&request, tm, &String::from("src"), &self.src)?;
+        let dest_dir = handle.template.path(&request, tm, &String::from("dest"), &self.dest)?;
+        let host_name = handle.host.read().unwrap().name.clone();
+        let dest = match get_local_path(&dest_dir, &host_name, &src) {
+            Ok(x) => x,
+            Err(y) => match tm {
+                TemplateMode::Off => PathBuf::new(),
+                _ => { return Err(handle.response.is_failed(request, &format!("field (src): {}", y))); }
+            }
+        };
+        return Ok(
+            EvaluatedTask {
+                action: Arc::new(FetchAction {
+                    src:  src,
+                    dest: dest,
+                }),
+                with: Arc::new(PreLogicInput::template(&handle, &request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(&handle, &request, tm, &self.and)?),
+            }
+        );
+    }
+
+}
+
+impl IsAction for FetchAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+
+        match request.request_type {
+
+            TaskRequestType::Query => {
+                if handle.remote.get_mode(request, &self.src)?.is_none() {
+                    return Err(handle.response.is_failed(request, &format!("{} does not exist", self.src)));
+                }
+                if handle.remote.get_is_directory(request, &self.src)? {
+                    return Err(handle.response.is_failed(request, &format!("{} is a directory, only files can be fetched", self.src)));
+                }
+                if ! self.dest.exists() {
+                    return Ok(self.with_dest(handle, &handle.response.needs_creation(request)));
+                }
+                let local_512 = handle.local.get_sha512(request, &self.dest, false)?;
+                let remote_512 = handle.remote.get_sha512(request, &self.src)?;
+                if ! remote_512.eq(&local_512) {
+                    return Ok(self.with_dest(handle, &handle.response.needs_modification(request, &vec![Field::Content])));
+                }
+                return Ok(self.with_dest(handle, &handle.response.is_matched(request)));
+            },
+
+            TaskRequestType::Create => {
+                self.do_fetch(handle, request)?;
+                return Ok(self.with_dest(handle, &handle.response.is_created(request)));
+            },
+
+            TaskRequestType::Modify => {
+                self.do_fetch(handle, request)?;
+                return Ok(self.with_dest(handle, &handle.response.is_modified(request, request.changes.clone())));
+            },
+
+            _ => { return Err(handle.response.not_supported(request)); }
+
+        }
+    }
+
+}
+
+impl FetchAction {
+
+    // the file is fetched next to its final location and only moved into place once the checksum
+    // matches the remote file, so a failed transfer never replaces a good local copy
+
+    fn do_fetch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+        if let Some(parent) = self.dest.parent() {
+            if let Err(y) = std::fs::create_dir_all(parent) {
+                return Err(handle.response.is_failed(request, &format!("unable to create directory: {}, {:?}", parent.display(), y)));
+            }
+        }
+        let partial = get_partial_path(&self.dest);
+        handle.remote.fetch_file(request, &self.src, &partial)?;
+
+        let local_512 = sha512_bytes(&handle.local.read_bytes(request, &partial)?);
+        let remote_512 = handle.remote.get_sha512(request, &self.src)?;
+        if ! remote_512.eq(&local_512) {
+            let _ = std::fs::remove_file(&partial);
+            return Err(handle.response.is_failed(request, &format!("checksum mismatch after fetching {}", self.src)));
+        }
+        if let Err(y) = std::fs::rename(&partial, &self.dest) {
+            return Err(handle.response.is_failed(request, &format!("unable to move fetched file into place: {}, {:?}", self.dest.display(), y)));
+        }
+        return Ok(());
+    }
+
+    // where the file ended up is available to and/save as 'dest'
+
+    fn with_dest(&self, handle: &Arc<TaskHandle>, response: &Arc<TaskResponse>) -> Arc<TaskResponse> {
+        let mut mapping = serde_yaml::Mapping::new();
+        mapping.insert(serde_yaml::Value::String(String::from("dest")), serde_yaml::Value::String(self.dest.display().to_string()));
+        return handle.response.with_data(response, mapping);
+    }
+
+}
+
+// the local copy lives at dest/<host>/<src>.  src becomes part of the local path, where '..' would
+// climb out of the host's directory, so it is refused
+
+fn get_local_path(dest: &String, host_name: &String, src: &String) -> Result<PathBuf, String> {
+    if Path::new(src).components().any(|x| x == Component::ParentDir) {
+        return Err(format!("'..' is not allowed: {}", src));
+    }
+    let mut path = PathBuf::from(dest);
+    path.push(host_name);
+    path.push(src.trim_start_matches('/'));
+    return Ok(path);
+}
+
+fn get_partial_path(dest: &PathBuf) -> PathBuf {
+    return PathBuf::from(format!("{}.part", dest.display()));
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_local_path() {
+        let host = String::from("web1");
+        assert_eq!(get_local_path(&String::from("fetched"), &host, &String::from("/etc/hosts")), Ok(PathBuf::from("fetched/web1/etc/hosts")));
+        assert_eq!(get_local_path(&String::from("/srv/fetched/"), &host, &String::from("logs/app.log")), Ok(PathBuf::from("/srv/fetched/web1/logs/app.log")));
+        assert!(get_local_path(&String::from("fetched"), &host, &String::from("/etc/../../../root/.ssh/id_rsa")).is_err());
+        assert!(get_local_path(&String::from("fetched"), &host, &String::from("../x")).is_err());
+        // only whole '..' components are refused
+        assert!(get_local_path(&String::from("fetched"), &host, &String::from("/tmp/..x")).is_ok());
+    }
+
+    #[test]
+    fn test_partial_path() {
+        let dest = PathBuf::from("fetched/web1/etc/hosts");
+        let partial = get_partial_path(&dest);
+        assert_eq!(partial, PathBuf::from("fetched/web1/etc/hosts.part"));
+        assert_eq!(partial.parent(), dest.parent());
+    }
+
+}
@@ -0,0 +1,123 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::tasks::lines::{Anchor,EditInPlace,ensure_line};
+use regex::Regex;
+use serde::{Deserialize};
+use std::sync::Arc;
+
+const MODULE: &str = "lineinfile";
+
+// manages a single line in a file that is otherwise left alone, such as a setting in a config
+// file owned by a package.  See tasks/lines.rs for how the line is matched and placed.
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LineInFileTask {
+    pub name: Option<String>,
+    pub path: String,
+    pub line: Option<String>,
+    pub regex: Option<String>,
+    pub insert_after: Option<String>,
+    pub insert_before: Option<String>,
+    pub create: Option<String>,
+    pub backup: Option<String>,
+    pub remove: Option<String>,
+    pub attributes: Option<FileAttributesInput>,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+
+struct LineInFileAction {
+    pub file: EditInPlace,
+    pub line: Option<String>,
+    pub regex: Option<Regex>,
+    pub anchor: Anchor,
+}
+
+impl IsTask for LineInFileTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let remove = handle.template.boolean_option_default_false(&request, tm, &String::from("remove"), &self.remove)?;
+        // with templates off (the first pass over a task) remove is not known yet
+        if tm != TemplateMode::Off && ! remove && self.line.is_none() {
+            return Err(handle.response.is_failed(request, &String::from("line is required unless remove is set")));
+        }
+        if tm != TemplateMode::Off && remove && self.line.is_none() && self.regex.is_none() {
+            return Err(handle.response.is_failed(request, &String::from("remove requires line or regex")));
+        }
+        return Ok(
+            EvaluatedTask {
+                action: Arc::new(LineInFileAction {
+                    file: EditInPlace {
+                        path:       handle.template.path(&request, tm, &String::from("path"), &self.path)?,
+                        create:     handle.template.boolean_option_default_false(&request, tm, &String::from("create"), &self.create)?,
+                        backup:     handle.template.boolean_option_default_false(&request, tm, &String::from("backup"), &self.backup)?,
+                        remove:     remove,
+                        attributes: FileAttributesInput::template(&handle, &request, tm, &self.attributes)?
+                    },
+                    line:   handle.template.string_option_unsafe_for_shell(&request, tm, &String::from("line"), &self.line)?,
+                    regex:  template_regex(handle, request, tm, &String::from("regex"), &self.regex)?,
+                    anchor: template_anchor(handle, request, tm, &self.insert_after, &self.insert_before)?,
+                }),
+                with: Arc::new(PreLogicInput::template(&handle, &request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(&handle, &request, tm, &self.and)?),
+            }
+        );
+    }
+
+}
+
+impl IsAction for LineInFileAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+        return self.file.dispatch(handle, request, |content| {
+            Ok(ensure_line(content, &self.line, &self.regex, self.file.remove, &self.anchor))
+        });
+    }
+
+}
+
+// these are shared with the blockinfile module
+
+pub fn template_regex(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode, field: &String, input: &Option<String>) -> Result<Option<Regex>, Arc<TaskResponse>> {
+    return match handle.template.string_option_unsafe_for_shell(request, tm, field, input)? {
+        Some(x) => match Regex::new(&x) {
+            Ok(re) => Ok(Some(re)),
+            Err(y) => Err(handle.response.is_failed(request, &format!("field ({}): invalid regex: {}", field, y)))
+        },
+        None => Ok(None)
+    };
+}
+
+pub fn template_anchor(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode, insert_after: &Option<String>, insert_before: &Option<String>) -> Result<Anchor, Arc<TaskResponse>> {
+    if insert_after.is_some() && insert_before.is_some() {
+        return Err(handle.response.is_failed(request, &String::from("insert_after and insert_before cannot both be used")));
+    }
+    if let Some(re) = template_regex(handle, request, tm, &String::from("insert_after"), insert_after)? {
+        return Ok(Anchor::After(re));
+    }
+    if let Some(re) = template_regex(handle, request, tm, &String::from("insert_before"), insert_before)? {
+        return Ok(Anchor::Before(re));
+    }
+    return Ok(Anchor::End);
+}
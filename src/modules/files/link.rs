@@ -0,0 +1,240 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::tasks::fields::Field;
+use serde::{Deserialize};
+use std::sync::Arc;
+use std::vec::Vec;
+use crate::tasks::files::Recurse;
+
+const MODULE: &str = "link";
+
+// manages a symbolic link (or with hard set, a hard link) at path pointing at target.  A symbolic
+// link pointing somewhere else is repointed, while a regular file in the way is only replaced with force.
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LinkTask {
+    pub name: Option<String>,
+    pub path: String,
+    pub target: String,
+    pub hard: Option<String>,
+    pub force: Option<String>,
+    pub remove: Option<String>,
+    pub attributes: Option<FileAttributesInput>,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+struct LinkAction {
+    pub path: String,
+    pub target: String,
+    pub hard: bool,
+    pub force: bool,
+    pub remove: bool,
+    pub attributes: Option<FileAttributesEvaluated>,
+}
+
+impl IsTask for LinkTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let hard = handle.template.boolean_option_default_false(&request, tm, &String::from("hard"), &self.hard)?;
+        let target = handle.template.path(&request, tm, &String::from("target"), &self.target)?;
+        let attributes = FileAttributesInput::template(&handle, &request, tm, &self.attributes)?;
+        if tm != TemplateMode::Off {
+            // a relative hard link target would be relative to wherever the command runs, not to path
+            if hard && ! target.starts_with("/") {
+                return Err(handle.response.is_failed(request, &String::from("target must be an absolute path for a hard link")));
+            }
+            // chmod follows symbolic links, and a hard link shares its mode with the target anyway
+            if attributes.is_some() && attributes.as_ref().unwrap().mode.is_some() {
+                return Err(handle.response.is_failed(request, &String::from("mode cannot be set on a link, set it on the target")));
+            }
+        }
+        return Ok(
+            EvaluatedTask {
+                action: Arc::new(LinkAction {
+                    path:       handle.template.path(&request, tm, &String::from("path"), &self.path)?,
+                    target:     target,
+                    hard:       hard,
+                    force:      handle.template.boolean_option_default_false(&request, tm, &String::from("force"), &self.force)?,
+                    remove:     handle.template.boolean_option_default_false(&request, tm, &String::from("remove"), &self.remove)?,
+                    attributes: attributes
+                }),
+                with: Arc::new(PreLogicInput::template(&handle, &request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(&handle, &request, tm, &self.and)?),
+            }
+        );
+    }
+
+}
+
+impl IsAction for LinkAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+
+        match request.request_type {
+
+            TaskRequestType::Query => {
+                let mut changes : Vec<Field> = Vec::new();
+                // without -L, the mode and ownership checks look at a symbolic link itself
+                let remote_mode = handle.remote.query_common_file_attributes(request, &self.path, &self.attributes, &mut changes, Recurse::No)?;
+                if remote_mode.is_none() {
+                    if self.remove { return Ok(handle.response.is_matched(request)); }
+                    else           { return Ok(handle.response.needs_creation(request)); }
+                }
+                let is_link = self.is_link(handle, request)?;
+                if self.remove {
+                    if is_link { return Ok(handle.response.needs_removal(request)); }
+                    else       { return Err(handle.response.is_failed(request, &format!("{} is not a link to {}, not removing it", self.path, self.target))); }
+                }
+                if ! is_link {
+                    let symlink_target = handle.remote.get_link_target(request, &self.path)?;
+                    let is_directory = symlink_target.is_none() && handle.remote.get_is_directory(request, &self.path)?;
+                    if let Err(y) = self.check_replaceable(&symlink_target, is_directory) {
+                        return Err(handle.response.is_failed(request, &y));
+                    }
+                    changes.push(Field::Target);
+                }
+                if changes.is_empty() { return Ok(handle.response.is_matched(request)); }
+                else                  { return Ok(handle.response.needs_modification(request, &changes)); }
+            },
+
+            TaskRequestType::Create => {
+                handle.remote.create_link(request, &self.target, &self.path, ! self.hard, false)?;
+                self.process_attributes(handle, request, &Field::all_file_attributes())?;
+                return Ok(handle.response.is_created(request));
+            },
+
+            TaskRequestType::Modify => {
+                if request.changes.contains(&Field::Target) {
+                    handle.remote.create_link(request, &self.target, &self.path, ! self.hard, true)?;
+                    self.process_attributes(handle, request, &Field::all_file_attributes())?;
+                } else {
+                    self.process_attributes(handle, request, &request.changes)?;
+                }
+                return Ok(handle.response.is_modified(request, request.changes.clone()));
+            },
+
+            TaskRequestType::Remove => {
+                handle.remote.delete_file(request, &self.path)?;
+                return Ok(handle.response.is_removed(request))
+            }
+
+            // no passive or execute leg
+            _ => { return Err(handle.response.not_supported(request)); }
+
+        }
+    }
+}
+
+impl LinkAction {
+
+    // is the path already the link we want?
+
+    fn is_link(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<bool, Arc<TaskResponse>> {
+        if self.hard {
+            return handle.remote.get_is_same_file(request, &self.path, &self.target);
+        }
+        return Ok(self.points_at_target(&handle.remote.get_link_target(request, &self.path)?));
+    }
+
+    fn points_at_target(&self, symlink_target: &Option<String>) -> bool {
+        return match symlink_target {
+            Some(x) => x.eq(&self.target),
+            None => false
+        };
+    }
+
+    // something other than the wanted link is at path.  A symbolic link pointing elsewhere is always
+    // repointed, a directory is never replaced, and anything else needs force.
+
+    fn check_replaceable(&self, symlink_target: &Option<String>, is_directory: bool) -> Result<(), String> {
+        if symlink_target.is_none() && is_directory {
+            return Err(format!("{} is a directory", self.path));
+        }
+        if (self.hard || symlink_target.is_none()) && ! self.force {
+            return Err(format!("{} exists and is not a link to {}, set force to replace it", self.path, self.target));
+        }
+        return Ok(());
+    }
+
+    // a hard link is the target file, so the regular commands work for it, but a symbolic link
+    // needs the variants that do not follow the link
+
+    fn process_attributes(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, changes: &Vec<Field>) -> Result<(), Arc<TaskResponse>> {
+        if self.hard {
+            return handle.remote.process_common_file_attributes(request, &self.path, &self.attributes, changes, Recurse::No);
+        }
+        if let Some(attributes) = self.attributes.as_ref() {
+            if changes.contains(&Field::Owner) && attributes.owner.is_some() {
+                handle.remote.set_link_owner(request, &self.path, attributes.owner.as_ref().unwrap())?;
+            }
+            if changes.contains(&Field::Group) && attributes.group.is_some() {
+                handle.remote.set_link_group(request, &self.path, attributes.group.as_ref().unwrap())?;
+            }
+        }
+        return Ok(());
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn action(hard: bool, force: bool) -> LinkAction {
+        return LinkAction {
+            path: String::from("/opt/app/current"),
+            target: String::from("/opt/app/releases/2"),
+            hard: hard,
+            force: force,
+            remove: false,
+            attributes: None
+        };
+    }
+
+    #[test]
+    fn test_points_at_target() {
+        let link = action(false, false);
+        assert!(link.points_at_target(&Some(String::from("/opt/app/releases/2"))));
+        assert!(! link.points_at_target(&Some(String::from("/opt/app/releases/1"))));
+        assert!(! link.points_at_target(&None));
+    }
+
+    #[test]
+    fn test_check_replaceable() {
+        let elsewhere = Some(String::from("/opt/app/releases/1"));
+        // a symbolic link with the wrong target is repointed without force
+        assert!(action(false, false).check_replaceable(&elsewhere, false).is_ok());
+        // a regular file in the way needs force
+        assert!(action(false, false).check_replaceable(&None, false).is_err());
+        assert!(action(false, true).check_replaceable(&None, false).is_ok());
+        // a hard link replaces a symbolic link only with force
+        assert!(action(true, false).check_replaceable(&elsewhere, false).is_err());
+        assert!(action(true, true).check_replaceable(&elsewhere, false).is_ok());
+        // a directory is never replaced, even with force
+        assert!(action(false, true).check_replaceable(&None, true).is_err());
+        assert!(action(true, true).check_replaceable(&None, true).is_err());
+    }
+
+}
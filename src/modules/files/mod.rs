@@ -16,9 +16,16 @@
 
 /** ADD MODULES HERE, KEEP ALPHABETIZED **/
 
+pub mod archive;
+pub mod blockinfile;
 pub mod copy;
 pub mod directory;
+pub mod fetch;
 pub mod file;
 pub mod git;
+pub mod lineinfile;
+pub mod link;
 pub mod stat;
-pub mod template;
\ No newline at end of file
+pub mod sync;
+pub mod template;
+pub mod unarchive;
\ No newline at end of file
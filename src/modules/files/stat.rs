@@ -31,10 +31,8 @@ pub struct StatTask {
     pub and: Option<PostLogicInput>
 }
 
-#[allow(dead_code)]
 struct StatAction {
     pub path: String,
-    pub save: String,
 }
 
 impl IsTask for StatTask {
@@ -48,10 +46,10 @@ impl IsTask for StatTask {
             EvaluatedTask {
                 action: Arc::new(StatAction {
                     path: handle.template.path(&request, tm, &String::from("path"), &self.path)?,
-                    save: handle.template.string_no_spaces(&request, tm, &String::from("save"), &self.save)?,
                 }),
                 with: Arc::new(PreLogicInput::template(handle, request, tm, &self.with)?),
-                and: Arc::new(PostLogicInput::template(handle, request, tm, &self.and)?),
+                // the stat results are saved along with the status, see TaskResponse::to_saved_mapping
+                and: Arc::new(PostLogicInput::template_with_shorthand(handle, request, tm, &self.and, &Some(self.save.clone()), &None, &None)?),
             }
         );
     }
@@ -69,8 +67,7 @@ impl IsAction for StatAction {
 
             TaskRequestType::Passive => {
                 let stat = stat_file(handle, request, &self.path)?;
-                save_results(handle, request, &self.save, stat)?;
-                return Ok(handle.response.is_passive(request));
+                return Ok(handle.response.with_data(&handle.response.is_passive(request), stat_mapping(stat)));
             },
 
             _ => { return Err(handle.response.not_supported(request)); }
@@ -126,11 +123,10 @@ fn stat_file(handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, path: &String
     }
 }
 
-fn save_results(handle: &Arc<TaskHandle>, _request: &Arc<TaskRequest>, key: &String, stat: StatResult) -> Result<(), Arc<TaskResponse>> {
-    let mut result = serde_yaml::Mapping::new();
+fn stat_mapping(stat: StatResult) -> serde_yaml::Mapping {
     // the following statement really can't fail.
-    let value = serde_yaml::to_value(stat).expect("internal error: failed to unwrap stat");
-    result.insert(serde_yaml::Value::String(key.clone()), value);
-    handle.host.write().unwrap().update_variables(result);
-    Ok(())
-}
\ No newline at end of file
+    return match serde_yaml::to_value(stat).expect("internal error: failed to unwrap stat") {
+        serde_yaml::Value::Mapping(x) => x,
+        _ => panic!("internal error: stat did not serialize to a mapping")
+    };
+}
@@ -0,0 +1,381 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::tasks::fields::Field;
+use std::collections::HashSet;
+use std::path::{Path,PathBuf};
+use serde::{Deserialize};
+use std::sync::Arc;
+use std::vec::Vec;
+use crate::tasks::files::Recurse;
+
+const MODULE: &str = "sync";
+
+// mirrors a local directory tree to a remote path, transferring only the files whose checksums
+// differ.  With delete, remote files that are not in the local tree are removed as well.  Links to
+// files are transferred as regular files, links to directories are skipped.
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SyncTask {
+    pub name: Option<String>,
+    pub src: String,
+    pub dest: String,
+    pub delete: Option<String>,
+    pub attributes: Option<FileAttributesInput>,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+struct SyncAction {
+    pub src: PathBuf,
+    pub dest: String,
+    pub delete: bool,
+    pub attributes: Option<FileAttributesEvaluated>,
+}
+
+// what needs to happen to make the remote tree match, all paths are relative to src and dest
+
+struct SyncPlan {
+    pub directories: Vec<String>,
+    pub files: Vec<String>,
+    pub extra_directories: Vec<String>,
+    pub extra_files: Vec<String>,
+}
+
+impl IsTask for SyncTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let src = handle.template.string(&request, tm, &String::from("src"), &self.src)?;
+        return Ok(
+            EvaluatedTask {
+                action: Arc::new(SyncAction {
+                    src:        handle.template.find_directory_path(request, tm, &String::from("src"), &src)?,
+                    dest:       handle.template.path(&request, tm, &String::from("dest"), &self.dest)?,
+                    delete:     handle.template.boolean_option_default_false(&request, tm, &String::from("delete"), &self.delete)?,
+                    attributes: FileAttributesInput::template(&handle, &request, tm, &self.attributes)?
+                }),
+                with: Arc::new(PreLogicInput::template(&handle, &request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(&handle, &request, tm, &self.and)?),
+            }
+        );
+    }
+
+}
+
+impl IsAction for SyncAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+
+        match request.request_type {
+
+            TaskRequestType::Query => {
+                let mut changes : Vec<Field> = Vec::new();
+                // recursive attributes are always reported as changes, as with the directory module
+                let remote_mode = handle.remote.query_common_file_attributes(request, &self.dest, &self.attributes, &mut changes, Recurse::Yes)?;
+                if remote_mode.is_none() {
+                    let plan = self.plan(handle, request, false)?;
+                    self.report(handle, request, &plan);
+                    return Ok(handle.response.with_data(&handle.response.needs_creation(request), plan.to_mapping()));
+                }
+                if ! handle.remote.get_is_directory(request, &self.dest)? {
+                    return Err(handle.response.is_failed(request, &format!("{} is not a directory", self.dest)));
+                }
+                let plan = self.plan(handle, request, true)?;
+                if ! plan.is_empty() {
+                    changes.push(Field::Content);
+                }
+                if ! changes.is_empty() {
+                    self.report(handle, request, &plan);
+                    return Ok(handle.response.with_data(&handle.response.needs_modification(request, &changes), plan.to_mapping()));
+                }
+                return Ok(handle.response.with_data(&handle.response.is_matched(request), plan.to_mapping()));
+            },
+
+            TaskRequestType::Create => {
+                handle.remote.create_directory(request, &self.dest)?;
+                let plan = self.plan(handle, request, false)?;
+                self.apply(handle, request, &plan)?;
+                handle.remote.process_all_common_file_attributes(request, &self.dest, &self.attributes, Recurse::Yes)?;
+                return Ok(handle.response.with_data(&handle.response.is_created(request), plan.to_mapping()));
+            },
+
+            TaskRequestType::Modify => {
+                let plan = self.plan(handle, request, true)?;
+                self.apply(handle, request, &plan)?;
+                handle.remote.process_common_file_attributes(request, &self.dest, &self.attributes, &request.changes, Recurse::Yes)?;
+                return Ok(handle.response.with_data(&handle.response.is_modified(request, request.changes.clone()), plan.to_mapping()));
+            },
+
+            _ => { return Err(handle.response.not_supported(request)); }
+
+        }
+    }
+
+}
+
+impl SyncAction {
+
+    fn remote_path(&self, relative: &String) -> String {
+        return join_remote(&self.dest, relative);
+    }
+
+    // compares the local tree to the remote one, which is taken to be empty if it does not exist yet
+
+    fn plan(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, exists: bool) -> Result<SyncPlan, Arc<TaskResponse>> {
+        let local = match local_tree(&self.src) {
+            Ok(x) => x,
+            Err(y) => { return Err(handle.response.is_failed(request, &y)); }
+        };
+        let remote = match exists {
+            true => handle.remote.list_tree(request, &self.dest)?,
+            false => (Vec::new(), Vec::new())
+        };
+        let (mut plan, both) = match compare_trees(&self.dest, &local, &remote, self.delete) {
+            Ok(x) => x,
+            Err(y) => { return Err(handle.response.is_failed(request, &y)); }
+        };
+        for file in both.iter() {
+            let local_512 = handle.local.get_sha512(request, &self.src.join(file), true)?;
+            let remote_512 = handle.remote.get_sha512(request, &self.remote_path(file))?;
+            if ! remote_512.eq(&local_512) {
+                plan.files.push(file.clone());
+            }
+        }
+        plan.files.sort();
+        return Ok(plan);
+    }
+
+    fn apply(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, plan: &SyncPlan) -> Result<(), Arc<TaskResponse>> {
+        for file in plan.extra_files.iter() {
+            handle.remote.delete_file(request, &self.remote_path(file))?;
+        }
+        for directory in plan.extra_directories.iter() {
+            handle.remote.delete_directory(request, &self.remote_path(directory), Recurse::Yes)?;
+        }
+        for directory in plan.directories.iter() {
+            handle.remote.create_directory(request, &self.remote_path(directory))?;
+        }
+        for file in plan.files.iter() {
+            // attributes are applied to the whole tree afterwards
+            handle.remote.copy_file(request, &self.src.join(file), &self.remote_path(file), |_f| { Ok(()) })?;
+        }
+        return Ok(());
+    }
+
+    // in check mode nothing is transferred, so list what would be
+
+    fn report(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, plan: &SyncPlan) {
+        if ! handle.response.get_visitor().read().expect("read visitor").is_check_mode() {
+            return;
+        }
+        for file in plan.files.iter() {
+            handle.debug(request, &format!("would copy: {}", self.remote_path(file)));
+        }
+        for path in plan.extra_files.iter().chain(plan.extra_directories.iter()) {
+            handle.debug(request, &format!("would delete: {}", self.remote_path(path)));
+        }
+    }
+
+}
+
+impl SyncPlan {
+
+    fn is_empty(&self) -> bool {
+        return self.directories.is_empty() && self.files.is_empty() && self.extra_directories.is_empty() && self.extra_files.is_empty();
+    }
+
+    // the changed paths are available to and/save as 'copied' and 'deleted'
+
+    fn to_mapping(&self) -> serde_yaml::Mapping {
+        let to_sequence = |paths: Vec<&String>| -> serde_yaml::Value {
+            serde_yaml::Value::Sequence(paths.iter().map(|x| serde_yaml::Value::String(x.to_string())).collect())
+        };
+        let mut mapping = serde_yaml::Mapping::new();
+        mapping.insert(serde_yaml::Value::String(String::from("copied")), to_sequence(self.files.iter().collect()));
+        mapping.insert(serde_yaml::Value::String(String::from("deleted")), to_sequence(self.extra_files.iter().chain(self.extra_directories.iter()).collect()));
+        return mapping;
+    }
+
+}
+
+fn join_remote(dest: &String, relative: &String) -> String {
+    return format!("{}/{}", dest.trim_end_matches('/'), relative);
+}
+
+fn is_below_any(path: &String, directories: &Vec<String>) -> bool {
+    return directories.iter().any(|x| path.starts_with(&format!("{}/", x)));
+}
+
+// everything in the plan that can be worked out from the two trees of (directories, files) alone.  Files
+// that exist on both sides are returned separately, as only their checksums can tell if they differ.
+
+fn compare_trees(dest: &String, local: &(Vec<String>, Vec<String>), remote: &(Vec<String>, Vec<String>), delete: bool) -> Result<(SyncPlan, Vec<String>), String> {
+    let (local_directories, local_files) = local;
+    let (remote_directories, remote_files) = remote;
+    let remote_directory_set : HashSet<&String> = remote_directories.iter().collect();
+    let remote_file_set : HashSet<&String> = remote_files.iter().collect();
+
+    let mut plan = SyncPlan { directories: Vec::new(), files: Vec::new(), extra_directories: Vec::new(), extra_files: Vec::new() };
+    let mut both : Vec<String> = Vec::new();
+    for directory in local_directories.iter() {
+        if remote_file_set.contains(directory) {
+            return Err(format!("{} exists and is not a directory", join_remote(dest, directory)));
+        }
+        if ! remote_directory_set.contains(directory) {
+            plan.directories.push(directory.clone());
+        }
+    }
+    for file in local_files.iter() {
+        if remote_directory_set.contains(file) {
+            return Err(format!("{} is a directory", join_remote(dest, file)));
+        }
+        match remote_file_set.contains(file) {
+            true => both.push(file.clone()),
+            false => plan.files.push(file.clone())
+        }
+    }
+
+    if delete {
+        let local_directory_set : HashSet<&String> = local_directories.iter().collect();
+        let local_file_set : HashSet<&String> = local_files.iter().collect();
+        // removing a directory takes everything below it, so only the topmost ones are listed
+        for directory in remote_directories.iter() {
+            if ! local_directory_set.contains(directory) && ! is_below_any(directory, &plan.extra_directories) {
+                plan.extra_directories.push(directory.clone());
+            }
+        }
+        for file in remote_files.iter() {
+            if ! local_file_set.contains(file) && ! is_below_any(file, &plan.extra_directories) {
+                plan.extra_files.push(file.clone());
+            }
+        }
+    }
+    return Ok((plan, both));
+}
+
+// returns the (directories, files) below a local path, relative to it and sorted.  Links to files
+// are followed, so a linked file is transferred as a regular file.  Links to directories are skipped,
+// as following them could leave the tree or loop back into it.
+
+fn local_tree(root: &Path) -> Result<(Vec<String>, Vec<String>), String> {
+    let mut directories : Vec<String> = Vec::new();
+    let mut files : Vec<String> = Vec::new();
+    let mut pending : Vec<PathBuf> = vec![root.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let entries = match std::fs::read_dir(&current) {
+            Ok(x) => x,
+            Err(y) => { return Err(format!("unable to read directory: {}, {}", current.display(), y)); }
+        };
+        for entry in entries {
+            let path = match entry {
+                Ok(x) => x.path(),
+                Err(y) => { return Err(format!("unable to read directory: {}, {}", current.display(), y)); }
+            };
+            let relative = match path.strip_prefix(root) {
+                Ok(x) => x.to_string_lossy().to_string(),
+                Err(_) => { continue; }
+            };
+            let metadata = match std::fs::symlink_metadata(&path) {
+                Ok(x) => x,
+                Err(y) => { return Err(format!("unable to read: {}, {}", path.display(), y)); }
+            };
+            if metadata.is_dir() {
+                directories.push(relative);
+                pending.push(path);
+                continue;
+            }
+            if metadata.file_type().is_symlink() {
+                match std::fs::metadata(&path) {
+                    Ok(x) if x.is_dir() => { continue; },
+                    Ok(_) => {},
+                    Err(y) => { return Err(format!("broken link: {}, {}", path.display(), y)); }
+                }
+            }
+            files.push(relative);
+        }
+    }
+    directories.sort();
+    files.sort();
+    return Ok((directories, files));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_below_any() {
+        let directories = vec![String::from("a"), String::from("b/c")];
+        assert!(is_below_any(&String::from("a/x"), &directories));
+        assert!(is_below_any(&String::from("b/c/d/e"), &directories));
+        assert!(! is_below_any(&String::from("ab"), &directories));
+        assert!(! is_below_any(&String::from("b/x"), &directories));
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        return values.iter().map(|x| String::from(*x)).collect();
+    }
+
+    #[test]
+    fn test_local_tree() {
+        let root = std::env::temp_dir().join(format!("jetp-sync-test-{}", guid_create::GUID::rand().to_string()));
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        std::fs::create_dir_all(root.join("empty")).unwrap();
+        std::fs::write(root.join("top.txt"), "top").unwrap();
+        std::fs::write(root.join("a/b/deep.txt"), "deep").unwrap();
+        // a linked file is listed as a file, a link back up the tree is not followed
+        std::os::unix::fs::symlink(root.join("top.txt"), root.join("a/linked.txt")).unwrap();
+        std::os::unix::fs::symlink(&root, root.join("a/b/loop")).unwrap();
+
+        let (directories, files) = local_tree(&root).unwrap();
+        assert_eq!(directories, strings(&["a", "a/b", "empty"]));
+        assert_eq!(files, strings(&["a/b/deep.txt", "a/linked.txt", "top.txt"]));
+
+        std::os::unix::fs::symlink(root.join("missing"), root.join("broken")).unwrap();
+        assert!(local_tree(&root).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_compare_trees() {
+        let dest = String::from("/srv/site/");
+        let local = (strings(&["css", "img"]), strings(&["css/site.css", "img/logo.png", "index.html"]));
+        let remote = (strings(&["css", "old", "old/x"]), strings(&["css/site.css", "index.html", "old/x/y.txt", "stale.txt"]));
+
+        let (plan, both) = compare_trees(&dest, &local, &remote, false).unwrap();
+        assert_eq!(plan.directories, strings(&["img"]));
+        assert_eq!(plan.files, strings(&["img/logo.png"]));
+        assert_eq!(both, strings(&["css/site.css", "index.html"]));
+        assert!(plan.extra_directories.is_empty() && plan.extra_files.is_empty());
+
+        // only the topmost extra directory is removed, along with extra files outside it
+        let (plan, _) = compare_trees(&dest, &local, &remote, true).unwrap();
+        assert_eq!(plan.extra_directories, strings(&["old"]));
+        assert_eq!(plan.extra_files, strings(&["stale.txt"]));
+
+        // a file where a directory should be, or the other way around, is an error
+        let conflict = (strings(&["index.html"]), strings(&["css"]));
+        assert_eq!(compare_trees(&dest, &conflict, &remote, false).err(), Some(String::from("/srv/site/index.html exists and is not a directory")));
+        let conflict = (Vec::new(), strings(&["css"]));
+        assert_eq!(compare_trees(&dest, &conflict, &remote, false).err(), Some(String::from("/srv/site/css is a directory")));
+    }
+}
@@ -77,6 +77,9 @@ impl IsAction for TemplateAction {
                 let mut changes : Vec<Field> = Vec::new();
                 let remote_mode = handle.remote.query_common_file_attributes(request, &self.dest, &self.attributes, &mut changes, Recurse::No)?;                   
                 if remote_mode.is_none() {
+                    handle.remote.show_file_diff(request, &self.dest, &remote_mode, &self.attributes, &changes, || {
+                        Ok(self.do_template(handle, request, false, None)?.into_bytes())
+                    })?;
                     return Ok(handle.response.needs_creation(request));
                 }
                 let data = self.do_template(handle, request, false, None)?;
@@ -86,6 +89,7 @@ impl IsAction for TemplateAction {
                     changes.push(Field::Content); 
                 }
                 if ! changes.is_empty() {
+                    handle.remote.show_file_diff(request, &self.dest, &remote_mode, &self.attributes, &changes, || Ok(data.into_bytes()))?;
                     return Ok(handle.response.needs_modification(request, &changes));
                 }
                 return Ok(handle.response.is_matched(request));
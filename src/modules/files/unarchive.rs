@@ -0,0 +1,217 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::tasks::fields::Field;
+use crate::tasks::files::ArchiveFormat;
+use crate::tasks::checksum::sha512;
+use std::path::PathBuf;
+use serde::{Deserialize};
+use std::sync::Arc;
+
+const MODULE: &str = "unarchive";
+
+// extracts a .tar, .tar.gz, .tar.xz or .zip file into a directory.  The archive is transferred from
+// the control machine unless remote_src is set.  After extracting, the checksum of the archive is
+// recorded under ~/.jet/state on the host (never inside dest), and the archive is only extracted
+// again once the checksum changes, or never if the path in 'creates' exists.
+
+#[derive(Deserialize,Debug)]
+#[serde(deny_unknown_fields)]
+pub struct UnarchiveTask {
+    pub name: Option<String>,
+    pub src: String,
+    pub dest: String,
+    pub remote_src: Option<String>,
+    pub strip_components: Option<String>,
+    pub creates: Option<String>,
+    pub with: Option<PreLogicInput>,
+    pub and: Option<PostLogicInput>
+}
+struct UnarchiveAction {
+    pub src: String,
+    pub local_src: Option<PathBuf>,
+    pub dest: String,
+    pub format: ArchiveFormat,
+    pub strip_components: u64,
+    pub creates: Option<String>,
+}
+
+impl IsTask for UnarchiveTask {
+
+    fn get_module(&self) -> String { String::from(MODULE) }
+    fn get_name(&self) -> Option<String> { self.name.clone() }
+    fn get_with(&self) -> Option<PreLogicInput> { self.with.clone() }
+
+    fn evaluate(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, tm: TemplateMode) -> Result<EvaluatedTask, Arc<TaskResponse>> {
+        let remote_src = handle.template.boolean_option_default_false(&request, tm, &String::from("remote_src"), &self.remote_src)?;
+        let (src, local_src) = match remote_src {
+            true => (handle.template.path(&request, tm, &String::from("src"), &self.src)?, None),
+            false => {
+                let src = handle.template.string(&request, tm, &String::from("src"), &self.src)?;
+                let local_src = handle.template.find_file_path(request, tm, &String::from("src"), &src)?;
+                (local_src.display().to_string(), Some(local_src))
+            }
+        };
+        let format = match tm {
+            TemplateMode::Off => ArchiveFormat::Tar,
+            _ => match ArchiveFormat::from_path(&src) {
+                Ok(x) => x,
+                Err(y) => { return Err(handle.response.is_failed(request, &y)); }
+            }
+        };
+        let strip_components = handle.template.integer_option_to_integer(&request, tm, &String::from("strip_components"), &self.strip_components, 0)?;
+        if format == ArchiveFormat::Zip && strip_components > 0 {
+            return Err(handle.response.is_failed(request, &String::from("strip_components is not supported for zip files")));
+        }
+        return Ok(
+            EvaluatedTask {
+                action: Arc::new(UnarchiveAction {
+                    src:              src,
+                    local_src:        local_src,
+                    dest:             handle.template.path(&request, tm, &String::from("dest"), &self.dest)?,
+                    format:           format,
+                    strip_components: strip_components,
+                    creates: match &self.creates {
+                        Some(x) => Some(handle.template.path(&request, tm, &String::from("creates"), x)?),
+                        None => None
+                    },
+                }),
+                with: Arc::new(PreLogicInput::template(&handle, &request, tm, &self.with)?),
+                and: Arc::new(PostLogicInput::template(&handle, &request, tm, &self.and)?),
+            }
+        );
+    }
+
+}
+
+impl IsAction for UnarchiveAction {
+
+    fn dispatch(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<Arc<TaskResponse>, Arc<TaskResponse>> {
+
+        match request.request_type {
+
+            TaskRequestType::Query => {
+                if let Some(creates) = &self.creates {
+                    if handle.remote.get_mode(request, creates)?.is_some() {
+                        return Ok(handle.response.is_matched(request));
+                    }
+                }
+                let archive_512 = match &self.local_src {
+                    Some(path) => handle.local.get_sha512(request, path, true)?,
+                    None => {
+                        if handle.remote.get_mode(request, &self.src)?.is_none() {
+                            return Err(handle.response.is_failed(request, &format!("{} does not exist", self.src)));
+                        }
+                        handle.remote.get_sha512(request, &self.src)?
+                    }
+                };
+                if handle.remote.get_mode(request, &self.dest)?.is_none() {
+                    return Ok(handle.response.needs_creation(request));
+                }
+                if ! handle.remote.get_is_directory(request, &self.dest)? {
+                    return Err(handle.response.is_failed(request, &format!("{} is not a directory", self.dest)));
+                }
+                let marker = handle.remote.make_state_path(request, &get_marker_name(&self.src, &self.dest))?;
+                let extracted_512 = match handle.remote.get_mode(request, &marker)? {
+                    Some(_) => String::from_utf8_lossy(&handle.remote.read_file(request, &marker)?).trim().to_string(),
+                    None => String::new()
+                };
+                if ! extracted_512.eq(&archive_512) {
+                    return Ok(handle.response.needs_modification(request, &vec![Field::Content]));
+                }
+                return Ok(handle.response.is_matched(request));
+            },
+
+            TaskRequestType::Create => {
+                handle.remote.create_directory(request, &self.dest)?;
+                self.do_extract(handle, request)?;
+                return Ok(handle.response.is_created(request));
+            },
+
+            TaskRequestType::Modify => {
+                self.do_extract(handle, request)?;
+                return Ok(handle.response.is_modified(request, request.changes.clone()));
+            },
+
+            _ => { return Err(handle.response.not_supported(request)); }
+
+        }
+    }
+
+}
+
+impl UnarchiveAction {
+
+    // a local archive is transferred to a temporary file first.  The marker records the checksum of the
+    // archive as found on the host, so a damaged transfer is extracted again on the next run.
+
+    fn do_extract(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<(), Arc<TaskResponse>> {
+        let archive = match &self.local_src {
+            Some(path) => {
+                let (_temp_dir, temp_path) = handle.remote.get_transfer_location(request)?;
+                let temp = temp_path.as_ref().unwrap().display().to_string();
+                handle.remote.copy_file(request, path, &temp, |_f| { Ok(()) })?;
+                temp
+            },
+            None => self.src.clone()
+        };
+        let result = self.extract_and_mark(handle, request, &archive);
+        if self.local_src.is_some() {
+            match result.is_err() {
+                // a failed cleanup must not hide why the extraction failed
+                true  => { let _ = handle.remote.delete_file(request, &archive); },
+                false => { handle.remote.delete_file(request, &archive)?; }
+            }
+        }
+        return result;
+    }
+
+    fn extract_and_mark(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, archive: &String) -> Result<(), Arc<TaskResponse>> {
+        let archive_512 = handle.remote.get_sha512(request, archive)?;
+        handle.remote.extract_archive(request, self.format, archive, &self.dest, self.strip_components)?;
+        let marker = handle.remote.make_state_path(request, &get_marker_name(&self.src, &self.dest))?;
+        handle.remote.write_data(request, &format!("{}\n", archive_512), &marker, |_f| { Ok(()) })?;
+        return Ok(());
+    }
+
+}
+
+// the marker is named after both the archive and dest, so the same archive unpacked in two places, or two
+// archives with the same file name unpacked into one place, each get their own
+
+fn get_marker_name(src: &String, dest: &String) -> String {
+    let key = format!("{}\n{}", src, dest.trim_end_matches('/'));
+    return format!("unarchive-{}", &sha512(&key)[..32]);
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_marker_name() {
+        let a = get_marker_name(&String::from("/srv/a/app.tar.gz"), &String::from("/opt/app"));
+        assert_eq!(a, get_marker_name(&String::from("/srv/a/app.tar.gz"), &String::from("/opt/app/")));
+        assert!(a.starts_with("unarchive-"));
+        assert!(! a.contains('/'));
+        assert_ne!(a, get_marker_name(&String::from("/srv/b/app.tar.gz"), &String::from("/opt/app")));
+        assert_ne!(a, get_marker_name(&String::from("/srv/a/app.tar.gz"), &String::from("/opt/other")));
+    }
+
+}
@@ -22,14 +22,18 @@ use crate::inventory::hosts::Host;
 use std::sync::{Arc,RwLock};
 use crate::connection::cache::ConnectionCache;
 use crate::registry::list::Task;
-use crate::util::yaml::blend_variables;
+use crate::tasks::logic::PreLogicInput;
+use crate::util::yaml::{blend_variables,record_variable_sources,VariableLayer,LayerSources};
 use crate::playbooks::templar::{Templar,TemplateMode};
 use crate::cli::parser::CliParser;
 use crate::handle::template::BlendTarget;
 use std::ops::Deref;
 use std::env;
+use std::fs;
 use guid_create::GUID;
 use expanduser::expanduser;
+use crate::connection::ssh::{HostKeyChecking,host_key_checking_from_string};
+use crate::connection::ssh_config::{SshConfig,SshConfigHost};
 
 // the playbook traversal state, and a little bit more than that.
 // the playbook context keeps track of where we are in a playbook
@@ -50,6 +54,14 @@ pub struct PlaybookContext {
 
     pub task_count: usize,
     pub task: Option<String>,
+    pub module: Option<String>,
+    pub no_log: bool,
+    // 'with' sections of the blocks around the current task, outermost first
+    blocks: Vec<PreLogicInput>,
+    // 'vars' of the includes around the current task with the file they apply to, outermost first
+    include_vars: Vec<(String,serde_yaml::Mapping)>,
+    // the task files currently being included, outermost first, to catch files that include themselves
+    include_files: Vec<PathBuf>,
     
     seen_hosts:               HashMap<String, Arc<RwLock<Host>>>,
     targetted_hosts:          HashMap<String, Arc<RwLock<Host>>>,
@@ -70,16 +82,23 @@ pub struct PlaybookContext {
     pub failed_tasks:           usize,
     pub defaults_storage:       RwLock<serde_yaml::Mapping>,
     pub vars_storage:           RwLock<serde_yaml::Mapping>,
+    pub vars_sources:           RwLock<HashMap<String,String>>,
     pub role_defaults_storage:  RwLock<serde_yaml::Mapping>,
+    pub role_defaults_sources:  RwLock<HashMap<String,String>>,
     pub role_vars_storage:      RwLock<serde_yaml::Mapping>,
+    pub role_vars_sources:      RwLock<HashMap<String,String>>,
     pub env_storage:            RwLock<serde_yaml::Mapping>,
     
     pub connection_cache:     RwLock<ConnectionCache>,
     pub templar:              RwLock<Templar>,
 
     pub ssh_user:             String,
+    pub ssh_user_set:         bool,
     pub ssh_port:             i64,
+    pub ssh_port_set:         bool,
+    pub ssh_config:           Arc<SshConfig>,
     pub sudo:                 Option<String>,
+    pub host_key_checking:    HostKeyChecking,
     extra_vars:               serde_yaml::Value,
 
 }
@@ -95,6 +114,11 @@ impl PlaybookContext {
             play: None,
             role: None,
             task: None,
+            module: None,
+            no_log: false,
+            blocks: Vec::new(),
+            include_vars: Vec::new(),
+            include_files: Vec::new(),
             play_count : 0,
             role_count : 0,
             task_count : 0,
@@ -116,12 +140,19 @@ impl PlaybookContext {
             templar:                  RwLock::new(Templar::new()),
             defaults_storage:         RwLock::new(serde_yaml::Mapping::new()),
             vars_storage:             RwLock::new(serde_yaml::Mapping::new()),
+            vars_sources:             RwLock::new(HashMap::new()),
             role_vars_storage:        RwLock::new(serde_yaml::Mapping::new()),
+            role_vars_sources:        RwLock::new(HashMap::new()),
             role_defaults_storage:    RwLock::new(serde_yaml::Mapping::new()),
+            role_defaults_sources:    RwLock::new(HashMap::new()),
             env_storage:              RwLock::new(serde_yaml::Mapping::new()),
             ssh_user:                 parser.default_user.clone(),
+            ssh_user_set:             parser.user_set,
             ssh_port:                 parser.default_port,
+            ssh_port_set:             parser.port_set,
+            ssh_config:               Arc::clone(&parser.ssh_config),
             sudo:                     parser.sudo.clone(),
+            host_key_checking:        parser.host_key_checking,
             extra_vars:               parser.extra_vars.clone(),
         };
         s.load_environment();
@@ -145,10 +176,12 @@ impl PlaybookContext {
 
     pub fn set_ssh_user(&mut self, ssh_user: &String) {
         self.ssh_user = ssh_user.clone();
+        self.ssh_user_set = true;
     }
 
     pub fn set_ssh_port(&mut self, ssh_port: i64) {
         self.ssh_port = ssh_port;
+        self.ssh_port_set = true;
     }
 
     // used in traversal to tell the context what the current set of possible
@@ -182,6 +215,29 @@ impl PlaybookContext {
         self.failed_hosts.insert(hostname.clone(), Arc::clone(&host));
     }
 
+    // a block puts failed hosts back into the pool to run 'rescue' and 'always' tasks.
+    // failure counts are not changed, see forgive_failures.
+
+    pub fn restore_host(&mut self, host: &Arc<RwLock<Host>>) {
+        let hostname = host.read().unwrap().name.clone();
+        self.failed_hosts.remove(&hostname);
+        self.targetted_hosts.insert(hostname.clone(), Arc::clone(&host));
+    }
+
+    // a host whose failures were handled by a block 'rescue' goes back to the
+    // failure count it had before the block, so it does not fail the run.
+
+    pub fn forgive_failures(&mut self, hostname: &String, count: usize) {
+        match count {
+            0 => { self.failed_count_for_host.remove(hostname); },
+            _ => { self.failed_count_for_host.insert(hostname.clone(), count); }
+        }
+    }
+
+    pub fn get_failed_count_for_host(&self, hostname: &String) -> usize {
+        return *self.failed_count_for_host.get(hostname).unwrap_or(&0);
+    }
+
     pub fn set_playbook_path(&mut self, path: &PathBuf) {
         self.playbook_path = Some(path_as_string(&path));
         self.playbook_directory = Some(directory_as_string(&path));
@@ -189,6 +245,52 @@ impl PlaybookContext {
 
     pub fn set_task(&mut self, task: &Task) {
         self.task = Some(task.get_display_name());
+        self.module = Some(task.get_module());
+        self.no_log = match task.get_with() {
+            Some(with) => with.no_log.unwrap_or(false),
+            None => false
+        } || self.blocks.iter().any(|x| x.no_log.unwrap_or(false));
+    }
+
+    // blocks are entered and left by traversal, see modules/control/block.rs
+
+    pub fn push_block(&mut self, with: &Option<PreLogicInput>) {
+        self.blocks.push(with.clone().unwrap_or(PreLogicInput::empty()));
+    }
+
+    pub fn pop_block(&mut self) {
+        self.blocks.pop();
+    }
+
+    pub fn get_blocks(&self) -> Vec<PreLogicInput> {
+        return self.blocks.clone();
+    }
+
+    // includes are entered and left by traversal, see modules/control/include.rs
+
+    pub fn push_include_vars(&mut self, path: &String, vars: &Option<serde_yaml::Mapping>) {
+        self.include_vars.push((path.clone(), vars.clone().unwrap_or(serde_yaml::Mapping::new())));
+    }
+
+    pub fn pop_include_vars(&mut self) {
+        self.include_vars.pop();
+    }
+
+    // fails if the file is already being included further up, directly or through other files.
+    // Paths are compared once resolved, as the same file may be named in different ways.
+
+    pub fn push_include_file(&mut self, path: &PathBuf) -> Result<(), String> {
+        let resolved = fs::canonicalize(path).unwrap_or(path.clone());
+        if self.include_files.contains(&resolved) {
+            let chain : Vec<String> = self.include_files.iter().map(|x| x.display().to_string()).collect();
+            return Err(format!("include loop: {} -> {}", chain.join(" -> "), resolved.display()));
+        }
+        self.include_files.push(resolved);
+        return Ok(());
+    }
+
+    pub fn pop_include_file(&mut self) {
+        self.include_files.pop();
     }
 
     pub fn set_play(&mut self, play: &Play) {
@@ -206,11 +308,21 @@ impl PlaybookContext {
     pub fn set_role(&mut self, role: &Role, invocation: &RoleInvocation, role_path: &String) {
         self.role = Some(role.clone());
         self.role_path = Some(role_path.clone());
-        if role.defaults.is_some() { 
-             *self.role_defaults_storage.write().unwrap() = role.defaults.as_ref().unwrap().clone();
-        }
+        // a role may follow another role (or a dependency), so anything left over from it is replaced
+        *self.role_defaults_storage.write().unwrap() = role.defaults.clone().unwrap_or(serde_yaml::Mapping::new());
+        *self.role_defaults_sources.write().unwrap() = role.defaults_sources.clone();
+        // variables from the role's vars/ directory, overridden by those given where the role is used
+        let mut role_vars = serde_yaml::Value::Mapping(role.vars.clone().unwrap_or(serde_yaml::Mapping::new()));
+        let mut role_vars_sources = role.vars_sources.clone();
         if invocation.vars.is_some() { 
-            *self.role_vars_storage.write().unwrap() = invocation.vars.as_ref().unwrap().clone();
+            let invoked_from = invocation.source.clone().unwrap_or(self.playbook_path.clone().unwrap_or(String::from("playbook")));
+            record_variable_sources(&mut role_vars_sources, invocation.vars.as_ref().unwrap(), &invoked_from);
+            blend_variables(&mut role_vars, serde_yaml::Value::Mapping(invocation.vars.as_ref().unwrap().clone()));
+        }
+        *self.role_vars_sources.write().unwrap() = role_vars_sources;
+        match role_vars {
+            serde_yaml::Value::Mapping(x) => { *self.role_vars_storage.write().unwrap() = x },
+            _ => panic!("unexpected, blend_variables produced a non-mapping")
         }
     }
 
@@ -218,7 +330,9 @@ impl PlaybookContext {
         self.role = None;
         self.role_path = None;
         self.role_defaults_storage.write().unwrap().clear();
+        self.role_defaults_sources.write().unwrap().clear();
         self.role_vars_storage.write().unwrap().clear();
+        self.role_vars_sources.write().unwrap().clear();
     }
 
     // template functions need to access all the variables about a host taking variable precendence rules into effect
@@ -233,28 +347,55 @@ impl PlaybookContext {
     }
 
     pub fn get_complete_blended_variables_as_value(&self, host: &Arc<RwLock<Host>>, blend_target: BlendTarget) -> serde_yaml::Value  {
-        
         let mut blended = serde_yaml::Value::from(serde_yaml::Mapping::new());
+        self.visit_variable_layers(host, blend_target, &mut |_name, variables, _sources| {
+            blend_variables(&mut blended, serde_yaml::Value::Mapping(variables.clone()));
+        });
+        return blended;
+    }
+
+    // the same layers, labelled with where each variable came from, for show-vars
+
+    pub fn get_variable_layers(&self, host: &Arc<RwLock<Host>>, blend_target: BlendTarget) -> Vec<VariableLayer> {
+        let mut layers : Vec<VariableLayer> = Vec::new();
+        self.visit_variable_layers(host, blend_target, &mut |name, variables, sources| {
+            layers.push(VariableLayer::from_sources(&name, variables, &sources));
+        });
+        return layers;
+    }
+
+    // calls visit with every source of variables for a host, lowest precedence first.  This is the definition
+    // of variable precedence.  Blending happens for every template, so nothing is copied here.
+
+    fn visit_variable_layers<F>(&self, host: &Arc<RwLock<Host>>, blend_target: BlendTarget, visit: &mut F)
+        where F: FnMut(String, &serde_yaml::Mapping, LayerSources) {
+
+        let playbook = self.playbook_path.clone().unwrap_or(String::from("playbook"));
+
         let src1 = self.defaults_storage.read().unwrap();
-        let src1a = src1.deref();
-        blend_variables(&mut blended, serde_yaml::Value::Mapping(src1a.clone()));
-        
+        visit(String::from("play defaults"), src1.deref(), LayerSources::All(&playbook));
+
         let src1r = self.role_defaults_storage.read().unwrap();
-        let src1ar = src1r.deref();
-        blend_variables(&mut blended, serde_yaml::Value::Mapping(src1ar.clone()));
+        visit(String::from("role defaults"), src1r.deref(), LayerSources::PerVariable(self.role_defaults_sources.read().unwrap().deref()));
 
-        let src2 = host.read().unwrap().get_blended_variables();
-        blend_variables(&mut blended, serde_yaml::Value::Mapping(src2));
+        host.read().unwrap().visit_variable_layers(visit);
 
         let src3 = self.vars_storage.read().unwrap();
-        let src3a = src3.deref();
-        blend_variables(&mut blended, serde_yaml::Value::Mapping(src3a.clone()));
+        visit(String::from("play vars"), src3.deref(), LayerSources::PerVariable(self.vars_sources.read().unwrap().deref()));
 
         let src3r = self.role_vars_storage.read().unwrap();
-        let src3ar = src3r.deref();
-        blend_variables(&mut blended, serde_yaml::Value::Mapping(src3ar.clone()));
+        visit(String::from("role vars"), src3r.deref(), LayerSources::PerVariable(self.role_vars_sources.read().unwrap().deref()));
 
-        blend_variables(&mut blended, self.extra_vars.clone());
+        for (path, vars) in self.include_vars.iter() {
+            visit(String::from("include vars"), vars, LayerSources::All(path));
+        }
+
+        match &self.extra_vars {
+            serde_yaml::Value::Mapping(x) => {
+                visit(String::from("extra vars"), x, LayerSources::All(&String::from("--extra-vars")));
+            },
+            _ => {}
+        }
 
         match blend_target {
             BlendTarget::NotTemplateModule => { },
@@ -262,11 +403,9 @@ impl PlaybookContext {
                 // for security reasons env vars from security tools like 'op run' are only exposed to the template module
                 // to prevent accidental leakage into logs and history
                 let src4 = self.env_storage.read().unwrap();
-                let src4a = src4.deref();
-                blend_variables(&mut blended, serde_yaml::Value::Mapping(src4a.clone()));
+                visit(String::from("environment"), src4.deref(), LayerSources::All(&String::from("ENV_* environment variables")));
             }
         };
-        return blended;
     }
 
     // template code is not used here directly, but in handle/template.rs, which passes back through here, since
@@ -306,35 +445,52 @@ impl PlaybookContext {
         let vars = self.get_complete_blended_variables(host,BlendTarget::NotTemplateModule);
         let host2 = host.read().unwrap();
 
+        // ~/.ssh/config (or --ssh-config) sits beneath the inventory variables, and also beneath any user or port
+        // given explicitly on the command line or in the play, but is preferred over the built-in defaults
+        let config = self.get_ssh_config_for_host(&host2);
+
+        let default_hostname = match &config.hostname {
+            Some(x) => x.clone(),
+            None => host2.name.clone()
+        };
+        let default_user = match (self.ssh_user_set, &config.user) {
+            (false, Some(x)) => x.clone(),
+            _ => self.ssh_user.clone()
+        };
+        let default_port = match (self.ssh_port_set, config.port) {
+            (false, Some(x)) => x,
+            _ => self.ssh_port
+        };
+
         let remote_hostname = match vars.contains_key(&String::from("jet_ssh_hostname")) {
             true => match vars.get(&String::from("jet_ssh_hostname")).unwrap().as_str() {
                 Some(x) => String::from(x),
-                None => host2.name.clone()
+                None => default_hostname
             },
-            false => host2.name.clone()
+            false => default_hostname
         };
         let remote_user = match vars.contains_key(&String::from("jet_ssh_user")) {
             true => match vars.get(&String::from("jet_ssh_user")).unwrap().as_str() {
                 Some(x) => String::from(x),
-                None => self.ssh_user.clone()
+                None => default_user
             },
-            false => self.ssh_user.clone()
+            false => default_user
         };
         let remote_port = match vars.contains_key(&String::from("jet_ssh_port")) {
             true => match vars.get(&String::from("jet_ssh_port")).unwrap().as_str() {
                 Some(x) => {
                     match x.parse::<i64>() {
                         Ok(ix) => ix,
-                        Err(_) => self.ssh_port
+                        Err(_) => default_port
                     }
                 },
                 None => match vars.get(&String::from("jet_ssh_port")).unwrap().as_i64() {
                     Some(x) => x,
-                    None => self.ssh_port
+                    None => default_port
                 }
             },
             false => {
-                self.ssh_port
+                default_port
             }
         };
         let keyfile : Option<String> = match vars.contains_key(&String::from("jet_ssh_private_key_file")) {
@@ -374,6 +530,70 @@ impl PlaybookContext {
         return (remote_hostname, remote_user, remote_port, keyfile, passphrase, key_comment)
     } 
 
+    // IdentityFile and IdentitiesOnly from the SSH config are used when no key is set with jet_ssh_private_key_file,
+    // see SshConnection::connect for how these combine with the SSH agent
+
+    pub fn get_ssh_identity_files(&self, host: &Arc<RwLock<Host>>) -> (Vec<String>, bool) {
+        let config = self.get_ssh_config_for_host(&host.read().unwrap());
+        return (config.identity_files, config.identities_only);
+    }
+
+    fn get_ssh_config_for_host(&self, host: &Host) -> SshConfigHost {
+        return self.ssh_config.lookup(&host.name, &self.ssh_user);
+    }
+
+    // jet_ssh_proxy_jump names one or more bastions (comma separated, like OpenSSH's ProxyJump) to go through
+
+    pub fn get_ssh_proxy_jump(&self, host: &Arc<RwLock<Host>>) -> Option<String> {
+        let vars = self.get_complete_blended_variables(host,BlendTarget::NotTemplateModule);
+        return match vars.get(&String::from("jet_ssh_proxy_jump")) {
+            Some(x) => match x.as_str() {
+                Some(y) if !y.trim().is_empty() => Some(String::from(y.trim())),
+                _ => None
+            },
+            None => None
+        };
+    }
+
+    // host key verification settings can be set per host (or group) with jet_ssh_host_key_checking and
+    // jet_ssh_known_hosts_file, otherwise the CLI default is used along with ~/.ssh/known_hosts
+
+    pub fn get_ssh_host_key_details(&self, host: &Arc<RwLock<Host>>) -> (HostKeyChecking, PathBuf) {
+
+        let vars = self.get_complete_blended_variables(host,BlendTarget::NotTemplateModule);
+
+        let host_key_checking = match vars.contains_key(&String::from("jet_ssh_host_key_checking")) {
+            true => match vars.get(&String::from("jet_ssh_host_key_checking")).unwrap().as_str() {
+                Some(x) => match host_key_checking_from_string(&String::from(x)) {
+                    Ok(policy) => policy,
+                    // an invalid setting should not quietly weaken checking
+                    Err(_) => HostKeyChecking::Strict
+                },
+                None => match vars.get(&String::from("jet_ssh_host_key_checking")).unwrap().as_bool() {
+                    Some(false) => HostKeyChecking::Off,
+                    _ => HostKeyChecking::Strict
+                }
+            },
+            false => self.host_key_checking
+        };
+        let default_known_hosts = match expanduser("~/.ssh/known_hosts") {
+            Ok(expanded) => expanded,
+            Err(_) => PathBuf::from(".ssh/known_hosts")
+        };
+        let known_hosts : PathBuf = match vars.contains_key(&String::from("jet_ssh_known_hosts_file")) {
+            true => match vars.get(&String::from("jet_ssh_known_hosts_file")).unwrap().as_str() {
+                Some(x) => match expanduser(String::from(x)) {
+                    Ok(expanded) => expanded,
+                    Err(_) => PathBuf::from(x)
+                },
+                None => default_known_hosts
+            },
+            false => default_known_hosts
+        };
+
+        return (host_key_checking, known_hosts);
+    }
+
     // loads environment variables into the context, adding an "ENV_foo" prefix
     // to each environment variable "foo". These variables will only be made available
     // to the template module since we use them for secret management features.
@@ -548,4 +768,50 @@ impl PlaybookContext {
     
 
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(yaml: &str) -> serde_yaml::Mapping {
+        return serde_yaml::from_str(yaml).unwrap();
+    }
+
+    #[test]
+    fn test_include_vars_layering() {
+        let mut context = PlaybookContext::new(&CliParser::new());
+        let host = Arc::new(RwLock::new(Host::new(&String::from("web1"))));
+        host.write().unwrap().set_variables(mapping("a: host\nb: host\nc: host\nd: host"));
+        *context.vars_storage.write().unwrap() = mapping("b: play\nc: play\nd: play");
+
+        context.push_include_vars(&String::from("outer.yml"), &Some(mapping("c: outer\nd: outer")));
+        context.push_include_vars(&String::from("inner.yml"), &Some(mapping("d: inner")));
+        let vars = context.get_complete_blended_variables(&host, BlendTarget::NotTemplateModule);
+        assert_eq!(vars, mapping("a: host\nb: play\nc: outer\nd: inner"));
+
+        context.pop_include_vars();
+        let vars = context.get_complete_blended_variables(&host, BlendTarget::NotTemplateModule);
+        assert_eq!(vars, mapping("a: host\nb: play\nc: outer\nd: outer"));
+
+        context.pop_include_vars();
+        let vars = context.get_complete_blended_variables(&host, BlendTarget::NotTemplateModule);
+        assert_eq!(vars, mapping("a: host\nb: play\nc: play\nd: play"));
+    }
+
+    #[test]
+    fn test_include_loop() {
+        let mut context = PlaybookContext::new(&CliParser::new());
+        let a = PathBuf::from("/nonexistent/a.yml");
+        let b = PathBuf::from("/nonexistent/b.yml");
+        assert!(context.push_include_file(&a).is_ok());
+        assert!(context.push_include_file(&b).is_ok());
+        assert_eq!(context.push_include_file(&a), Err(String::from("include loop: /nonexistent/a.yml -> /nonexistent/b.yml -> /nonexistent/a.yml")));
+
+        // the same file may be included again once the first include is done
+        context.pop_include_file();
+        context.pop_include_file();
+        assert!(context.push_include_file(&a).is_ok());
+        assert!(context.push_include_file(&b).is_ok());
+    }
+}
@@ -15,6 +15,7 @@
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use serde::{Deserialize};
+use std::collections::HashMap;
 use crate::registry::list::Task;
 
 // all the playbook language YAML structures!
@@ -43,15 +44,29 @@ pub struct Role {
     pub name: String,
     pub defaults: Option<serde_yaml::Mapping>,
     pub tasks: Option<Vec<String>>,
-    pub handlers: Option<Vec<String>>
+    pub handlers: Option<Vec<String>>,
+    // roles that run before this one.  Each runs once per play unless it sets allow_duplicates.
+    pub dependencies: Option<Vec<RoleInvocation>>,
+    pub allow_duplicates: Option<bool>,
+    // loaded from the vars/ directory of the role, see find_role
+    #[serde(skip)]
+    pub vars: Option<serde_yaml::Mapping>,
+    // the file each variable in vars and defaults came from, for show-vars
+    #[serde(skip)]
+    pub vars_sources: HashMap<String,String>,
+    #[serde(skip)]
+    pub defaults_sources: HashMap<String,String>
 }
 
-#[derive(Debug,Deserialize)]
+#[derive(Debug,Deserialize,Clone)]
 #[serde(deny_unknown_fields)]
 pub struct RoleInvocation {
     pub role: String,
     pub vars: Option<serde_yaml::Mapping>,
-    pub tags: Option<Vec<String>>
+    pub tags: Option<Vec<String>>,
+    // the role.yml of the role that depends on this one, None if invoked from the playbook
+    #[serde(skip)]
+    pub source: Option<String>
 }
 
 // for Task/module definitions see registry/list.rs
@@ -17,6 +17,7 @@
 pub mod language;
 pub mod context;
 pub mod visitor;
+pub mod output;
 pub mod traversal;
 pub mod templar;
 pub mod task_fsm;
@@ -0,0 +1,330 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::playbooks::context::PlaybookContext;
+use crate::playbooks::visitor::{PlaybookVisitor,RunSummary,redact};
+use crate::playbooks::traversal::HandlerMode;
+use crate::connection::command::CommandResult;
+use crate::inventory::hosts::Host;
+use crate::tasks::*;
+use std::sync::{Arc,RwLock};
+use inline_colorization::{color_red,color_blue,color_green,color_cyan,color_reset,color_yellow};
+
+// the default colored terminal output, selected with --output human
+
+pub struct HumanVisitor {}
+
+impl HumanVisitor {
+
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    fn banner(&self) {
+        println!("----------------------------------------------------------");
+    }
+
+}
+
+impl PlaybookVisitor for HumanVisitor {
+
+    fn debug_host(&self, host: &Arc<RwLock<Host>>, message: &String) {
+        println!("{color_cyan}  ..... {} : {}{color_reset}", host.read().unwrap().name, message);
+    }
+
+    fn on_playbook_start(&self, context: &Arc<RwLock<PlaybookContext>>) {
+        let ctx = context.read().unwrap();
+        let path = ctx.playbook_path.as_ref().unwrap();
+        self.banner();
+        println!("> playbook start: {}", path);
+    }
+
+    fn on_play_start(&self, context: &Arc<RwLock<PlaybookContext>>) {
+        let play = &context.read().unwrap().play;
+        self.banner();
+        println!("> play: {}", play.as_ref().unwrap());
+    }
+
+    fn on_play_stop(&self, context: &Arc<RwLock<PlaybookContext>>, failed: bool) {
+        // failed occurs if *ALL* hosts in a play have failed
+        let ctx = context.read().unwrap();
+        let play_name = ctx.get_play_name();
+        if ! failed {
+            self.banner();
+            println!("> play complete: {}", play_name);
+        } else {
+            self.banner();
+            println!("{color_red}> play failed: {}{color_reset}", play_name);
+
+        }
+    }
+
+    fn on_exit(&self, context: &Arc<RwLock<PlaybookContext>>) {
+        println!("----------------------------------------------------------");
+        println!("");
+        self.show_playbook_summary(context);
+    }
+
+    fn on_task_start(&self, context: &Arc<RwLock<PlaybookContext>>, is_handler: HandlerMode) {
+        let context2 = context.read().unwrap();
+        let task = context2.task.as_ref().unwrap();
+        let role = &context2.role;
+
+        let what = match is_handler {
+            HandlerMode::NormalTasks => String::from("task"),
+            HandlerMode::Handlers    => String::from("handler")
+        };
+
+        self.banner();
+        if role.is_none() {
+            println!("> begin {}: {}", what, task);
+        }
+        else {
+            println!("> ({}) begin {}: {}", role.as_ref().unwrap().name, what, task);
+        }
+    }
+
+    fn on_batch(&self, batch_num: usize, batch_count: usize, batch_size: usize) {
+        self.banner();
+        println!("> batch {}/{}, {} hosts", batch_num+1, batch_count, batch_size);
+    }
+
+    fn on_host_task_start(&self, _context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>) {
+        let host2 = host.read().unwrap();
+        println!("… {} => running", host2.name);
+    }
+
+    fn on_notify_handler(&self, host: &Arc<RwLock<Host>>, which_handler: &String) {
+        let host2 = host.read().unwrap();
+        println!("… {} => notified: {}", host2.name, which_handler);
+    }
+
+    fn on_host_delegate(&self, host: &Arc<RwLock<Host>>, delegated: &String) {
+        let host2 = host.read().unwrap();
+        println!("{color_blue}✓ {} => delegating to: {}{color_reset}",  &host2.name, delegated.clone());
+    }
+
+    fn on_host_task_ok(&self, _context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        let host2 = host.read().unwrap();
+        match &task_response.status {
+            TaskStatus::IsCreated  =>  {
+                println!("{color_blue}✓ {} => created{color_reset}",  &host2.name);
+            },
+            TaskStatus::IsRemoved  =>  {
+                println!("{color_blue}✓ {} => removed{color_reset}",  &host2.name);
+            },
+            TaskStatus::IsModified =>  {
+                let changes2 : Vec<String> = task_response.changes.iter().map(|x| { format!("{:?}", x) }).collect();
+                let change_str = changes2.join(",");
+                println!("{color_blue}✓ {} => modified ({}){color_reset}", &host2.name, change_str);
+            },
+            TaskStatus::IsExecuted =>  {
+                println!("{color_blue}✓ {} => complete{color_reset}", &host2.name);
+            },
+            TaskStatus::IsPassive  =>  {
+                // println!("{color_green}! host: {} => ok (no effect) {color_reset}", &host2.name);
+            }
+            TaskStatus::IsMatched  =>  {
+                println!("{color_green}✓ {} => matched {color_reset}", &host2.name);
+            }
+            TaskStatus::IsSkipped  =>  {
+                println!("{color_yellow}✓ {} => skipped {color_reset}", &host2.name);
+            }
+            TaskStatus::Failed => {
+                println!("{color_yellow}✓ {} => failed (ignored){color_reset}", &host2.name);
+            }
+            _ => {}
+        }
+    }
+
+    // the check mode version of on_host_task_ok - different possible states, slightly different output
+
+    fn on_host_task_check_ok(&self, _context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        let host2 = host.read().unwrap();
+        match &task_response.status {
+            TaskStatus::NeedsCreation  =>  {
+                println!("{color_blue}✓ {} => would create{color_reset}",  &host2.name);
+            },
+            TaskStatus::NeedsRemoval  =>  {
+                println!("{color_blue}✓ {} => would remove{color_reset}",  &host2.name);
+            },
+            TaskStatus::NeedsModification =>  {
+                let changes2 : Vec<String> = task_response.changes.iter().map(|x| { format!("{:?}", x) }).collect();
+                let change_str = changes2.join(",");
+                println!("{color_blue}✓ {} => would modify ({}) {color_reset}", &host2.name, change_str);
+            },
+            TaskStatus::NeedsExecution =>  {
+                println!("{color_blue}✓ {} => would run{color_reset}", &host2.name);
+            },
+            TaskStatus::IsPassive  =>  {}
+            TaskStatus::IsMatched  =>  {
+                println!("{color_green}✓ {} => matched {color_reset}", &host2.name);
+            }
+            TaskStatus::IsSkipped  =>  {
+                println!("{color_yellow}✓ {} => skipped {color_reset}", &host2.name);
+            }
+            TaskStatus::Failed => {
+                println!("{color_yellow}✓ {} => failed (ignored){color_reset}", &host2.name);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_host_task_retry(&self, context: &Arc<RwLock<PlaybookContext>>,host: &Arc<RwLock<Host>>, retries: u64, delay: u64, reason: &String) {
+        let host2 = host.read().unwrap();
+        let reason = redact(context, reason);
+        println!("{color_blue}! {} => retrying ({} retries left) in {} seconds: {}{color_reset}",host2.name,retries,delay,reason);
+    }
+
+    fn on_host_task_failed(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        let host2 = host.read().unwrap();
+        if task_response.msg.is_some() {
+            let msg = &task_response.msg;
+            if task_response.command_result.is_some() {
+                let cmd_result = task_response.command_result.as_ref().as_ref().unwrap();
+                let cmd = redact(context, &cmd_result.cmd);
+                let out = redact(context, &cmd_result.out);
+                let reason = redact(context, msg.as_ref().unwrap());
+                let _lock = context.write().unwrap();
+                println!("{color_red}! {} => failed", host2.name);
+                // failed_when and until explain why a command that ran is a failure
+                if ! reason.eq("command failed") {
+                    println!("    msg: {}", reason);
+                }
+                println!("    cmd: {}", cmd);
+                println!("    out: {}", out);
+                println!("    rc: {}{color_reset}", cmd_result.rc);
+            } else {
+                println!("{color_red}! error: {}: {}{color_reset}", host2.name, redact(context, msg.as_ref().unwrap()));
+            }
+        } else {
+            println!("{color_red}! host failed: {}, {color_reset}", host2.name);
+        }
+    }
+
+    fn on_host_connect_failed(&self, _context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, msg: &String) {
+        let host2 = host.read().unwrap();
+        println!("{color_red}! connection failed to host: {}: {}{color_reset}", host2.name, msg);
+    }
+
+    fn on_before_transfer(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, path: &String) {
+        let host2 = host.read().unwrap();
+        if context.read().unwrap().verbosity > 0 {
+            println!("{color_blue}! {} => transferring to: {}", host2.name, &path.clone());
+        }
+    }
+
+    fn on_command_run(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, cmd: &String) {
+        let host2 = host.read().unwrap();
+        if context.read().unwrap().verbosity > 0 {
+            println!("{color_blue}! {} => exec: {}", host2.name, redact(context, cmd));
+        }
+    }
+
+    fn on_command_ok(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, result: &Arc<Option<CommandResult>>,) {
+        let host2 = host.read().unwrap();
+        let cmd_result = result.as_ref().as_ref().expect("missing command result");
+        if context.read().unwrap().verbosity > 2 {
+            let cmd = redact(context, &cmd_result.cmd);
+            let out = redact(context, &cmd_result.out);
+            let _ctx2 = context.write().unwrap(); // lock for multi-line output
+            println!("{color_blue}! {} ... command ok", host2.name);
+            println!("    cmd: {}", cmd);
+            println!("    out: {}", out);
+            println!("    rc: {}{color_reset}", cmd_result.rc);
+        }
+    }
+
+    fn on_command_failed(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, result: &Arc<Option<CommandResult>>,) {
+        let host2 = host.read().expect("context read");
+        let cmd_result = result.as_ref().as_ref().expect("missing command result");
+        if context.read().unwrap().verbosity > 2 {
+            let cmd = redact(context, &cmd_result.cmd);
+            let out = redact(context, &cmd_result.out);
+            let _ctx2 = context.write().unwrap(); // lock for multi-line output
+            println!("{color_red}! {} ... command failed", host2.name);
+            println!("    cmd: {}", cmd);
+            println!("    out: {}", out);
+            println!("    rc: {}{color_reset}", cmd_result.rc);
+        }
+    }
+
+    fn on_host_diff(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, path: &String, diff: &String) {
+        let host2 = host.read().unwrap();
+        let diff = redact(context, diff);
+        let _ctx2 = context.write().unwrap(); // lock for multi-line output
+        println!("{color_cyan}~ {} => diff: {}{color_reset}", host2.name, path);
+        for line in diff.lines() {
+            if line.starts_with("+++") || line.starts_with("---") || line.starts_with("@@") {
+                println!("{color_cyan}    {}{color_reset}", line);
+            } else if line.starts_with("+") {
+                println!("{color_green}    {}{color_reset}", line);
+            } else if line.starts_with("-") {
+                println!("{color_red}    {}{color_reset}", line);
+            } else {
+                println!("    {}", line);
+            }
+        }
+    }
+
+    fn show_playbook_summary(&self, context: &Arc<RwLock<PlaybookContext>>) {
+
+        let s = RunSummary::new(context);
+
+        let summary = match s.failed_hosts {
+            0 => match s.adjusted_hosts {
+                0 => String::from(format!("{color_green}(✓) Perfect. All hosts matched policy.{color_reset}")),
+                _ => String::from(format!("{color_blue}(✓) Actions were applied.{color_reset}")),
+            },
+            _ => String::from(format!("{color_red}(X) Failures have occured.{color_reset}")),
+        };
+
+        let mode_table = format!("|:-|:-|:-|\n\
+                          | Results | Items | Hosts \n\
+                          | --- | --- | --- |\n\
+                          | Roles | {} | |\n\
+                          | Tasks | {} | {}|\n\
+                          | --- | --- | --- |\n\
+                          | Matched | {} | {}\n\
+                          | Created | {} | {}\n\
+                          | Modified | {} | {}\n\
+                          | Removed | {} | {}\n\
+                          | Executed | {} | {}\n\
+                          | Passive | {} | {}\n\
+                          | Skipped | {} | {}\n\
+                          | --- | --- | ---\n\
+                          | Unchanged | {} | {}\n\
+                          | Changed | {} | {}\n\
+                          | Failed | {} | {}\n\
+                          |-|-|-",
+                          s.role_ct, s.task_ct, s.seen_hosts,
+                          s.matched_ct, s.matched_hosts,
+                          s.created_ct, s.created_hosts,
+                          s.modified_ct, s.modified_hosts,
+                          s.removed_ct, s.removed_hosts,
+                          s.executed_ct, s.executed_hosts,
+                          s.passive_ct, s.passive_hosts,
+                          s.skipped_ct, s.skipped_hosts,
+                          s.unchanged_ct, s.unchanged_hosts,
+                          s.adjusted_ct, s.adjusted_hosts,
+                          s.failed_ct, s.failed_hosts);
+
+        crate::util::terminal::markdown_print(&mode_table);
+        println!("{}", format!("\n{summary}"));
+        println!("");
+    }
+
+}
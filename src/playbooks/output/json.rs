@@ -0,0 +1,293 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::playbooks::context::PlaybookContext;
+use crate::playbooks::visitor::{PlaybookVisitor,RunSummary,redact};
+use crate::playbooks::traversal::HandlerMode;
+use crate::inventory::hosts::Host;
+use crate::tasks::*;
+use crate::util::secrets::mask_secrets;
+use std::sync::{Arc,RwLock,Mutex};
+use std::io::Write;
+use serde_json::json;
+use guid_create::GUID;
+use chrono::prelude::*;
+
+// one JSON object per line for every event.  This is both --output json, which
+// writes to the terminal or a file, and the logfile, which is always written.
+
+pub struct JsonVisitor {
+    out: Mutex<Box<dyn Write + Send>>,
+    run_id: String,
+    utc_start: DateTime<Utc>
+}
+
+pub struct LogData {
+    pub event: String,
+    pub play: Option<String>,
+    pub playbook_path: Option<String>,
+    pub role: Option<String>,
+    pub task: Option<String>,
+    pub task_ct: Option<usize>,
+    pub cmd: Option<String>,
+    pub cmd_rc: Option<i32>,
+    pub cmd_out: Option<String>,
+    pub task_status: Option<String>,
+    pub host: Option<String>,
+    pub msg: Option<String>,
+    pub summary: Option<serde_json::map::Map<String,serde_json::Value>>
+}
+
+impl JsonVisitor {
+
+    pub fn new(out: Box<dyn Write + Send>) -> Self {
+        Self {
+            out: Mutex::new(out),
+            utc_start: Utc::now(),
+            run_id: GUID::rand().to_string()
+        }
+    }
+
+    pub fn log_entry(&self, event: &String, context: Arc<RwLock<PlaybookContext>>) -> LogData {
+        let ctx = context.read().unwrap();
+        LogData {
+            event: event.clone(),
+            play: ctx.play.clone(),
+            playbook_path: ctx.playbook_path.clone(),
+            role: match &ctx.role {
+                Some(x) => Some(x.name.clone()),
+                None => None
+            },
+            task: match &ctx.task {
+                Some(x) => Some(x.clone()),
+                None => None
+            },
+            task_ct: match &ctx.task {
+                Some(_) => Some(ctx.task_count),
+                None => None
+            },
+            cmd: None,
+            cmd_rc: None,
+            cmd_out: None,
+            task_status: None,
+            host: None,
+            msg: None,
+            summary: None
+        }
+    }
+
+    pub fn log(&self, log: &LogData) {
+
+        let now = Utc::now();
+
+        let mut obj =  serde_json::map::Map::new();
+        obj.insert(String::from("event"), json!(log.event.clone()));
+        obj.insert(String::from("run"), json!(self.run_id));
+        obj.insert(String::from("now"), json!(now.to_rfc2822()));
+        obj.insert(String::from("start"), json!(self.utc_start.to_rfc2822()));
+        let elapsed = now - self.utc_start;
+        obj.insert(String::from("elapsed"),     json!(elapsed.num_seconds()));
+
+        if log.play.is_some()        { obj.insert(String::from("playbook"),    json!(log.playbook_path.clone().unwrap())); }
+        if log.play.is_some()        { obj.insert(String::from("play"),        json!(log.play.clone().unwrap()));          }
+        if log.role.is_some()        { obj.insert(String::from("role"),        json!(log.role.clone().unwrap()));          }
+        if log.task.is_some()        { obj.insert(String::from("task"),        json!(log.task.clone().unwrap()));          }
+        if log.task.is_some()        { obj.insert(String::from("task_ct"),     json!(log.task_ct.clone().unwrap()));       }
+        if log.cmd.is_some()         { obj.insert(String::from("cmd"),         json!(mask_secrets(log.cmd.as_ref().unwrap()))); }
+        if log.cmd_rc.is_some()      { obj.insert(String::from("cmd_rc"),      json!(log.cmd_rc.clone().unwrap()));        }
+        if log.cmd_out.is_some()     { obj.insert(String::from("cmd_out"),     json!(mask_secrets(log.cmd_out.as_ref().unwrap()))); }
+        if log.task_status.is_some() { obj.insert(String::from("task_status"), json!(log.task_status.clone().unwrap()));   }
+        if log.host.is_some()        { obj.insert(String::from("host"),        json!(log.host.clone().unwrap()));          }
+        if log.msg.is_some()         { obj.insert(String::from("msg"),         json!(mask_secrets(log.msg.as_ref().unwrap()))); }
+
+        if log.summary.is_some()     { obj.insert(String::from("summary"),     json!(log.summary.clone().unwrap()));       }
+
+        match serde_json::to_string(&obj) {
+            Ok(json_str) => {
+                let mut f = self.out.lock().unwrap();
+                match writeln!(f, "{}",  json_str) {
+                    Ok(_) => {},
+                    Err(_e) => { }
+                }
+                let _ = f.flush();
+            },
+            Err(_y) => {}
+        }
+
+    }
+
+    fn log_event(&self, event: &str, context: &Arc<RwLock<PlaybookContext>>) {
+        let log_entry = self.log_entry(&String::from(event), Arc::clone(context));
+        self.log(&log_entry);
+    }
+
+    fn log_task_status(&self, event: &str, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        let mut log_entry = self.log_entry(&String::from(event), Arc::clone(context));
+        log_entry.host = Some(host.read().unwrap().name.clone());
+        log_entry.task_status = Some(format!("{:?}", &task_response.status));
+        self.log(&log_entry);
+    }
+
+}
+
+impl PlaybookVisitor for JsonVisitor {
+
+    fn on_playbook_start(&self, context: &Arc<RwLock<PlaybookContext>>) {
+        self.log_event("PLAYBOOK_START", context);
+    }
+
+    fn on_play_start(&self, context: &Arc<RwLock<PlaybookContext>>) {
+        self.log_event("PLAY_START", context);
+    }
+
+    fn on_role_start(&self, context: &Arc<RwLock<PlaybookContext>>) {
+        self.log_event("ROLE_START", context);
+    }
+
+    fn on_task_start(&self, context: &Arc<RwLock<PlaybookContext>>, _is_handler: HandlerMode) {
+        self.log_event("TASK_START", context);
+    }
+
+    fn on_host_task_ok(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        self.log_task_status("TASK_STATUS", context, task_response, host);
+    }
+
+    fn on_host_task_check_ok(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        self.log_task_status("TASK_CHECK_STATUS", context, task_response, host);
+    }
+
+    fn on_host_task_failed(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        let mut log_entry = self.log_entry(&String::from("TASK_FAILED"), Arc::clone(context));
+        if task_response.msg.is_some() && task_response.command_result.is_some() {
+            let cmd_result = task_response.command_result.as_ref().as_ref().unwrap();
+            log_entry.cmd     = Some(redact(context, &cmd_result.cmd));
+            log_entry.cmd_out = Some(redact(context, &cmd_result.out));
+            log_entry.cmd_rc  = Some(cmd_result.rc.clone());
+        }
+        log_entry.host = Some(host.read().unwrap().name.clone());
+        log_entry.task_status = Some(format!("{:?}", &task_response.status));
+        self.log(&log_entry);
+    }
+
+    fn on_host_diff(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, path: &String, diff: &String) {
+        let mut log_entry = self.log_entry(&String::from("TASK_DIFF"), Arc::clone(context));
+        log_entry.host = Some(host.read().unwrap().name.clone());
+        log_entry.msg = Some(format!("{}\n{}", path, redact(context, diff)));
+        self.log(&log_entry);
+    }
+
+    fn on_host_connect_failed(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, msg: &String) {
+        let mut log_entry = self.log_entry(&String::from("HOST_CONNECT_FAILED"), Arc::clone(context));
+        log_entry.host = Some(host.read().unwrap().name.clone());
+        log_entry.msg = Some(msg.clone());
+        self.log(&log_entry);
+    }
+
+    fn show_playbook_summary(&self, context: &Arc<RwLock<PlaybookContext>>) {
+        let s = RunSummary::new(context);
+        let mut log_entry = self.log_entry(&String::from("SUMMARY"), Arc::clone(context));
+        let mut map : serde_json::map::Map<String,serde_json::Value> = serde_json::map::Map::new();
+        map.insert(String::from("matched_ct"),      json!(s.matched_ct));
+        map.insert(String::from("matched_hosts"),   json!(s.matched_hosts));
+        map.insert(String::from("created_ct"),      json!(s.created_ct));
+        map.insert(String::from("created_hosts"),   json!(s.created_hosts));
+        map.insert(String::from("modified_ct"),     json!(s.modified_ct));
+        map.insert(String::from("modified_hosts"),  json!(s.modified_hosts));
+        map.insert(String::from("executed_ct"),     json!(s.executed_ct));
+        map.insert(String::from("executed_hosts"),  json!(s.executed_hosts));
+        map.insert(String::from("passive_ct"),      json!(s.passive_ct));
+        map.insert(String::from("passive_hosts"),   json!(s.passive_hosts));
+        map.insert(String::from("skipped_ct"),      json!(s.skipped_ct));
+        map.insert(String::from("skipped_hosts"),   json!(s.skipped_hosts));
+        map.insert(String::from("unchanged_ct"),    json!(s.unchanged_ct));
+        map.insert(String::from("unchanged_hosts"), json!(s.unchanged_hosts));
+        map.insert(String::from("adjusted_ct"),     json!(s.adjusted_ct));
+        map.insert(String::from("adjusted_hosts"),  json!(s.adjusted_hosts));
+        map.insert(String::from("failed_ct"),       json!(s.failed_ct));
+        map.insert(String::from("failed_hosts"),    json!(s.failed_hosts));
+        log_entry.summary = Some(map);
+        self.log(&log_entry);
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::secrets::register_secret;
+
+    // collects what the visitor writes so it can be read back
+    #[derive(Clone)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> { self.0.lock().unwrap().write(data) }
+        fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+    }
+
+    fn empty_log(event: &str) -> LogData {
+        return LogData { event: String::from(event), play: None, playbook_path: None, role: None, task: None, task_ct: None,
+            cmd: None, cmd_rc: None, cmd_out: None, task_status: None, host: None, msg: None, summary: None };
+    }
+
+    #[test]
+    fn test_log_shape() {
+        register_secret(&String::from("json-test-password"));
+        let buffer = Buffer(Arc::new(Mutex::new(Vec::new())));
+        let visitor = JsonVisitor::new(Box::new(buffer.clone()));
+
+        visitor.log(&empty_log("PLAYBOOK_START"));
+        let mut failed = empty_log("TASK_FAILED");
+        failed.play = Some(String::from("web"));
+        failed.playbook_path = Some(String::from("site.yml"));
+        failed.role = Some(String::from("nginx"));
+        failed.task = Some(String::from("install"));
+        failed.task_ct = Some(3);
+        failed.cmd = Some(String::from("login --password json-test-password"));
+        failed.cmd_rc = Some(1);
+        failed.cmd_out = Some(String::from("denied"));
+        failed.task_status = Some(String::from("Failed"));
+        failed.host = Some(String::from("web1"));
+        visitor.log(&failed);
+
+        // one object per line
+        let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines : Vec<serde_json::Value> = text.lines().map(|x| serde_json::from_str(x).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+
+        // every event has the same run id and timing fields, and only the fields that were set
+        let keys = |value: &serde_json::Value| { let mut k : Vec<String> = value.as_object().unwrap().keys().cloned().collect(); k.sort(); k };
+        assert_eq!(keys(&lines[0]), vec!["elapsed", "event", "now", "run", "start"]);
+        assert_eq!(keys(&lines[1]), vec!["cmd", "cmd_out", "cmd_rc", "elapsed", "event", "host", "now", "play", "playbook", "role", "run", "start", "task", "task_ct", "task_status"]);
+        assert_eq!(lines[0]["run"], lines[1]["run"]);
+        assert_eq!(lines[0]["event"], json!("PLAYBOOK_START"));
+
+        let event = &lines[1];
+        assert_eq!(event["event"], json!("TASK_FAILED"));
+        assert_eq!(event["playbook"], json!("site.yml"));
+        assert_eq!(event["play"], json!("web"));
+        assert_eq!(event["role"], json!("nginx"));
+        assert_eq!(event["task"], json!("install"));
+        assert_eq!(event["task_ct"], json!(3));
+        assert_eq!(event["cmd_rc"], json!(1));
+        assert_eq!(event["cmd_out"], json!("denied"));
+        assert_eq!(event["task_status"], json!("Failed"));
+        assert_eq!(event["host"], json!("web1"));
+        // secrets are masked in commands, output and messages
+        assert!(! event["cmd"].as_str().unwrap().contains("json-test-password"));
+        assert!(event["cmd"].as_str().unwrap().starts_with("login --password "));
+    }
+}
@@ -0,0 +1,261 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::playbooks::context::PlaybookContext;
+use crate::playbooks::visitor::{PlaybookVisitor,redact};
+use crate::playbooks::traversal::HandlerMode;
+use crate::playbooks::output::describe_status;
+use crate::inventory::hosts::Host;
+use crate::tasks::*;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc,RwLock,Mutex};
+use std::time::Instant;
+
+// a JUnit XML report for CI systems, selected with --output junit or junit=FILE.
+// Each play is a test suite, and each task on each host is a test case.  The report
+// is written when the run finishes.
+
+pub struct JunitVisitor {
+    path: Option<PathBuf>,
+    suites: Mutex<Vec<JunitSuite>>,
+    task_name: Mutex<String>,
+    started: Mutex<HashMap<String,Instant>>
+}
+
+struct JunitSuite {
+    name: String,
+    cases: Vec<JunitCase>
+}
+
+struct JunitCase {
+    host: String,
+    name: String,
+    seconds: f64,
+    outcome: JunitOutcome
+}
+
+enum JunitOutcome {
+    Passed(String),
+    Skipped,
+    Failed(String, String)
+}
+
+impl JunitVisitor {
+
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path: path,
+            suites: Mutex::new(Vec::new()),
+            task_name: Mutex::new(String::new()),
+            started: Mutex::new(HashMap::new())
+        }
+    }
+
+    fn add_case(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, name: String, outcome: JunitOutcome) {
+        let host_name = host.read().unwrap().name.clone();
+        let seconds = match self.started.lock().unwrap().remove(&host_name) {
+            Some(x) => x.elapsed().as_secs_f64(),
+            None => 0.0
+        };
+        let mut suites = self.suites.lock().unwrap();
+        if suites.is_empty() {
+            suites.push(JunitSuite { name: context.read().unwrap().get_play_name(), cases: Vec::new() });
+        }
+        suites.last_mut().unwrap().cases.push(JunitCase {
+            host: host_name,
+            name: name,
+            seconds: seconds,
+            outcome: outcome
+        });
+    }
+
+    fn task_result(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        let outcome = match task_response.status {
+            TaskStatus::IsSkipped => JunitOutcome::Skipped,
+            TaskStatus::Failed => JunitOutcome::Passed(String::from("failed (ignored)")),
+            _ => JunitOutcome::Passed(describe_status(task_response))
+        };
+        let name = self.task_name.lock().unwrap().clone();
+        self.add_case(context, host, name, outcome);
+    }
+
+    fn to_xml(&self) -> String {
+        let suites = self.suites.lock().unwrap();
+        let mut total_tests = 0;
+        let mut total_failures = 0;
+        let mut body = String::new();
+        for suite in suites.iter() {
+            let failures = suite.cases.iter().filter(|x| matches!(x.outcome, JunitOutcome::Failed(_,_))).count();
+            let skipped = suite.cases.iter().filter(|x| matches!(x.outcome, JunitOutcome::Skipped)).count();
+            // summing no f64s gives -0.0, which would show as time="-0.000"
+            let seconds : f64 = suite.cases.iter().fold(0.0, |total, x| total + x.seconds);
+            total_tests = total_tests + suite.cases.len();
+            total_failures = total_failures + failures;
+            body.push_str(&format!("  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\" time=\"{:.3}\">\n",
+                xml_escape(&suite.name), suite.cases.len(), failures, skipped, seconds));
+            for case in suite.cases.iter() {
+                body.push_str(&format!("    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                    xml_escape(&case.host), xml_escape(&case.name), case.seconds));
+                match &case.outcome {
+                    JunitOutcome::Passed(status) => {
+                        body.push_str(&format!(">\n      <system-out>{}</system-out>\n    </testcase>\n", xml_escape(status)));
+                    },
+                    JunitOutcome::Skipped => {
+                        body.push_str(">\n      <skipped/>\n    </testcase>\n");
+                    },
+                    JunitOutcome::Failed(message, output) => {
+                        body.push_str(&format!(">\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n", xml_escape(message), xml_escape(output)));
+                    }
+                }
+            }
+            body.push_str("  </testsuite>\n");
+        }
+        return format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites name=\"jetp\" tests=\"{}\" failures=\"{}\">\n{}</testsuites>\n",
+            total_tests, total_failures, body);
+    }
+
+}
+
+impl PlaybookVisitor for JunitVisitor {
+
+    fn on_play_start(&self, context: &Arc<RwLock<PlaybookContext>>) {
+        let name = context.read().unwrap().get_play_name();
+        self.suites.lock().unwrap().push(JunitSuite { name: name, cases: Vec::new() });
+    }
+
+    fn on_task_start(&self, context: &Arc<RwLock<PlaybookContext>>, is_handler: HandlerMode) {
+        let ctx = context.read().unwrap();
+        let mut name = ctx.task.clone().unwrap_or(String::from("?"));
+        if is_handler == HandlerMode::Handlers {
+            name = format!("handler: {}", name);
+        }
+        if let Some(role) = &ctx.role {
+            name = format!("({}) {}", role.name, name);
+        }
+        *self.task_name.lock().unwrap() = name;
+    }
+
+    fn on_host_task_start(&self, _context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>) {
+        let host_name = host.read().unwrap().name.clone();
+        self.started.lock().unwrap().insert(host_name, Instant::now());
+    }
+
+    fn on_host_task_ok(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        self.task_result(context, task_response, host);
+    }
+
+    fn on_host_task_check_ok(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        self.task_result(context, task_response, host);
+    }
+
+    fn on_host_task_failed(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        let (message, output) = match (&task_response.msg, task_response.command_result.as_ref()) {
+            (_, Some(cmd_result)) => (
+                format!("command failed with rc {}", cmd_result.rc),
+                format!("cmd: {}\nout: {}", redact(context, &cmd_result.cmd), redact(context, &cmd_result.out))
+            ),
+            (Some(msg), None) => (redact(context, msg), String::new()),
+            (None, None) => (String::from("failed"), String::new())
+        };
+        let name = self.task_name.lock().unwrap().clone();
+        self.add_case(context, host, name, JunitOutcome::Failed(message, output));
+    }
+
+    fn on_host_connect_failed(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, msg: &String) {
+        self.add_case(context, host, String::from("connect"), JunitOutcome::Failed(msg.clone(), String::new()));
+    }
+
+    fn on_error(&self, context: &Arc<RwLock<PlaybookContext>>, _msg: &String) {
+        // CI should still see which hosts failed when the run stops early
+        self.on_exit(context);
+    }
+
+    fn on_exit(&self, _context: &Arc<RwLock<PlaybookContext>>) {
+        let xml = self.to_xml();
+        match &self.path {
+            Some(path) => match std::fs::write(path, xml) {
+                Ok(_) => {},
+                Err(y) => { println!("unable to write JUnit report to {}: {}", path.display(), y); }
+            },
+            None => { print!("{}", xml); }
+        }
+    }
+
+}
+
+fn xml_escape(text: &String) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&'  => result.push_str("&amp;"),
+            '<'  => result.push_str("&lt;"),
+            '>'  => result.push_str("&gt;"),
+            '"'  => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            // control characters other than whitespace are not allowed in XML 1.0
+            x if x.is_control() && x != '\n' && x != '\t' && x != '\r' => {},
+            x => result.push(x)
+        }
+    }
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(host: &str, name: &str, outcome: JunitOutcome) -> JunitCase {
+        return JunitCase { host: String::from(host), name: String::from(name), seconds: 0.5, outcome: outcome };
+    }
+
+    #[test]
+    fn test_to_xml() {
+        let visitor = JunitVisitor::new(None);
+        visitor.suites.lock().unwrap().push(JunitSuite { name: String::from("web & db"), cases: vec![
+            case("web1", "install", JunitOutcome::Passed(String::from("modified (Content)"))),
+            case("web1", "restart", JunitOutcome::Skipped),
+            case("web2", "install", JunitOutcome::Failed(String::from("command failed with rc 1"), String::from("cmd: make <all>\nout: oops"))),
+        ]});
+        visitor.suites.lock().unwrap().push(JunitSuite { name: String::from("empty"), cases: Vec::new() });
+        assert_eq!(visitor.to_xml(), String::from(
+"<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<testsuites name=\"jetp\" tests=\"3\" failures=\"1\">
+  <testsuite name=\"web &amp; db\" tests=\"3\" failures=\"1\" errors=\"0\" skipped=\"1\" time=\"1.500\">
+    <testcase classname=\"web1\" name=\"install\" time=\"0.500\">
+      <system-out>modified (Content)</system-out>
+    </testcase>
+    <testcase classname=\"web1\" name=\"restart\" time=\"0.500\">
+      <skipped/>
+    </testcase>
+    <testcase classname=\"web2\" name=\"install\" time=\"0.500\">
+      <failure message=\"command failed with rc 1\">cmd: make &lt;all&gt;
+out: oops</failure>
+    </testcase>
+  </testsuite>
+  <testsuite name=\"empty\" tests=\"0\" failures=\"0\" errors=\"0\" skipped=\"0\" time=\"0.000\">
+  </testsuite>
+</testsuites>
+"));
+    }
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(xml_escape(&String::from("a < b && c > \"d\" 'e'")), String::from("a &lt; b &amp;&amp; c &gt; &quot;d&quot; &apos;e&apos;"));
+        // control characters are dropped, but line breaks and tabs are kept
+        assert_eq!(xml_escape(&String::from("red\u{1b}[31m\u{0}text\tline\r\n")), String::from("red[31mtext\tline\r\n"));
+    }
+}
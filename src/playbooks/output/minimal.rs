@@ -0,0 +1,100 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::playbooks::context::PlaybookContext;
+use crate::playbooks::visitor::{PlaybookVisitor,RunSummary,redact};
+use crate::playbooks::output::describe_status;
+use crate::inventory::hosts::Host;
+use crate::tasks::*;
+use std::sync::{Arc,RwLock};
+use inline_colorization::{color_red,color_blue,color_green,color_cyan,color_reset,color_yellow};
+
+// terse output selected with --output minimal: one line per host for each task,
+// and a one line summary at the end
+
+pub struct MinimalVisitor {}
+
+impl MinimalVisitor {
+
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    fn task_result(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        let task = context.read().unwrap().task.clone().unwrap_or(String::from("?"));
+        let color = match task_response.status {
+            TaskStatus::IsPassive | TaskStatus::IsMatched | TaskStatus::NeedsPassive => color_green,
+            TaskStatus::IsSkipped | TaskStatus::Failed => color_yellow,
+            _ => color_blue
+        };
+        let mut status = describe_status(task_response);
+        if task_response.status == TaskStatus::Failed {
+            status.push_str(" (ignored)");
+        }
+        println!("{color}{} | {} | {}{color_reset}", host.read().unwrap().name, task, status);
+    }
+
+}
+
+impl PlaybookVisitor for MinimalVisitor {
+
+    fn debug_host(&self, host: &Arc<RwLock<Host>>, message: &String) {
+        println!("{color_cyan}{} | {}{color_reset}", host.read().unwrap().name, message);
+    }
+
+    fn on_host_task_ok(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        self.task_result(context, task_response, host);
+    }
+
+    fn on_host_task_check_ok(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        self.task_result(context, task_response, host);
+    }
+
+    fn on_host_task_failed(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        let task = context.read().unwrap().task.clone().unwrap_or(String::from("?"));
+        let detail = match (&task_response.msg, task_response.command_result.as_ref()) {
+            (Some(_), Some(cmd_result)) => format!("rc={} {}", cmd_result.rc, redact(context, &cmd_result.out)),
+            (Some(msg), None) => redact(context, msg),
+            (None, _) => String::from("")
+        };
+        // keep the one line promise even for multi-line command output
+        let detail = detail.trim().replace("\n", " \\n ");
+        println!("{color_red}{} | {} | FAILED | {}{color_reset}", host.read().unwrap().name, task, detail);
+    }
+
+    fn on_host_diff(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, path: &String, diff: &String) {
+        let diff = redact(context, diff);
+        let _ctx2 = context.write().unwrap(); // lock for multi-line output
+        println!("{color_cyan}{} | diff | {}{color_reset}", host.read().unwrap().name, path);
+        print!("{}", diff);
+    }
+
+    fn on_host_connect_failed(&self, _context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, msg: &String) {
+        println!("{color_red}{} | connect | FAILED | {}{color_reset}", host.read().unwrap().name, msg);
+    }
+
+    fn show_playbook_summary(&self, context: &Arc<RwLock<PlaybookContext>>) {
+        let s = RunSummary::new(context);
+        let color = match (s.failed_hosts, s.adjusted_hosts) {
+            (0,0) => color_green,
+            (0,_) => color_blue,
+            (_,_) => color_red
+        };
+        println!("{color}hosts={} tasks={} unchanged={} changed={} skipped={} failed={}{color_reset}",
+            s.seen_hosts, s.task_ct, s.unchanged_ct, s.adjusted_ct, s.skipped_ct, s.failed_ct);
+    }
+
+}
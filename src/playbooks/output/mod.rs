@@ -0,0 +1,65 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// output formats selected with --output, each implements PlaybookVisitor
+
+pub mod human;
+pub mod json;
+pub mod junit;
+pub mod minimal;
+pub mod report;
+
+use crate::tasks::*;
+
+// a short description of a task result, such as "modified (Content,Mode)"
+
+pub fn describe_status(task_response: &TaskResponse) -> String {
+    let changes : Vec<String> = task_response.changes.iter().map(|x| { format!("{:?}", x) }).collect();
+    return match task_response.status {
+        TaskStatus::IsCreated         => String::from("created"),
+        TaskStatus::IsRemoved         => String::from("removed"),
+        TaskStatus::IsModified        => format!("modified ({})", changes.join(",")),
+        TaskStatus::IsExecuted        => String::from("complete"),
+        TaskStatus::IsPassive         => String::from("ok"),
+        TaskStatus::IsMatched         => String::from("matched"),
+        TaskStatus::IsSkipped         => String::from("skipped"),
+        TaskStatus::NeedsCreation     => String::from("would create"),
+        TaskStatus::NeedsRemoval      => String::from("would remove"),
+        TaskStatus::NeedsModification => format!("would modify ({})", changes.join(",")),
+        TaskStatus::NeedsExecution    => String::from("would run"),
+        TaskStatus::NeedsPassive      => String::from("ok"),
+        TaskStatus::Failed            => String::from("failed")
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn response(status: TaskStatus, changes: Vec<Field>) -> TaskResponse {
+        return TaskResponse { status: status, changes: changes, msg: None, command_result: Arc::new(None), with: Arc::new(None), and: Arc::new(None), data: None };
+    }
+
+    #[test]
+    fn test_describe_status() {
+        assert_eq!(describe_status(&response(TaskStatus::IsModified, vec![Field::Content, Field::Mode])), String::from("modified (Content,Mode)"));
+        assert_eq!(describe_status(&response(TaskStatus::NeedsModification, vec![Field::Owner])), String::from("would modify (Owner)"));
+        assert_eq!(describe_status(&response(TaskStatus::IsExecuted, Vec::new())), String::from("complete"));
+        assert_eq!(describe_status(&response(TaskStatus::NeedsPassive, Vec::new())), String::from("ok"));
+        assert_eq!(describe_status(&response(TaskStatus::IsSkipped, Vec::new())), String::from("skipped"));
+    }
+}
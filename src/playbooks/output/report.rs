@@ -0,0 +1,440 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::playbooks::context::PlaybookContext;
+use crate::playbooks::visitor::{PlaybookVisitor,RunSummary,redact};
+use crate::playbooks::traversal::HandlerMode;
+use crate::connection::command::CommandResult;
+use crate::inventory::hosts::Host;
+use crate::tasks::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc,RwLock,Mutex};
+use std::time::Instant;
+use chrono::prelude::*;
+
+// --report FILE writes one JSON document when the run ends, with every task, the
+// result on every host, the commands that were run, retries, and timings.  Unlike
+// the logfile this is meant to be ingested whole, for instance by a dashboard.
+
+pub struct ReportVisitor {
+    path: PathBuf,
+    check_mode: bool,
+    utc_start: DateTime<Utc>,
+    started: Instant,
+    state: Mutex<ReportState>
+}
+
+struct ReportState {
+    playbooks: Vec<String>,
+    tasks: Vec<TaskReport>,
+    task_started: Option<Instant>,
+    // results for hosts still working on the current task, keyed by host name
+    in_progress: HashMap<String,(Instant,HostReport)>
+}
+
+#[derive(Serialize)]
+struct Report {
+    start: String,
+    end: String,
+    duration_seconds: f64,
+    check_mode: bool,
+    playbooks: Vec<String>,
+    error: Option<String>,
+    tasks: Vec<TaskReport>,
+    summary: SummaryReport
+}
+
+#[derive(Serialize)]
+struct TaskReport {
+    playbook: Option<String>,
+    play: Option<String>,
+    role: Option<String>,
+    task: String,
+    module: Option<String>,
+    handler: bool,
+    start: String,
+    duration_seconds: f64,
+    hosts: Vec<HostReport>
+}
+
+#[derive(Serialize)]
+struct HostReport {
+    host: String,
+    status: Option<String>,
+    failed: bool,
+    ignored: bool,
+    changes: Vec<String>,
+    msg: Option<String>,
+    delegated_to: Option<String>,
+    retries: u64,
+    commands: Vec<CommandReport>,
+    diff: Option<String>,
+    duration_seconds: f64,
+    // a failing command is reported once as ok by the connection and again as failed by the module
+    #[serde(skip)]
+    last_ok: Option<(String,i32)>
+}
+
+#[derive(Serialize)]
+struct CommandReport {
+    cmd: String,
+    rc: i32,
+    out: String
+}
+
+#[derive(Serialize)]
+struct SummaryReport {
+    hosts: usize,
+    roles: usize,
+    tasks: usize,
+    matched: usize,
+    created: usize,
+    modified: usize,
+    removed: usize,
+    executed: usize,
+    passive: usize,
+    skipped: usize,
+    unchanged: usize,
+    changed: usize,
+    failed: usize,
+    failed_hosts: usize
+}
+
+impl ReportVisitor {
+
+    pub fn new(path: &PathBuf, check_mode: bool) -> Self {
+        Self {
+            path: path.clone(),
+            check_mode: check_mode,
+            utc_start: Utc::now(),
+            started: Instant::now(),
+            state: Mutex::new(ReportState {
+                playbooks: Vec::new(),
+                tasks: Vec::new(),
+                task_started: None,
+                in_progress: HashMap::new()
+            })
+        }
+    }
+
+    fn new_host_report(host_name: &String) -> HostReport {
+        return HostReport {
+            host: host_name.clone(),
+            status: None,
+            failed: false,
+            ignored: false,
+            changes: Vec::new(),
+            msg: None,
+            delegated_to: None,
+            retries: 0,
+            commands: Vec::new(),
+            diff: None,
+            duration_seconds: 0.0,
+            last_ok: None
+        };
+    }
+
+    // applies a change to the in-progress result for a host, starting one if the host has not been seen yet
+    fn update_host<F>(&self, host: &Arc<RwLock<Host>>, update: F) where F: FnOnce(&mut HostReport) {
+        let host_name = host.read().unwrap().name.clone();
+        let mut state = self.state.lock().unwrap();
+        let entry = state.in_progress.entry(host_name.clone()).or_insert_with(|| (Instant::now(), Self::new_host_report(&host_name)));
+        update(&mut entry.1);
+    }
+
+    // moves the result for a host onto the current task
+    fn finish_host<F>(&self, host: &Arc<RwLock<Host>>, update: F) where F: FnOnce(&mut HostReport) {
+        let host_name = host.read().unwrap().name.clone();
+        let mut state = self.state.lock().unwrap();
+        let (started, mut report) = match state.in_progress.remove(&host_name) {
+            Some(x) => x,
+            None => (Instant::now(), Self::new_host_report(&host_name))
+        };
+        update(&mut report);
+        report.duration_seconds = started.elapsed().as_secs_f64();
+        match state.tasks.last_mut() {
+            Some(task) => task.hosts.push(report),
+            // connection failures can happen before any task has started
+            None => {}
+        }
+    }
+
+    fn record_command(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, result: &Arc<Option<CommandResult>>, ok: bool) {
+        let cmd_result = match result.as_ref() {
+            Some(x) => x,
+            None => { return; }
+        };
+        let command = CommandReport {
+            cmd: redact(context, &cmd_result.cmd),
+            rc: cmd_result.rc,
+            out: redact(context, &cmd_result.out)
+        };
+        let key = (cmd_result.cmd.clone(), cmd_result.rc);
+        self.update_host(host, |report| {
+            if ! ok && report.last_ok.as_ref() == Some(&key) {
+                report.last_ok = None;
+                return;
+            }
+            report.last_ok = match ok { true => Some(key), false => None };
+            report.commands.push(command);
+        });
+    }
+
+    fn record_result(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>, failed: bool) {
+        let msg = task_response.msg.as_ref().map(|x| redact(context, x));
+        self.finish_host(host, |report| {
+            report.status = Some(format!("{:?}", task_response.status));
+            report.failed = failed;
+            // a failed status that did not fail the host means ignore_errors was used
+            report.ignored = ! failed && task_response.status == TaskStatus::Failed;
+            report.changes = task_response.changes.iter().map(|x| format!("{:?}", x)).collect();
+            report.msg = msg;
+        });
+    }
+
+    fn close_task(&self, state: &mut ReportState) {
+        // anything still in progress never reported a result, such as hosts dropped by an error
+        let leftovers : Vec<(Instant,HostReport)> = state.in_progress.drain().map(|(_,v)| v).collect();
+        if let Some(task) = state.tasks.last_mut() {
+            for (started, mut report) in leftovers.into_iter() {
+                report.duration_seconds = started.elapsed().as_secs_f64();
+                task.hosts.push(report);
+            }
+            task.hosts.sort_by(|a,b| a.host.cmp(&b.host));
+        }
+        if let (Some(task), Some(started)) = (state.tasks.last_mut(), state.task_started) {
+            task.duration_seconds = started.elapsed().as_secs_f64();
+        }
+    }
+
+    fn write_report(&self, context: &Arc<RwLock<PlaybookContext>>, error: Option<String>) {
+        let s = RunSummary::new(context);
+        let mut state = self.state.lock().unwrap();
+        self.close_task(&mut state);
+        let report = Report {
+            start: self.utc_start.to_rfc3339(),
+            end: Utc::now().to_rfc3339(),
+            duration_seconds: self.started.elapsed().as_secs_f64(),
+            check_mode: self.check_mode,
+            playbooks: state.playbooks.clone(),
+            error: error,
+            tasks: std::mem::take(&mut state.tasks),
+            summary: SummaryReport {
+                hosts: s.seen_hosts,
+                roles: s.role_ct,
+                tasks: s.task_ct,
+                matched: s.matched_ct,
+                created: s.created_ct,
+                modified: s.modified_ct,
+                removed: s.removed_ct,
+                executed: s.executed_ct,
+                passive: s.passive_ct,
+                skipped: s.skipped_ct,
+                unchanged: s.unchanged_ct,
+                changed: s.adjusted_ct,
+                failed: s.failed_ct,
+                failed_hosts: s.failed_hosts
+            }
+        };
+        let json = match serde_json::to_string_pretty(&report) {
+            Ok(x) => x,
+            Err(y) => { println!("unable to serialize the run report: {}", y); return; }
+        };
+        match std::fs::write(&self.path, json) {
+            Ok(_) => {},
+            Err(y) => { println!("unable to write the run report to {}: {}", self.path.display(), y); }
+        }
+    }
+
+}
+
+impl PlaybookVisitor for ReportVisitor {
+
+    fn on_playbook_start(&self, context: &Arc<RwLock<PlaybookContext>>) {
+        if let Some(path) = &context.read().unwrap().playbook_path {
+            self.state.lock().unwrap().playbooks.push(path.clone());
+        }
+    }
+
+    fn on_task_start(&self, context: &Arc<RwLock<PlaybookContext>>, is_handler: HandlerMode) {
+        let ctx = context.read().unwrap();
+        let mut state = self.state.lock().unwrap();
+        self.close_task(&mut state);
+        state.task_started = Some(Instant::now());
+        state.tasks.push(TaskReport {
+            playbook: ctx.playbook_path.clone(),
+            play: ctx.play.clone(),
+            role: ctx.role.as_ref().map(|x| x.name.clone()),
+            task: ctx.task.clone().unwrap_or(String::from("?")),
+            module: ctx.module.clone(),
+            handler: is_handler == HandlerMode::Handlers,
+            start: Utc::now().to_rfc3339(),
+            duration_seconds: 0.0,
+            hosts: Vec::new()
+        });
+    }
+
+    fn on_host_task_start(&self, _context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>) {
+        let host_name = host.read().unwrap().name.clone();
+        self.state.lock().unwrap().in_progress.insert(host_name.clone(), (Instant::now(), Self::new_host_report(&host_name)));
+    }
+
+    fn on_host_delegate(&self, host: &Arc<RwLock<Host>>, delegated: &String) {
+        self.update_host(host, |report| report.delegated_to = Some(delegated.clone()));
+    }
+
+    fn on_host_task_retry(&self, _context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, _retries: u64, _delay: u64, _reason: &String) {
+        self.update_host(host, |report| report.retries = report.retries + 1);
+    }
+
+    fn on_command_ok(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, result: &Arc<Option<CommandResult>>) {
+        self.record_command(context, host, result, true);
+    }
+
+    fn on_command_failed(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, result: &Arc<Option<CommandResult>>) {
+        self.record_command(context, host, result, false);
+    }
+
+    fn on_host_diff(&self, context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, _path: &String, diff: &String) {
+        let diff = redact(context, diff);
+        self.update_host(host, |report| report.diff = Some(diff));
+    }
+
+    fn on_host_task_ok(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        self.record_result(context, task_response, host, false);
+    }
+
+    fn on_host_task_check_ok(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        self.record_result(context, task_response, host, false);
+    }
+
+    fn on_host_task_failed(&self, context: &Arc<RwLock<PlaybookContext>>, task_response: &Arc<TaskResponse>, host: &Arc<RwLock<Host>>) {
+        self.record_result(context, task_response, host, true);
+    }
+
+    fn on_host_connect_failed(&self, _context: &Arc<RwLock<PlaybookContext>>, host: &Arc<RwLock<Host>>, msg: &String) {
+        self.finish_host(host, |report| {
+            report.status = Some(String::from("ConnectionFailed"));
+            report.failed = true;
+            report.msg = Some(msg.clone());
+        });
+    }
+
+    fn on_exit(&self, context: &Arc<RwLock<PlaybookContext>>) {
+        self.write_report(context, None);
+    }
+
+    fn on_error(&self, context: &Arc<RwLock<PlaybookContext>>, msg: &String) {
+        self.write_report(context, Some(msg.clone()));
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::parser::CliParser;
+    use guid_create::GUID;
+
+    fn keys(value: &serde_json::Value) -> Vec<String> {
+        let mut keys : Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        return keys;
+    }
+
+    fn command(cmd: &str, rc: i32) -> Arc<Option<CommandResult>> {
+        return Arc::new(Some(CommandResult { cmd: String::from(cmd), out: String::from("out"), rc: rc }));
+    }
+
+    fn response(status: TaskStatus, changes: Vec<Field>, msg: Option<&str>) -> Arc<TaskResponse> {
+        return Arc::new(TaskResponse { status: status, changes: changes, msg: msg.map(String::from), command_result: Arc::new(None),
+            with: Arc::new(None), and: Arc::new(None), data: None });
+    }
+
+    #[test]
+    fn test_report_shape() {
+        let path = std::env::temp_dir().join(format!("jetp-report-test-{}.json", GUID::rand().to_string()));
+        let visitor = ReportVisitor::new(&path, false);
+        let context = Arc::new(RwLock::new(PlaybookContext::new(&CliParser::new())));
+        {
+            let mut ctx = context.write().unwrap();
+            ctx.playbook_path = Some(String::from("site.yml"));
+            ctx.play = Some(String::from("web"));
+            ctx.task = Some(String::from("build"));
+            ctx.module = Some(String::from("shell"));
+        }
+        let web1 = Arc::new(RwLock::new(Host::new(&String::from("web1"))));
+        let web2 = Arc::new(RwLock::new(Host::new(&String::from("web2"))));
+
+        visitor.on_playbook_start(&context);
+        visitor.on_task_start(&context, HandlerMode::NormalTasks);
+        visitor.on_host_task_start(&context, &web2);
+        visitor.on_host_task_start(&context, &web1);
+        visitor.on_command_ok(&context, &web1, &command("make", 0));
+        visitor.on_host_task_ok(&context, &response(TaskStatus::IsExecuted, Vec::new(), None), &web1);
+        // the connection reports the failing command as ok, then the module reports it as failed: it is listed once
+        visitor.on_host_task_retry(&context, &web2, 1, 0, &String::from("retrying"));
+        visitor.on_command_ok(&context, &web2, &command("make", 2));
+        visitor.on_command_failed(&context, &web2, &command("make", 2));
+        // but a command that only failed is still listed
+        visitor.on_command_failed(&context, &web2, &command("make clean", 1));
+        visitor.on_host_task_failed(&context, &response(TaskStatus::Failed, Vec::new(), Some("make failed")), &web2);
+
+        context.write().unwrap().task = Some(String::from("restart"));
+        visitor.on_task_start(&context, HandlerMode::Handlers);
+        visitor.on_host_task_start(&context, &web1);
+        visitor.on_host_task_ok(&context, &response(TaskStatus::IsModified, vec![Field::Content], None), &web1);
+        visitor.on_exit(&context);
+
+        let report : serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(keys(&report), vec!["check_mode", "duration_seconds", "end", "error", "playbooks", "start", "summary", "tasks"]);
+        assert_eq!(report["playbooks"], serde_json::json!(["site.yml"]));
+        assert_eq!(report["error"], serde_json::Value::Null);
+        assert_eq!(report["check_mode"], serde_json::json!(false));
+        assert_eq!(keys(&report["summary"]), vec!["changed", "created", "executed", "failed", "failed_hosts", "hosts", "matched",
+            "modified", "passive", "removed", "roles", "skipped", "tasks", "unchanged"]);
+
+        let tasks = report["tasks"].as_array().unwrap();
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(keys(&tasks[0]), vec!["duration_seconds", "handler", "hosts", "module", "play", "playbook", "role", "start", "task"]);
+        assert_eq!(tasks[0]["task"], serde_json::json!("build"));
+        assert_eq!(tasks[0]["module"], serde_json::json!("shell"));
+        assert_eq!(tasks[0]["handler"], serde_json::json!(false));
+        assert_eq!(tasks[1]["handler"], serde_json::json!(true));
+
+        // hosts are sorted by name, and last_ok is internal only
+        let hosts = tasks[0]["hosts"].as_array().unwrap();
+        assert_eq!(hosts.len(), 2);
+        assert_eq!(keys(&hosts[0]), vec!["changes", "commands", "delegated_to", "diff", "duration_seconds", "failed", "host",
+            "ignored", "msg", "retries", "status"]);
+        assert_eq!(hosts[0]["host"], serde_json::json!("web1"));
+        assert_eq!(hosts[0]["status"], serde_json::json!("IsExecuted"));
+        assert_eq!(hosts[0]["commands"], serde_json::json!([{ "cmd": "make", "rc": 0, "out": "out" }]));
+        assert_eq!(hosts[1]["host"], serde_json::json!("web2"));
+        assert_eq!(hosts[1]["failed"], serde_json::json!(true));
+        assert_eq!(hosts[1]["msg"], serde_json::json!("make failed"));
+        assert_eq!(hosts[1]["retries"], serde_json::json!(1));
+        assert_eq!(hosts[1]["commands"], serde_json::json!([
+            { "cmd": "make", "rc": 2, "out": "out" },
+            { "cmd": "make clean", "rc": 1, "out": "out" }
+        ]));
+        assert_eq!(tasks[1]["hosts"][0]["changes"], serde_json::json!(["Content"]));
+    }
+}
@@ -18,7 +18,9 @@ use crate::registry::list::Task;
 use crate::connection::connection::Connection;
 use crate::handle::handle::TaskHandle;
 use crate::playbooks::traversal::RunState;
+use crate::playbooks::visitor::PlaybookVisitor;
 use crate::inventory::hosts::Host;
+use crate::inventory::patterns::{HostPattern,PatternScope,is_host_pattern};
 use crate::playbooks::traversal::HandlerMode;
 use crate::playbooks::language::Play;
 use crate::tasks::request::SudoDetails;
@@ -76,9 +78,8 @@ pub fn fsm_run_task(run_state: &Arc<RunState>, play: &Play, task: &Task, are_han
             },
             Err(x) => {
                 // hosts with connection failures are removed from the pool
-                run_state.visitor.read().unwrap().debug_host(&host, &x);
                 run_state.context.write().unwrap().fail_host(&host);
-                run_state.visitor.read().unwrap().on_host_connect_failed(&run_state.context, &host);
+                run_state.visitor.read().unwrap().on_host_connect_failed(&run_state.context, &host, &x);
             }
         }
         // rayon needs some math to add up, hence the 1. It seems to short-circuit without some work to do.
@@ -93,54 +94,71 @@ fn get_actual_connection(run_state: &Arc<RunState>, host: &Arc<RwLock<Host>>, ta
     // usually the connection we already have is the one we will use, but this is not the case for using the delegate_to feature
     // this is a bit complex...
 
-    return match task.get_with() {
-        
-        // if the task has a with section then the task might be delegated
-        Some(task_with) => match task_with.delegate_to {
-
-            // we have found the delegate_to keyword
-            Some(pre_delegate) => {
-
-                // we need to store the variable 'delegate_host' into the host's facts storage so it can be used in module parameters.
-                let hn = host.read().unwrap().name.clone();
-                let mut mapping = serde_yaml::Mapping::new();
-                mapping.insert(serde_yaml::Value::String(String::from("delegate_host")), serde_yaml::Value::String(hn.clone()));
-                host.write().unwrap().update_facts2(mapping);
-                
-                // the delegate_to parameter could be a variable
-                let delegate = run_state.context.read().unwrap().render_template(&pre_delegate, host, BlendTarget::NotTemplateModule, TemplateMode::Strict)?;
-
-                if delegate.eq(&hn.clone()) {
-                    // delegating to the same host will deadlock since the connection is wrapped in a mutex, 
-                    // so just return the original connection if that is requested
-                    return Ok((None, input_connection))
+    // delegate_to on the task wins over delegate_to on any enclosing block
+    let task_delegate = match task.get_with() {
+        Some(task_with) => task_with.delegate_to,
+        None => None
+    };
+    let blocks = run_state.context.read().unwrap().get_blocks();
+    let block_delegate = blocks.iter().rev().find_map(|x| x.delegate_to.clone());
+
+    return match task_delegate.or(block_delegate) {
+
+        // we have found the delegate_to keyword
+        Some(pre_delegate) => {
+
+            // we need to store the variable 'delegate_host' into the host's facts storage so it can be used in module parameters.
+            let hn = host.read().unwrap().name.clone();
+            let mut mapping = serde_yaml::Mapping::new();
+            mapping.insert(serde_yaml::Value::String(String::from("delegate_host")), serde_yaml::Value::String(hn.clone()));
+            host.write().unwrap().update_facts2(mapping);
+            
+            // the delegate_to parameter could be a variable
+            let rendered = run_state.context.read().unwrap().render_template(&pre_delegate, host, BlendTarget::NotTemplateModule, TemplateMode::Strict)?;
+            // or a host pattern, as long as it picks out exactly one host
+            let delegate = match is_host_pattern(&rendered) {
+                true => get_delegate_from_pattern(run_state, &rendered)?,
+                false => rendered
+            };
+
+            if delegate.eq(&hn.clone()) {
+                // delegating to the same host will deadlock since the connection is wrapped in a mutex, 
+                // so just return the original connection if that is requested
+                return Ok((None, input_connection))
+            }
+            else if delegate.eq(&String::from("localhost")) {
+                // localhost delegation has some security implications (see docs) so require a CLI flag for access
+                if run_state.allow_localhost_delegation {
+                    return Ok((Some(delegate.clone()), run_state.connection_factory.read().unwrap().get_local_connection(&run_state.context)?))
+                } else {
+                    return Err(format!("localhost delegation has potential security implementations, pass --allow-localhost-delegation to sign off"));
                 }
-                else if delegate.eq(&String::from("localhost")) {
-                    // localhost delegation has some security implications (see docs) so require a CLI flag for access
-                    if run_state.allow_localhost_delegation {
-                        return Ok((Some(delegate.clone()), run_state.connection_factory.read().unwrap().get_local_connection(&run_state.context)?))
-                    } else {
-                        return Err(format!("localhost delegation has potential security implementations, pass --allow-localhost-delegation to sign off"));
-                    }
+            }
+            else {
+                // with some pre-checks out of the way, allow delegation to the host if it's in inventory
+                let has_host = run_state.inventory.read().unwrap().has_host(&delegate);
+                if ! has_host {
+                    return Err(format!("cannot delegate to a host not found in inventory: {}", delegate));
                 }
-                else {
-                    // with some pre-checks out of the way, allow delegation to the host if it's in inventory
-                    let has_host = run_state.inventory.read().unwrap().has_host(&delegate);
-                    if ! has_host {
-                        return Err(format!("cannot delegate to a host not found in inventory: {}", delegate));
-                    }
-                    let host = run_state.inventory.read().unwrap().get_host(&delegate);
-                    return Ok((Some(delegate.clone()), run_state.connection_factory.read().unwrap().get_connection(&run_state.context, &host)?));
-                } 
-            },
-            // there was no delegate keyword, use the original connection
-            None => Ok((None, input_connection))
+                let host = run_state.inventory.read().unwrap().get_host(&delegate);
+                return Ok((Some(delegate.clone()), run_state.connection_factory.read().unwrap().get_connection(&run_state.context, &host)?));
+            } 
         },
-        // there was no 'with' block, use teh original connection
+        // there was no delegate keyword, use the original connection
         None => Ok((None, input_connection))
     };
 }
 
+fn get_delegate_from_pattern(run_state: &Arc<RunState>, pattern: &String) -> Result<String, String> {
+    let host_pattern = HostPattern::parse(pattern)?;
+    let names = host_pattern.select_names(&run_state.inventory.read().unwrap(), PatternScope::Any)?;
+    return match names.len() {
+        1 => Ok(names[0].clone()),
+        0 => Err(format!("delegate_to pattern ({}) does not match any host", pattern)),
+        n => Err(format!("delegate_to pattern ({}) must match exactly one host, it matches {}", pattern, n))
+    };
+}
+
 fn run_task_on_host(
     run_state: &Arc<RunState>,
     input_connection: Arc<Mutex<dyn Connection>>,
@@ -191,7 +209,17 @@ fn run_task_on_host(
         if condition.is_some() {
             let cond = handle.template.test_condition(&validate, TemplateMode::Strict, &condition.as_ref().unwrap())?;
             if ! cond {
-                return Ok(handle.response.is_skipped(&Arc::clone(&validate)));
+                return skip_task(&handle, &validate, host, &evaluated);
+            }
+        }
+    }
+
+    // conditions on enclosing blocks must also be true
+    let blocks = run_state.context.read().unwrap().get_blocks();
+    for block_with in blocks.iter() {
+        if let Some(condition) = &block_with.condition {
+            if ! handle.template.test_condition(&validate, TemplateMode::Strict, condition)? {
+                return skip_task(&handle, &validate, host, &evaluated);
             }
         }
     }
@@ -222,34 +250,81 @@ fn run_task_on_host(
         // re-evaluate the task, allowing the 'items' to be plugged in.
         let evaluated = task.evaluate(&handle, &validate, TemplateMode::Strict)?;
 
-        // see if there is any retry or delay logic in the task
-        let mut retries = match evaluated.and.as_ref().is_some() {
-            false => 0, true => evaluated.and.as_ref().as_ref().unwrap().retry
+        // see if there is any retry, delay, until or timeout logic in the task
+        let post_logic = evaluated.and.as_ref().as_ref();
+        let delay = match post_logic {
+            None => 1, Some(logic) => logic.delay
+        };
+        let timeout = match post_logic {
+            None => 0, Some(logic) => logic.timeout
         };
-            let delay = match evaluated.and.as_ref().is_some() {
-            false => 1, true => evaluated.and.as_ref().as_ref().unwrap().delay
+        let until = match post_logic {
+            None => None, Some(logic) => logic.until.clone()
         };
+        // and/until without retry or timeout was already refused when the task was evaluated
+        let mut retries = match post_logic {
+            None => 0, 
+            // with only a timeout, retry as often as the delay allows
+            Some(logic) if logic.retry == 0 && timeout > 0 => timeout / std::cmp::max(delay, 1),
+            Some(logic) => logic.retry
+        };
+        let started = time::Instant::now();
+        let mut attempts : u64 = 0;
     
         // run the task as many times as defined by retry logic
         loop {
             
             // here we finally call the actual task, everything around this is just support
             // for delegation, loops, and retries!
-            match run_task_on_host_inner(run_state, &connection, host, play, task, are_handlers, &handle, &validate, &evaluated) {
-                Err(e) => match retries {
-                    // retries are used up
-                    0 => { return Err(e); },
-                    // we have retries left
-                    _ => { 
-                        retries = retries - 1;
-                        run_state.visitor.read().unwrap().on_host_task_retry(&run_state.context, host, retries, delay);
-                        if delay > 0 {
-                            let duration = time::Duration::from_secs(delay);
-                            thread::sleep(duration);
+            let attempt = run_task_on_host_inner(run_state, &connection, host, play, task, are_handlers, &handle, &validate, &evaluated);
+            attempts = attempts + 1;
+
+            // with and/until, a successful attempt only ends the loop once the condition is true.  The condition
+            // sees the values 'save' stores along with any facts or variables the task changed.
+            let attempt = match (attempt, &until) {
+                (Ok(x), Some(condition)) => {
+                    match handle.template.test_condition_with_extra_data(&validate, TemplateMode::Strict, condition, host, x.to_saved_mapping())? {
+                        true => Ok(x),
+                        false => Err(handle.response.with_status(&x, TaskStatus::Failed, Some(format!("until condition not met: {}", condition))))
+                    }
+                },
+                (other, _) => other
+            };
+
+            match attempt {
+                Err(e) => {
+                    let out_of_time = timeout > 0 && started.elapsed() + time::Duration::from_secs(delay) > time::Duration::from_secs(timeout);
+                    if retries == 0 || out_of_time {
+                        // retries are used up, or waiting any longer would pass the timeout
+                        let reason = e.msg.clone().unwrap_or(String::from("failed"));
+                        let e = match (out_of_time, &until) {
+                            (true, _) => handle.response.with_status(&e, TaskStatus::Failed, 
+                                Some(format!("timed out after {} seconds and {} attempts: {}", started.elapsed().as_secs(), attempts, reason))),
+                            (false, Some(_)) => handle.response.with_status(&e, TaskStatus::Failed, 
+                                Some(format!("gave up after {} attempts: {}", attempts, reason))),
+                            (false, None) => e
+                        };
+                        save_result(host, &evaluated, &e);
+                        // ignore_errors was already applied to failed attempts, but not to until
+                        if until.is_some() && post_logic.unwrap().ignore_errors {
+                            return Ok(e);
                         }
+                        return Err(e); 
+                    }
+                    // we have retries left
+                    retries = retries - 1;
+                    let reason = e.msg.clone().unwrap_or(String::from("failed"));
+                    run_state.visitor.read().unwrap().on_host_task_retry(&run_state.context, host, retries, delay, &reason);
+                    if delay > 0 {
+                        let duration = time::Duration::from_secs(delay);
+                        thread::sleep(duration);
                     }
                 },
-                Ok(x) => { last = Some(Ok(x)); break }
+                Ok(x) => { 
+                    save_result(host, &evaluated, &x);
+                    last = Some(Ok(x)); 
+                    break 
+                }
             }
         }
     
@@ -266,6 +341,84 @@ fn run_task_on_host(
 
 }
 
+// with and/save the result of the task is stored as a host variable.  When looping over items
+// each result replaces the previous one, so the last item's result is kept.
+
+fn save_result(host: &Arc<RwLock<Host>>, evaluated: &EvaluatedTask, response: &Arc<TaskResponse>) {
+    if let Some(logic) = evaluated.and.as_ref() {
+        if let Some(key) = &logic.save {
+            save_result_as(host, key, response);
+        }
+    }
+}
+
+fn save_result_as(host: &Arc<RwLock<Host>>, key: &String, response: &Arc<TaskResponse>) {
+    let mut result = serde_yaml::Mapping::new();
+    result.insert(serde_yaml::Value::String(key.clone()), serde_yaml::Value::Mapping(response.to_saved_mapping()));
+    host.write().unwrap().update_variables(result);
+}
+
+// a task skipped by its condition, or by the condition of an enclosing block, still saves its result so
+// later tasks can test result.skipped.  It was only evaluated with templates off, which leaves the
+// variable name in and/save as written.
+
+fn skip_task(handle: &Arc<TaskHandle>, validate: &Arc<TaskRequest>, host: &Arc<RwLock<Host>>, evaluated: &EvaluatedTask) -> Result<Arc<TaskResponse>,Arc<TaskResponse>> {
+    let response = handle.response.is_skipped(validate);
+    if let Some(logic) = evaluated.and.as_ref() {
+        if let Some(key) = handle.template.string_option_no_spaces(validate, TemplateMode::Strict, &String::from("save"), &logic.save)? {
+            save_result_as(host, &key, &response);
+        }
+    }
+    return Ok(response);
+}
+
+// failed_when and changed_when are tested against the values 'save' would store, such as rc, out
+// and status.  failed_when decides whether the task failed, and a task that is not failed is
+// reported as executed (changed).  changed_when then decides whether a successful task counts as
+// changed, a task that is not changed is reported as passive.  In check mode the conditions only
+// apply to passive modules, as other modules report what they would do without running.
+
+fn apply_result_conditions(
+    handle: &Arc<TaskHandle>,
+    validate: &Arc<TaskRequest>,
+    host: &Arc<RwLock<Host>>,
+    logic: &PostLogicEvaluated,
+    result: Result<Arc<TaskResponse>,Arc<TaskResponse>>) -> Result<Result<Arc<TaskResponse>,Arc<TaskResponse>>,Arc<TaskResponse>> {
+
+    let response = match &result {
+        Ok(x) => Arc::clone(x),
+        Err(x) => Arc::clone(x)
+    };
+    let saved = response.to_saved_mapping();
+    let mut result = result;
+
+    if let Some(condition) = &logic.failed_when {
+        let failed = handle.template.test_condition_with_extra_data(validate, TemplateMode::Strict, condition, host, saved.clone())?;
+        result = match (failed, result) {
+            (true, Ok(x)) => Err(handle.response.with_status(&x, TaskStatus::Failed, Some(format!("failed_when: {}", condition)))),
+            (false, Err(x)) => Ok(handle.response.with_status(&x, TaskStatus::IsExecuted, None)),
+            (_, unchanged) => unchanged
+        };
+    }
+
+    if let Some(condition) = &logic.changed_when {
+        if let Ok(x) = &result {
+            let changed = handle.template.test_condition_with_extra_data(validate, TemplateMode::Strict, condition, host, saved)?;
+            let is_changed = match x.status {
+                TaskStatus::IsCreated | TaskStatus::IsModified | TaskStatus::IsRemoved | TaskStatus::IsExecuted => true,
+                _ => false
+            };
+            if changed && ! is_changed {
+                result = Ok(handle.response.with_status(x, TaskStatus::IsExecuted, x.msg.clone()));
+            } else if ! changed && is_changed {
+                result = Ok(handle.response.with_status(x, TaskStatus::IsPassive, x.msg.clone()));
+            }
+        }
+    }
+
+    return Ok(result);
+}
+
 // the "on this host" method body from _task
 fn run_task_on_host_inner(
     run_state: &Arc<RunState>,
@@ -298,6 +451,14 @@ fn run_task_on_host_inner(
         Some(x) => x.clone()
     };
     
+    // sudo on enclosing blocks overrides the play, and sudo on the task overrides that
+    let blocks = run_state.context.read().unwrap().get_blocks();
+    for block_with in blocks.iter() {
+        if block_with.sudo.is_some() {
+            sudo = handle.template.string_option_no_spaces(validate, TemplateMode::Strict, &String::from("sudo"), &block_with.sudo)?;
+        }
+    }
+
     // is 'with' provided?
     if pre_logic.is_some() {
         let logic = pre_logic.as_ref().as_ref().unwrap();
@@ -351,7 +512,12 @@ fn run_task_on_host_inner(
 
             // matched indicates we don't need to do anything
             TaskStatus::IsMatched => {
-                Ok(handle.response.is_matched(&Arc::clone(&query)))
+                let matched = handle.response.is_matched(&Arc::clone(&query));
+                match &qrc_ok.data {
+                    // keep anything the module reported so 'save' sees the same shape on every run
+                    Some(data) => Ok(handle.response.with_data(&matched, data.clone())),
+                    None => Ok(matched)
+                }
             },
 
             TaskStatus::NeedsCreation => match modify_mode {
@@ -457,6 +623,13 @@ fn run_task_on_host_inner(
         }
     };
 
+    // and/failed_when and and/changed_when may reinterpret the result
+
+    let prelim_result = match post_logic.as_ref() {
+        Some(logic) => apply_result_conditions(handle, validate, host, logic, prelim_result)?,
+        None => prelim_result
+    };
+
     // now that we've got a result, whether we use that result depends
     // on whether ignore_errors was set.
 
@@ -15,21 +15,28 @@
 // long with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::playbooks::language::Play;
-use crate::playbooks::visitor::PlaybookVisitor;
+use crate::playbooks::visitor::{PlaybookVisitor,MultiVisitor};
 use crate::playbooks::context::PlaybookContext;
 use crate::playbooks::language::{Role,RoleInvocation};
 use crate::connection::factory::ConnectionFactory;
 use crate::registry::list::Task;
+use crate::modules::control::block::BlockTask;
+use crate::modules::control::include::IncludeTask;
+use crate::handle::template::BlendTarget;
+use crate::playbooks::templar::TemplateMode;
 use crate::playbooks::task_fsm::fsm_run_task;
 use crate::inventory::inventory::Inventory;
 use crate::inventory::hosts::Host;
+use crate::inventory::patterns::{HostPattern,PatternScope};
 use crate::util::io::{jet_file_open,directory_as_string};
-use crate::util::yaml::{blend_variables,show_yaml_error_in_context};
+use crate::util::yaml::{blend_variables,show_yaml_error_in_context,record_variable_sources};
 use std::path::PathBuf;
-use std::collections::HashMap;
+use std::collections::{HashMap,HashSet};
 use std::sync::{Arc,RwLock};
 use std::path::Path;
 use std::env;
+use crate::util::vault::load_vars_file;
+use std::fs;
 
 // this module contains the start of everything related to playbook evaluation
 
@@ -52,12 +59,24 @@ pub struct RunState {
     pub limit_groups: Vec<String>,
     pub batch_size: Option<usize>,
     pub context: Arc<RwLock<PlaybookContext>>,
-    pub visitor: Arc<RwLock<PlaybookVisitor>>,
+    pub visitor: Arc<RwLock<MultiVisitor>>,
     pub connection_factory: Arc<RwLock<dyn ConnectionFactory>>,
     pub tags: Option<Vec<String>>,
     pub allow_localhost_delegation: bool
 }
 
+// parses a playbook file, which is a list of plays
+
+pub fn load_playbook(playbook_path: &PathBuf) -> Result<Vec<Play>, String> {
+    let playbook_file = jet_file_open(&playbook_path)?;
+    let parsed: Result<Vec<Play>, serde_yaml::Error> = serde_yaml::from_reader(playbook_file);
+    if parsed.is_err() {
+        show_yaml_error_in_context(&parsed.unwrap_err(), &playbook_path);
+        return Err(format!("edit the file and try again?"));
+    }
+    return Ok(parsed.unwrap());
+}
+
 // this is the top end traversal function that is called from cli/playbooks.rs
 
 pub fn playbook_traversal(run_state: &Arc<RunState>) -> Result<(), String> {
@@ -76,12 +95,7 @@ pub fn playbook_traversal(run_state: &Arc<RunState>) -> Result<(), String> {
         run_state.visitor.read().unwrap().on_playbook_start(&run_state.context);
 
         // parse the playbook file
-        let playbook_file = jet_file_open(&playbook_path)?;
-        let parsed: Result<Vec<Play>, serde_yaml::Error> = serde_yaml::from_reader(playbook_file);
-        if parsed.is_err() {
-            show_yaml_error_in_context(&parsed.unwrap_err(), &playbook_path);
-            return Err(format!("edit the file and try again?"));
-        }   
+        let plays = load_playbook(playbook_path)?;
 
         // chdir in the playbook directory
         let p1 = env::current_dir().expect("could not get current directory");
@@ -94,7 +108,6 @@ pub fn playbook_traversal(run_state: &Arc<RunState>) -> Result<(), String> {
         }
 
         // walk each play in the playbook
-        let plays: Vec<Play> = parsed.unwrap();
         for play in plays.iter() {
             match handle_play(&run_state, play) {
                 Ok(_) => {},
@@ -137,15 +150,10 @@ fn handle_play(run_state: &Arc<RunState>, play: &Play) -> Result<(), String> {
     }
     run_state.visitor.read().unwrap().on_play_start(&run_state.context);
 
-    // make sure all host and groups used to limit exists
-    validate_limit_groups(run_state, play)?;
-    validate_limit_hosts(run_state, play)?;
-
-    // make sure all hosts are valid and we have some hosts to talk to
-    validate_groups(run_state, play)?;
-    let hosts = get_play_hosts(run_state, play);
+    // make sure all hosts and groups referenced exist and we have some hosts to talk to
+    let hosts = get_play_hosts(run_state, play)?;
     validate_hosts(run_state, play, &hosts)?;
-    load_vars_into_context(run_state, play)?;
+    load_vars_into_context(&run_state.context, play)?;
 
     // support for serialization if using push configuration
     // means we may not configure hosts all at once but may take
@@ -193,7 +201,8 @@ fn handle_batch(run_state: &Arc<RunState>, play: &Play, hosts: &Vec<Arc<RwLock<H
     // handle role tasks
     if play.roles.is_some() {
         let roles = play.roles.as_ref().unwrap();
-        for invocation in roles.iter() { process_role(run_state, &play, &invocation, HandlerMode::NormalTasks)?; }
+        let mut roles_run : HashSet<String> = HashSet::new();
+        for invocation in roles.iter() { process_role(run_state, &play, &invocation, HandlerMode::NormalTasks, &mut roles_run, &Vec::new())?; }
     }
     { let mut ctx = run_state.context.write().unwrap(); ctx.unset_role(); }
 
@@ -206,7 +215,8 @@ fn handle_batch(run_state: &Arc<RunState>, play: &Play, hosts: &Vec<Arc<RwLock<H
     // handle role handlers
     if play.roles.is_some() {
         let roles = play.roles.as_ref().unwrap();
-        for invocation in roles.iter() { process_role(run_state, &play, &invocation, HandlerMode::Handlers)?; }
+        let mut roles_run : HashSet<String> = HashSet::new();
+        for invocation in roles.iter() { process_role(run_state, &play, &invocation, HandlerMode::Handlers, &mut roles_run, &Vec::new())?; }
     }   
     { let mut ctx = run_state.context.write().unwrap(); ctx.unset_role(); }  
 
@@ -240,6 +250,15 @@ fn check_tags(run_state: &Arc<RunState>, task: &Task, role_invocation: Option<&R
                 },
                 None => {}
             };
+            // tags on enclosing blocks apply to every task inside them
+            for block_with in run_state.context.read().unwrap().get_blocks().iter() {
+                match &block_with.tags {
+                    Some(block_tags) => {
+                        for x in block_tags.iter() { if cli_tags.contains(&x) { return true; } }
+                    },
+                    None => {}
+                }
+            }
             match role_invocation {
                 // the role invocation has tags applied
                 Some(role_invoke) => match &role_invoke.tags {
@@ -266,6 +285,14 @@ fn process_task(run_state: &Arc<RunState>, play: &Play, task: &Task, are_handler
     let hosts : HashMap<String, Arc<RwLock<Host>>> = run_state.context.read().unwrap().get_remaining_hosts();
     if hosts.len() == 0 { return Err(String::from("no hosts remaining")) }
 
+    // blocks are containers for other tasks rather than something to run
+    if let Task::Block(block) = task {
+        return process_block(run_state, play, block, are_handlers, role_invocation);
+    }
+    if let Task::Include(include) = task {
+        return process_include(run_state, play, include, are_handlers, role_invocation);
+    }
+
     // we will run tasks with the FSM only if not skipped by tags
     let should_run = check_tags(run_state, task, role_invocation);
     if should_run {
@@ -278,16 +305,241 @@ fn process_task(run_state: &Arc<RunState>, play: &Play, task: &Task, are_handler
     return Ok(());
 }
 
-fn process_role(run_state: &Arc<RunState>, play: &Play, invocation: &RoleInvocation, are_handlers: HandlerMode) -> Result<(), String> {
+fn process_block(run_state: &Arc<RunState>, play: &Play, block: &BlockTask, are_handlers: HandlerMode, role_invocation: Option<&RoleInvocation>) -> Result<(), String> {
+
+    // the block's 'with' section is kept in the context while the block runs so the task FSM
+    // can apply it to every task inside.  See modules/control/block.rs.
+
+    block.validate()?;
+    run_state.context.write().unwrap().push_block(&block.with);
+    let result = process_block_sections(run_state, play, block, are_handlers, role_invocation);
+    run_state.context.write().unwrap().pop_block();
+    return result;
+}
+
+fn process_block_sections(run_state: &Arc<RunState>, play: &Play, block: &BlockTask, are_handlers: HandlerMode, role_invocation: Option<&RoleInvocation>) -> Result<(), String> {
+
+    // hosts are moved in and out of the remaining pool here so that each section of the block
+    // only runs on the hosts it applies to, and put back the way they should be afterwards
+
+    let entered = get_remaining_host_list(run_state);
+    let failures_before : HashMap<String,usize> = {
+        let ctx = run_state.context.read().unwrap();
+        entered.iter().map(|h| { let n = h.read().unwrap().name.clone(); let c = ctx.get_failed_count_for_host(&n); (n, c) }).collect()
+    };
+
+    process_block_tasks(run_state, play, &block.tasks, are_handlers, role_invocation)?;
+
+    // hosts that failed in the block run the rescue tasks, and if those all succeed the host is no longer failed
+
+    if let Some(rescue) = &block.rescue {
+        let (survivors, failed) = split_block_hosts(run_state, &entered);
+        if ! failed.is_empty() {
+            {
+                let mut ctx = run_state.context.write().unwrap();
+                for host in failed.iter() { ctx.restore_host(host); }
+                ctx.set_targetted_hosts(&failed);
+            }
+            process_block_tasks(run_state, play, rescue, are_handlers, role_invocation)?;
+            let mut hosts = get_remaining_host_list(run_state);
+            let mut ctx = run_state.context.write().unwrap();
+            for host in hosts.iter() {
+                let name = host.read().unwrap().name.clone();
+                ctx.forgive_failures(&name, *failures_before.get(&name).unwrap_or(&0));
+            }
+            hosts.extend(survivors);
+            ctx.set_targetted_hosts(&hosts);
+        }
+    }
+
+    // the always tasks run on every host that entered the block, but hosts that had failed stay failed
+
+    if let Some(always) = &block.always {
+        let (_, failed) = split_block_hosts(run_state, &entered);
+        {
+            let mut ctx = run_state.context.write().unwrap();
+            for host in failed.iter() { ctx.restore_host(host); }
+            ctx.set_targetted_hosts(&entered);
+        }
+        process_block_tasks(run_state, play, always, are_handlers, role_invocation)?;
+        let remaining = run_state.context.read().unwrap().get_remaining_hosts();
+        let mut ctx = run_state.context.write().unwrap();
+        for host in failed.iter() {
+            if remaining.contains_key(&host.read().unwrap().name) { ctx.fail_host(host); }
+        }
+    }
+
+    return Ok(());
+}
+
+fn process_block_tasks(run_state: &Arc<RunState>, play: &Play, tasks: &Vec<Task>, are_handlers: HandlerMode, role_invocation: Option<&RoleInvocation>) -> Result<(), String> {
+    for task in tasks.iter() {
+        // once every host has failed, stop so that rescue and always can still run
+        if run_state.context.read().unwrap().get_remaining_hosts().is_empty() {
+            break;
+        }
+        process_task(run_state, play, task, are_handlers, role_invocation)?;
+    }
+    return Ok(());
+}
+
+fn process_include(run_state: &Arc<RunState>, play: &Play, include: &IncludeTask, are_handlers: HandlerMode, role_invocation: Option<&RoleInvocation>) -> Result<(), String> {
+
+    // like a block, the 'with' section of the include applies to every task in the file,
+    // and the include's vars are layered on top of the other variables while it runs
+
+    include.validate()?;
+    {
+        let mut ctx = run_state.context.write().unwrap();
+        ctx.push_block(&include.with);
+        ctx.push_include_vars(&include.file, &include.vars);
+    }
+    let result = process_include_files(run_state, play, include, are_handlers, role_invocation);
+    {
+        let mut ctx = run_state.context.write().unwrap();
+        ctx.pop_include_vars();
+        ctx.pop_block();
+    }
+    return result;
+}
+
+fn process_include_files(run_state: &Arc<RunState>, play: &Play, include: &IncludeTask, are_handlers: HandlerMode, role_invocation: Option<&RoleInvocation>) -> Result<(), String> {
+
+    // the file name may use variables, so hosts are grouped by the file they pick and each
+    // group runs its file in turn
+
+    let in_role = run_state.context.read().unwrap().role.is_some();
+    let groups = group_hosts_by_include_path(&get_remaining_host_list(run_state), |host| {
+        let rendered = run_state.context.read().unwrap().render_template(&include.file, host, BlendTarget::NotTemplateModule, TemplateMode::Strict)?;
+        return Ok(find_include_path(&rendered, in_role, are_handlers));
+    })?;
+
+    // load every file before running any of them so a bad file stops the play before changes are made
+    let mut loaded : Vec<(&PathBuf, Vec<Task>, &Vec<Arc<RwLock<Host>>>)> = Vec::new();
+    for (path, hosts) in groups.iter() {
+        loaded.push((path, load_task_file(path)?, hosts));
+    }
+
+    let mut survivors : Vec<Arc<RwLock<Host>>> = Vec::new();
+    for (path, tasks, hosts) in loaded.iter() {
+        {
+            let mut ctx = run_state.context.write().unwrap();
+            ctx.push_include_file(path)?;
+            ctx.set_targetted_hosts(hosts);
+        }
+        let result = process_block_tasks(run_state, play, tasks, are_handlers, role_invocation);
+        run_state.context.write().unwrap().pop_include_file();
+        result?;
+        survivors.extend(get_remaining_host_list(run_state));
+    }
+    run_state.context.write().unwrap().set_targetted_hosts(&survivors);
+    return Ok(());
+}
+
+// hosts in the order given, grouped by the include file path chosen for each
+
+fn group_hosts_by_include_path<F>(hosts: &Vec<Arc<RwLock<Host>>>, mut path_for: F) -> Result<Vec<(PathBuf, Vec<Arc<RwLock<Host>>>)>, String>
+    where F: FnMut(&Arc<RwLock<Host>>) -> Result<PathBuf, String> {
+
+    let mut groups : Vec<(PathBuf, Vec<Arc<RwLock<Host>>>)> = Vec::new();
+    for host in hosts.iter() {
+        let path = path_for(host)?;
+        match groups.iter_mut().find(|(p,_)| p.eq(&path)) {
+            Some((_, hosts)) => hosts.push(Arc::clone(host)),
+            None => groups.push((path, vec![Arc::clone(host)]))
+        }
+    }
+    return Ok(groups);
+}
+
+// relative include paths are found in the role's tasks/ (or handlers/) directory when in a role,
+// otherwise next to the playbook.  Traversal has already changed into the role or playbook directory.
+
+fn find_include_path(file: &String, in_role: bool, are_handlers: HandlerMode) -> PathBuf {
+    if file.starts_with("/") {
+        return PathBuf::from(file);
+    }
+    let mut pb = PathBuf::new();
+    if in_role {
+        match are_handlers {
+            HandlerMode::NormalTasks => { pb.push("tasks"); },
+            HandlerMode::Handlers    => { pb.push("handlers"); },
+        };
+    }
+    pb.push(file);
+    return pb;
+}
+
+fn load_task_file(path: &PathBuf) -> Result<Vec<Task>, String> {
+    let task_fh = jet_file_open(&path.as_path())?;
+    let parsed: Result<Vec<Task>, serde_yaml::Error> = serde_yaml::from_reader(task_fh);
+    if parsed.is_err() {
+        show_yaml_error_in_context(&parsed.unwrap_err(), &path.as_path());
+        return Err(format!("edit the file and try again?"));
+    }
+    return Ok(parsed.unwrap());
+}
+
+fn get_remaining_host_list(run_state: &Arc<RunState>) -> Vec<Arc<RwLock<Host>>> {
+    return run_state.context.read().unwrap().get_remaining_hosts().values().map(|x| Arc::clone(x)).collect();
+}
+
+// of the hosts that entered a block, which are still remaining and which have failed
+
+fn split_block_hosts(run_state: &Arc<RunState>, entered: &Vec<Arc<RwLock<Host>>>) -> (Vec<Arc<RwLock<Host>>>, Vec<Arc<RwLock<Host>>>) {
+    let remaining = run_state.context.read().unwrap().get_remaining_hosts();
+    let mut ok : Vec<Arc<RwLock<Host>>> = Vec::new();
+    let mut failed : Vec<Arc<RwLock<Host>>> = Vec::new();
+    for host in entered.iter() {
+        match remaining.contains_key(&host.read().unwrap().name) {
+            true  => ok.push(Arc::clone(host)),
+            false => failed.push(Arc::clone(host))
+        }
+    }
+    return (ok, failed);
+}
+
+fn process_role(run_state: &Arc<RunState>, play: &Play, invocation: &RoleInvocation, are_handlers: HandlerMode,
+    roles_run: &mut HashSet<String>, dependents: &Vec<String>) -> Result<(), String> {
 
     // traversal code for roles.  This is called twice, once for normal tasks and again when processing handler tasks.
+    // roles_run is the roles already processed in this pass, and dependents is the chain of roles that
+    // (through their dependencies) asked for this one, which is empty for roles listed in the play.
 
     // we traverse roles by seeing the 'invocation' in the playbook, which is different from the definition.
     // the definition involves all of the role files in the role directory
     let role_name = invocation.role.clone();
 
+    if dependents.contains(&role_name) {
+        return Err(format!("role dependency loop: {} -> {}", dependents.join(" -> "), role_name));
+    }
+
     // can we find a role directory in the configured role paths?
     let (role, role_path) = find_role(run_state, &play, role_name.clone())?;
+
+    // a role that already ran in this play, whether as a dependency or listed in the play itself,
+    // is not run again unless the role allows it
+    if roles_run.contains(&role_name) && ! role.allow_duplicates.unwrap_or(false) {
+        return Ok(());
+    }
+    roles_run.insert(role_name.clone());
+
+    // dependencies run before the role itself, and pick up the tags of the role that needs them
+    if let Some(dependencies) = &role.dependencies {
+        let mut chain = dependents.clone();
+        chain.push(role_name.clone());
+        for dependency in dependencies.iter() {
+            let mut dependency_invocation = dependency.clone();
+            dependency_invocation.source = Some(role_path.join("role.yml").display().to_string());
+            if let Some(tags) = &invocation.tags {
+                let mut all_tags = dependency.tags.clone().unwrap_or(Vec::new());
+                all_tags.extend(tags.iter().cloned());
+                dependency_invocation.tags = Some(all_tags);
+            }
+            process_role(run_state, play, &dependency_invocation, are_handlers, roles_run, &chain)?;
+        }
+    }
+
     {
         // we're good.
         let mut ctx = run_state.context.write().unwrap();
@@ -343,13 +595,7 @@ fn process_role(run_state: &Arc<RunState>, play: &Play, invocation: &RoleInvocat
 
             // parse the YAML file
 
-            let task_fh = jet_file_open(&task_buf.as_path())?;
-            let parsed: Result<Vec<Task>, serde_yaml::Error> = serde_yaml::from_reader(task_fh);
-            if parsed.is_err() {
-                show_yaml_error_in_context(&parsed.unwrap_err(), &task_buf.as_path());
-                return Err(format!("edit the file and try again?"));
-            }   
-            let tasks = parsed.unwrap();
+            let tasks = load_task_file(&task_buf)?;
             for task in tasks.iter() {
 
                 // process all tasks in the YAML file, this is the same function used
@@ -425,102 +671,43 @@ fn get_host_batches(run_state: &Arc<RunState>, play: &Play, hosts: Vec<Arc<RwLoc
 
 }
 
-fn get_play_hosts(run_state: &Arc<RunState>,play: &Play) -> Vec<Arc<RwLock<Host>>> {
+fn get_play_hosts(run_state: &Arc<RunState>,play: &Play) -> Result<Vec<Arc<RwLock<Host>>>, String> {
 
     // the hosts we want to talk to are the ones specified in the play but may
     // be further constrained by the parameters --limit-hosts and limit--groups
-    // from the CLI.
-    
-    let groups = &play.groups;
-    let mut results : HashMap<String, Arc<RwLock<Host>>> = HashMap::new();
-    
-    let has_group_limits = match run_state.limit_groups.len() {
-        0 => false,
-        _ => true
-    };
-    let has_host_limits = match run_state.limit_hosts.len() {
-        0 => false,
-        _ => true
-    };
-
-    for group in groups.iter() {
-
-        // for each mentioned group get all the hosts in that group and any subgroups
-
-        let group_object = run_state.inventory.read().unwrap().get_group(&group.clone());
-        let hosts = group_object.read().unwrap().get_descendant_hosts();
-        
-        for (k,v) in hosts.iter() {
-
-            // only add the host to the play if it agrees with the limits
-            // or no limits are specified
-        
-            if has_host_limits && ! run_state.limit_hosts.contains(k) {
-                continue;
-            }
-            
-            if has_group_limits {
-                let mut ok = false;
-                for group_name in run_state.limit_groups.iter() {
-                    if v.read().unwrap().has_ancestor_group(group_name) {
-                        ok = true; 
-                        break;
-                    }
-                }
-                if ok {
-                    results.insert(k.clone(), Arc::clone(&v));
-                }
-            } 
-            else {
-                results.insert(k.clone(), Arc::clone(&v));
-            }
-
-        }
-    }
-
-    return results.iter().map(|(_k,v)| Arc::clone(&v)).collect();
-}
-
-fn validate_limit_groups(run_state: &Arc<RunState>, _play: &Play) -> Result<(), String> {
-
-    // limit groups on the command line can't mention any groups that aren't in inventory
+    // from the CLI.  All of these are host patterns, see inventory/patterns.rs, and
+    // mentioning a host or group that is not in inventory is an error.
 
-    let limit_groups = &run_state.limit_groups;
     let inv = run_state.inventory.read().unwrap();
-    for group_name in limit_groups.iter() {
-        if !inv.has_group(&group_name.clone()) {
-            return Err(format!("--limit-groups: at least one referenced group ({}) is not found in inventory", group_name));
-        }
-    }
-    return Ok(());
-}
-
-fn validate_limit_hosts(run_state: &Arc<RunState>, _play: &Play) -> Result<(), String> {
 
-    // limit hosts on the command line can't mention any hosts that aren't in inventory
+    let mut results = match HostPattern::from_list(&play.groups) {
+        Ok(pattern) => pattern.select(&inv, PatternScope::Any)?,
+        Err(y) => { return Err(format!("groups: {}", y)); }
+    };
 
-    let limit_hosts = &run_state.limit_hosts;
-    let inv = run_state.inventory.read().unwrap();
-    for host_name in limit_hosts.iter() {
-        if !inv.has_host(&host_name.clone()) {
-            return Err(format!("--limit-hosts: at least one referenced host ({}) is not found in inventory", host_name));
+    if ! run_state.limit_groups.is_empty() {
+        let limited = match HostPattern::from_list(&run_state.limit_groups) {
+            Ok(pattern) => pattern.select(&inv, PatternScope::Groups),
+            Err(y) => Err(y)
+        };
+        match limited {
+            Ok(limited) => { results.retain(|k,_| limited.contains_key(k)); },
+            Err(y) => { return Err(format!("--limit-groups: {}", y)); }
         }
     }
-    return Ok(());
-}
-
-fn validate_groups(run_state: &Arc<RunState>, play: &Play) -> Result<(), String> {
-
-    // groups on the play can't mention any groups that aren't in inventory
 
-    let groups = &play.groups;
-    let inv = run_state.inventory.read().unwrap();
-    for group_name in groups.iter() {
-        if !inv.has_group(&group_name.clone()) {
-            return Err(format!("at least one referenced group ({}) is not found in inventory", group_name));
+    if ! run_state.limit_hosts.is_empty() {
+        let limited = match HostPattern::from_list(&run_state.limit_hosts) {
+            Ok(pattern) => pattern.select(&inv, PatternScope::Hosts),
+            Err(y) => Err(y)
+        };
+        match limited {
+            Ok(limited) => { results.retain(|k,_| limited.contains_key(k)); },
+            Err(y) => { return Err(format!("--limit-hosts: {}", y)); }
         }
     }
-    return Ok(());
+
+    return Ok(results.iter().map(|(_k,v)| Arc::clone(&v)).collect());
 }
 
 fn validate_hosts(_run_state: &Arc<RunState>, _play: &Play, hosts: &Vec<Arc<RwLock<Host>>>) -> Result<(), String> {
@@ -534,20 +721,24 @@ fn validate_hosts(_run_state: &Arc<RunState>, _play: &Play, hosts: &Vec<Arc<RwLo
     return Ok(());
 }
 
-fn load_vars_into_context(run_state: &Arc<RunState>, play: &Play) -> Result<(), String> {
+pub fn load_vars_into_context(context: &Arc<RwLock<PlaybookContext>>, play: &Play) -> Result<(), String> {
 
     // the context object is fairly pervasive throughout the running of the program
     // and is (eventually) the gateway that template requests pass through, since
     // it holds on to losts of play and role variables. This function loads
-    // a lot of the variables into the context ensuring proper variable precedence
+    // a lot of the variables into the context ensuring proper variable precedence.
+    // also used by show-vars, which is why it does not take the run state.
 
-    let ctx = run_state.context.write().unwrap();
+    let ctx = context.write().unwrap();
     let mut ctx_vars_storage = serde_yaml::Value::from(serde_yaml::Mapping::new());
     let mut ctx_defaults_storage = serde_yaml::Value::from(serde_yaml::Mapping::new());
+    let mut ctx_vars_sources : HashMap<String,String> = HashMap::new();
+    let playbook = ctx.playbook_path.clone().unwrap_or(String::from("playbook"));
     
     if play.vars.is_some() {
         // vars are inline variables that are loaded at maximum precedence
         let vars = play.vars.as_ref().unwrap();
+        record_variable_sources(&mut ctx_vars_sources, vars, &playbook);
         blend_variables(&mut ctx_vars_storage, serde_yaml::Value::Mapping(vars.clone()));
     }
 
@@ -556,13 +747,14 @@ fn load_vars_into_context(run_state: &Arc<RunState>, play: &Play) -> Result<(),
         let vars_files = play.vars_files.as_ref().unwrap();
         for pathname in vars_files {
             let path = Path::new(&pathname);
-            let vars_file = jet_file_open(&path)?;
-            let parsed: Result<serde_yaml::Mapping, serde_yaml::Error> = serde_yaml::from_reader(vars_file);
-            if parsed.is_err() {
-                show_yaml_error_in_context(&parsed.unwrap_err(), &path);
-                return Err(format!("edit the file and try again?"));
-            }
-            blend_variables(&mut ctx_vars_storage, serde_yaml::Value::Mapping(parsed.unwrap()));
+            // the file, or values inside it, may be vault encrypted
+            let vars = load_vars_file(&path)?;
+            let source = match fs::canonicalize(&path) {
+                Ok(x) => x.display().to_string(),
+                Err(_) => pathname.clone()
+            };
+            record_variable_sources(&mut ctx_vars_sources, &vars, &source);
+            blend_variables(&mut ctx_vars_storage, serde_yaml::Value::Mapping(vars));
         }
     }
 
@@ -577,6 +769,7 @@ fn load_vars_into_context(run_state: &Arc<RunState>, play: &Play) -> Result<(),
         serde_yaml::Value::Mapping(x) => { *ctx.vars_storage.write().unwrap() = x },
         _ => panic!("unexpected, get_blended_variables produced a non-mapping (1)")
     }
+    *ctx.vars_sources.write().unwrap() = ctx_vars_sources;
     match ctx_defaults_storage {
         serde_yaml::Value::Mapping(x) => { *ctx.defaults_storage.write().unwrap() = x },
         _ => panic!("unexpected, get_blended_variables produced a non-mapping (1)")
@@ -610,7 +803,15 @@ fn find_role(run_state: &Arc<RunState>, _play: &Play, role_name: String) -> Resu
                 show_yaml_error_in_context(&parsed.unwrap_err(), &path);
                 return Err(format!("edit the file and try again?"));
             }   
-            let role = parsed.unwrap();
+            let mut role = parsed.unwrap();
+
+            // the vars/ and defaults/ directories of a role may hold any number of variable files,
+            // which are loaded in name order.  Defaults from files override those in role.yml.
+            if let Some(defaults) = &role.defaults {
+                record_variable_sources(&mut role.defaults_sources, defaults, &pb2.display().to_string());
+            }
+            role.vars = load_role_variables(&pb, "vars", &None, &mut role.vars_sources)?;
+            role.defaults = load_role_variables(&pb, "defaults", &role.defaults, &mut role.defaults_sources)?;
             
             return Ok((role,pb));
         }
@@ -618,4 +819,238 @@ fn find_role(run_state: &Arc<RunState>, _play: &Play, role_name: String) -> Resu
     return Err(format!("role not found: {}", role_name));
 }  
 
+fn load_role_variables(role_path: &PathBuf, subdirectory: &str, initial: &Option<serde_yaml::Mapping>, sources: &mut HashMap<String,String>)
+    -> Result<Option<serde_yaml::Mapping>, String> {
+
+    let mut dir = role_path.clone();
+    dir.push(subdirectory);
+    if ! dir.is_dir() {
+        return Ok(initial.clone());
+    }
+
+    let mut paths : Vec<PathBuf> = Vec::new();
+    let entries = match fs::read_dir(&dir) {
+        Ok(x) => x,
+        Err(y) => { return Err(format!("unable to read role directory {}: {}", dir.display(), y)); }
+    };
+    for entry in entries {
+        let path = match entry {
+            Ok(x) => x.path(),
+            Err(y) => { return Err(format!("unable to read role directory {}: {}", dir.display(), y)); }
+        };
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("yml") | Some("yaml") => { paths.push(path); },
+            _ => {}
+        }
+    }
+    paths.sort();
+
+    let mut blended = serde_yaml::Value::Mapping(initial.clone().unwrap_or(serde_yaml::Mapping::new()));
+    for path in paths.iter() {
+        // the file, or values inside it, may be vault encrypted
+        let vars = load_vars_file(&path)?;
+        record_variable_sources(sources, &vars, &path.display().to_string());
+        blend_variables(&mut blended, serde_yaml::Value::Mapping(vars));
+    }
+    return match blended {
+        serde_yaml::Value::Mapping(x) => Ok(Some(x)),
+        _ => panic!("unexpected, blend_variables produced a non-mapping")
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::parser::CliParser;
+    use crate::connection::local::LocalFactory;
+    use crate::playbooks::visitor::CheckMode;
+    use guid_create::GUID;
+    use std::sync::Mutex;
+
+    // traversal changes the working directory of the process, so only one playbook runs at a time
+    static TRAVERSAL_LOCK: Mutex<()> = Mutex::new(());
+
+    fn host(name: &str) -> Arc<RwLock<Host>> {
+        return Arc::new(RwLock::new(Host::new(&String::from(name))));
+    }
+
+    fn test_dir() -> PathBuf {
+        let dir = env::temp_dir().join(format!("jetp-traversal-test-{}", GUID::rand().to_string()));
+        fs::create_dir_all(&dir).unwrap();
+        return dir;
+    }
+
+    fn write_file(dir: &PathBuf, name: &str, contents: &str) {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+    }
 
+    fn read_lines(path: &PathBuf) -> Vec<String> {
+        return match fs::read_to_string(path) {
+            Ok(x) => x.lines().map(|l| l.to_string()).collect(),
+            Err(_) => Vec::new()
+        };
+    }
+
+    // runs dir/playbook.yml with the local connection against hosts in the group 'web', given as
+    // a name and YAML variables.  Roles are found in dir/roles.
+
+    fn run_playbook(dir: &PathBuf, hosts: &Vec<(&str, &str)>) -> (Result<(), String>, Arc<RunState>) {
+        let _lock = TRAVERSAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        env::set_var("JET_LOG", "/dev/null");
+        let inventory : Arc<RwLock<Inventory>> = Arc::new(RwLock::new(Inventory::new()));
+        {
+            let mut inv = inventory.write().unwrap();
+            inv.store_host(&String::from("all"), &String::from("localhost"));
+            for (name, vars) in hosts.iter() {
+                inv.store_host(&String::from("web"), &String::from(*name));
+                inv.get_host(&String::from(*name)).write().unwrap().set_variables(serde_yaml::from_str(vars).unwrap());
+            }
+        }
+        let parser = CliParser::new();
+        let run_state = Arc::new(RunState {
+            inventory: Arc::clone(&inventory),
+            playbook_paths: Arc::new(RwLock::new(vec![dir.join("playbook.yml")])),
+            role_paths: Arc::new(RwLock::new(vec![dir.join("roles")])),
+            module_paths: Arc::new(RwLock::new(Vec::new())),
+            limit_hosts: Vec::new(),
+            limit_groups: Vec::new(),
+            batch_size: None,
+            context: Arc::new(RwLock::new(PlaybookContext::new(&parser))),
+            visitor: Arc::new(RwLock::new(MultiVisitor::new(CheckMode::No, false, &Vec::new(), &None).unwrap())),
+            connection_factory: Arc::new(RwLock::new(LocalFactory::new(&inventory))),
+            tags: None,
+            allow_localhost_delegation: false
+        });
+        let result = playbook_traversal(&run_state);
+        return (result, run_state);
+    }
+
+    // a role whose tasks append a line to dir/log
+    fn write_role(dir: &PathBuf, name: &str, role_yml: &str, line: &str) {
+        write_file(dir, &format!("roles/{}/role.yml", name), &format!("name: {}\ntasks: [ main.yml ]\n{}", name, role_yml));
+        write_file(dir, &format!("roles/{}/tasks/main.yml", name), &format!("- !shell\n  cmd: \"echo {} >> {{{{ out }}}}/log\"\n  unsafe: true\n", line));
+    }
+
+    #[test]
+    fn test_find_include_path() {
+        let file = String::from("setup.yml");
+        assert_eq!(find_include_path(&file, false, HandlerMode::NormalTasks), PathBuf::from("setup.yml"));
+        assert_eq!(find_include_path(&file, false, HandlerMode::Handlers), PathBuf::from("setup.yml"));
+        assert_eq!(find_include_path(&file, true, HandlerMode::NormalTasks), PathBuf::from("tasks/setup.yml"));
+        assert_eq!(find_include_path(&file, true, HandlerMode::Handlers), PathBuf::from("handlers/setup.yml"));
+        let absolute = String::from("/srv/tasks/setup.yml");
+        assert_eq!(find_include_path(&absolute, true, HandlerMode::NormalTasks), PathBuf::from("/srv/tasks/setup.yml"));
+    }
+
+    #[test]
+    fn test_group_hosts_by_include_path() {
+        let hosts = vec![host("web1"), host("db1"), host("web2"), host("db2"), host("cache1")];
+        let groups = group_hosts_by_include_path(&hosts, |host| {
+            let name = host.read().unwrap().name.clone();
+            return Ok(PathBuf::from(format!("{}.yml", name.trim_end_matches(char::is_numeric))));
+        }).unwrap();
+
+        // groups keep the order in which their first host was seen, and hosts keep their order within a group
+        let names : Vec<(String, Vec<String>)> = groups.iter().map(|(path, hosts)| {
+            (path.display().to_string(), hosts.iter().map(|h| h.read().unwrap().name.clone()).collect())
+        }).collect();
+        assert_eq!(names, vec![
+            (String::from("web.yml"), vec![String::from("web1"), String::from("web2")]),
+            (String::from("db.yml"), vec![String::from("db1"), String::from("db2")]),
+            (String::from("cache.yml"), vec![String::from("cache1")]),
+        ]);
+
+        // a template that fails for any host stops the include
+        let result = group_hosts_by_include_path(&hosts, |host| {
+            match host.read().unwrap().name.starts_with("db") {
+                true => Err(String::from("undefined variable")),
+                false => Ok(PathBuf::from("web.yml"))
+            }
+        });
+        assert_eq!(result.err(), Some(String::from("undefined variable")));
+    }
+
+    #[test]
+    fn test_role_dependencies() {
+        let dir = test_dir();
+        write_role(&dir, "base", "", "base");
+        write_role(&dir, "certs", "dependencies:\n  - role: base\n", "certs");
+        write_role(&dir, "webapp", "defaults:\n  port: 8080\n  user: www\ndependencies:\n  - role: certs\n  - role: base\n", "webapp {{ greeting }} {{ port }} {{ user }}");
+        write_file(&dir, "roles/webapp/vars/main.yml", "greeting: hello\n");
+        write_file(&dir, "roles/webapp/defaults/main.yml", "port: 80\n");
+        // base already ran as a dependency, so listing it in the play does not run it again
+        write_file(&dir, "playbook.yml", "- name: roles\n  groups: [ web ]\n  roles:\n    - role: webapp\n    - role: base\n");
+
+        let out = format!("out: {}", dir.display());
+        let (result, _) = run_playbook(&dir, &vec![("web1", &out)]);
+        assert_eq!(result, Ok(()));
+        assert_eq!(read_lines(&dir.join("log")), vec![
+            String::from("base"),
+            String::from("certs"),
+            String::from("webapp hello 80 www"),
+        ]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_role_allow_duplicates() {
+        let dir = test_dir();
+        write_role(&dir, "base", "allow_duplicates: true\n", "base");
+        write_role(&dir, "certs", "dependencies:\n  - role: base\n", "certs");
+        write_file(&dir, "playbook.yml", "- name: roles\n  groups: [ web ]\n  roles:\n    - role: certs\n    - role: base\n");
+
+        let out = format!("out: {}", dir.display());
+        let (result, _) = run_playbook(&dir, &vec![("web1", &out)]);
+        assert_eq!(result, Ok(()));
+        assert_eq!(read_lines(&dir.join("log")), vec![String::from("base"), String::from("certs"), String::from("base")]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_role_dependency_loop() {
+        let dir = test_dir();
+        write_role(&dir, "a", "dependencies:\n  - role: b\n", "a");
+        write_role(&dir, "b", "dependencies:\n  - role: a\n", "b");
+        write_file(&dir, "playbook.yml", "- name: roles\n  groups: [ web ]\n  roles:\n    - role: a\n");
+
+        let out = format!("out: {}", dir.display());
+        let (result, _) = run_playbook(&dir, &vec![("web1", &out)]);
+        assert_eq!(result, Err(String::from("role dependency loop: a -> b -> a")));
+        assert!(read_lines(&dir.join("log")).is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_block_rescue_and_always() {
+        let dir = test_dir();
+        let record = |line: &str| format!("    - !shell\n      cmd: \"echo {} >> {{{{ out }}}}/{{{{ label }}}}\"\n      unsafe: true\n", line);
+        let playbook = format!("- name: blocks\n  groups: [ web ]\n  tasks:\n  - !block\n    tasks:\n{}    - !fail\n      with:\n        condition: (eq fail_block \"yes\")\n    rescue:\n{}    - !fail\n      with:\n        condition: (eq fail_rescue \"yes\")\n    always:\n{}  - !shell\n    cmd: \"echo after >> {{{{ out }}}}/{{{{ label }}}}\"\n    unsafe: true\n",
+            record("block"), record("rescue"), record("always"));
+        write_file(&dir, "playbook.yml", &playbook);
+
+        let vars = |label: &str, fail_block: &str, fail_rescue: &str| format!("out: {}\nlabel: {}\nfail_block: \"{}\"\nfail_rescue: \"{}\"\n", dir.display(), label, fail_block, fail_rescue);
+        let (recovers, stays_failed, never_fails) = (vars("recovers", "yes", "no"), vars("stays_failed", "yes", "yes"), vars("never_fails", "no", "no"));
+        let (result, run_state) = run_playbook(&dir, &vec![("web1", &recovers), ("web2", &stays_failed), ("web3", &never_fails)]);
+        assert_eq!(result, Ok(()));
+
+        // rescue only runs where the block failed, always runs everywhere, and a host that failed
+        // in rescue as well is failed again after always, so it runs nothing more
+        let lines = |name: &str| read_lines(&dir.join(name));
+        assert_eq!(lines("recovers"), vec![String::from("block"), String::from("rescue"), String::from("always"), String::from("after")]);
+        assert_eq!(lines("stays_failed"), vec![String::from("block"), String::from("rescue"), String::from("always")]);
+        assert_eq!(lines("never_fails"), vec![String::from("block"), String::from("always"), String::from("after")]);
+
+        let ctx = run_state.context.read().unwrap();
+        let remaining = ctx.get_remaining_hosts();
+        assert!(remaining.contains_key(&String::from("web1")));
+        assert!(! remaining.contains_key(&String::from("web2")));
+        assert!(remaining.contains_key(&String::from("web3")));
+        assert_eq!(ctx.get_failed_count_for_host(&String::from("web1")), 0);
+        assert!(ctx.get_failed_count_for_host(&String::from("web2")) > 0);
+        assert_eq!(ctx.get_failed_count_for_host(&String::from("web3")), 0);
+        drop(ctx);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -36,19 +36,28 @@ use crate::modules::commands::shell::ShellTask;
 
 // control
 use crate::modules::control::assert::AssertTask;
+use crate::modules::control::block::BlockTask;
 use crate::modules::control::debug::DebugTask;
 use crate::modules::control::echo::EchoTask;
 use crate::modules::control::fail::FailTask;
 use crate::modules::control::facts::FactsTask;
+use crate::modules::control::include::IncludeTask;
 use crate::modules::control::set::SetTask;
 
 // files
+use crate::modules::files::archive::ArchiveTask;
+use crate::modules::files::blockinfile::BlockInFileTask;
 use crate::modules::files::copy::CopyTask;
 use crate::modules::files::directory::DirectoryTask;
+use crate::modules::files::fetch::FetchTask;
 use crate::modules::files::file::FileTask;
 use crate::modules::files::git::GitTask;
+use crate::modules::files::lineinfile::LineInFileTask;
+use crate::modules::files::link::LinkTask;
 use crate::modules::files::stat::StatTask;
+use crate::modules::files::sync::SyncTask;
 use crate::modules::files::template::TemplateTask;
+use crate::modules::files::unarchive::UnarchiveTask;
 
 // packages
 use crate::modules::packages::apt::AptTask;
@@ -66,7 +75,10 @@ use crate::modules::services::sd_service::SystemdServiceTask;
 pub enum Task {
     // ADD NEW MODULES HERE, KEEP ALPHABETIZED BY NAME
     Apt(AptTask),
+    Archive(ArchiveTask),
     Assert(AssertTask),
+    Block(BlockTask),
+    BlockInFile(BlockInFileTask),
     Copy(CopyTask),
     Debug(DebugTask),
     Directory(DirectoryTask),
@@ -75,16 +87,22 @@ pub enum Task {
     External(ExternalTask),
     Facts(FactsTask),
     Fail(FailTask),
+    Fetch(FetchTask),
     File(FileTask),
     Git(GitTask),
     Group(GroupTask),
     Homebrew(HomebrewTask),
+    Include(IncludeTask),
+    LineInFile(LineInFileTask),
+    Link(LinkTask),
     Pacman(PacmanTask),
     Sd_Service(SystemdServiceTask),
     Set(SetTask),
     Shell(ShellTask),
     Stat(StatTask),
+    Sync(SyncTask),
     Template(TemplateTask),
+    Unarchive(UnarchiveTask),
     User(UserTask),
     Yum(YumDnfTask),
     Zypper(ZypperTask),
@@ -96,7 +114,10 @@ impl Task {
         // ADD NEW MODULES HERE, KEEP ALPHABETIZED BY NAME
         return match self {
             Task::Apt(x)        => x.get_module(),
+            Task::Archive(x)    => x.get_module(),
             Task::Assert(x)     => x.get_module(),
+            Task::Block(x)      => x.get_module(),
+            Task::BlockInFile(x) => x.get_module(),
             Task::Copy(x)       => x.get_module(),
             Task::Debug(x)      => x.get_module(),
             Task::Directory(x)  => x.get_module(),
@@ -105,16 +126,22 @@ impl Task {
             Task::External(x)   => x.get_module(),
             Task::Facts(x)      => x.get_module(), 
             Task::Fail(x)       => x.get_module(), 
+            Task::Fetch(x)      => x.get_module(),
             Task::File(x)       => x.get_module(),
             Task::Git(x)        => x.get_module(), 
             Task::Group(x)      => x.get_module(),
             Task::Homebrew(x)   => x.get_module(),
+            Task::Include(x)    => x.get_module(),
+            Task::LineInFile(x) => x.get_module(),
+            Task::Link(x)       => x.get_module(),
             Task::Pacman(x)     => x.get_module(),
             Task::Sd_Service(x) => x.get_module(),
             Task::Set(x)        => x.get_module(), 
             Task::Shell(x)      => x.get_module(), 
             Task::Stat(x)       => x.get_module(), 
+            Task::Sync(x)       => x.get_module(), 
             Task::Template(x)   => x.get_module(), 
+            Task::Unarchive(x)  => x.get_module(), 
             Task::User(x)       => x.get_module(),
             Task::Yum(x)        => x.get_module(),
             Task::Zypper(x)     => x.get_module(),
@@ -125,7 +152,10 @@ impl Task {
         // ADD NEW MODULES HERE, KEEP ALPHABETIZED BY NAME
         return match self {
             Task::Apt(x)        => x.get_name(),
+            Task::Archive(x)    => x.get_name(),
             Task::Assert(x)     => x.get_name(),
+            Task::Block(x)      => x.get_name(),
+            Task::BlockInFile(x) => x.get_name(),
             Task::Copy(x)       => x.get_name(),
             Task::Debug(x)      => x.get_name(), 
             Task::Directory(x)  => x.get_name(),
@@ -134,16 +164,22 @@ impl Task {
             Task::External(x)   => x.get_name(),
             Task::Facts(x)      => x.get_name(),
             Task::Fail(x)       => x.get_name(), 
+            Task::Fetch(x)      => x.get_name(), 
             Task::File(x)       => x.get_name(), 
             Task::Git(x)        => x.get_name(),
             Task::Group(x)      => x.get_name(),
             Task::Homebrew(x)   => x.get_name(),
+            Task::Include(x)    => x.get_name(),
+            Task::LineInFile(x) => x.get_name(),
+            Task::Link(x)       => x.get_name(),
             Task::Pacman(x)     => x.get_name(),
             Task::Sd_Service(x) => x.get_name(),
             Task::Set(x)        => x.get_name(),
             Task::Shell(x)      => x.get_name(), 
             Task::Stat(x)       => x.get_name(),
+            Task::Sync(x)       => x.get_name(),
             Task::Template(x)   => x.get_name(), 
+            Task::Unarchive(x)  => x.get_name(), 
             Task::User(x)       => x.get_name(),
             Task::Yum(x)        => x.get_name(),
             Task::Zypper(x)     => x.get_name(),
@@ -154,7 +190,10 @@ impl Task {
         // ADD NEW MODULES HERE, KEEP ALPHABETIZED BY NAME
         return match self {
             Task::Apt(x)        => x.get_with(),
+            Task::Archive(x)    => x.get_with(),
             Task::Assert(x)     => x.get_with(),
+            Task::Block(x)      => x.get_with(),
+            Task::BlockInFile(x) => x.get_with(),
             Task::Copy(x)       => x.get_with(),
             Task::Debug(x)      => x.get_with(), 
             Task::Directory(x)  => x.get_with(),
@@ -163,16 +202,22 @@ impl Task {
             Task::External(x)   => x.get_with(),
             Task::Facts(x)      => x.get_with(),
             Task::Fail(x)       => x.get_with(), 
+            Task::Fetch(x)      => x.get_with(),
             Task::File(x)       => x.get_with(),
             Task::Git(x)        => x.get_with(), 
             Task::Group(x)      => x.get_with(),
             Task::Homebrew(x)   => x.get_with(),
+            Task::Include(x)    => x.get_with(),
+            Task::LineInFile(x) => x.get_with(),
+            Task::Link(x)       => x.get_with(),
             Task::Pacman(x)     => x.get_with(),
             Task::Sd_Service(x) => x.get_with(),
             Task::Set(x)        => x.get_with(),
             Task::Shell(x)      => x.get_with(), 
             Task::Stat(x)       => x.get_with(), 
+            Task::Sync(x)       => x.get_with(), 
             Task::Template(x)   => x.get_with(),
+            Task::Unarchive(x)  => x.get_with(),
             Task::User(x)       => x.get_with(),
             Task::Yum(x)        => x.get_with(), 
             Task::Zypper(x)     => x.get_with(),
@@ -183,7 +228,10 @@ impl Task {
         // ADD NEW MODULES HERE, KEEP ALPHABETIZED BY NAME
         return match self {
             Task::Apt(x)        => x.evaluate(handle, request, tm),
+            Task::Archive(x)    => x.evaluate(handle, request, tm),
             Task::Assert(x)     => x.evaluate(handle, request, tm),
+            Task::Block(x)      => x.evaluate(handle, request, tm),
+            Task::BlockInFile(x) => x.evaluate(handle, request, tm),
             Task::Copy(x)       => x.evaluate(handle, request, tm),
             Task::Debug(x)      => x.evaluate(handle, request, tm), 
             Task::Directory(x)  => x.evaluate(handle, request, tm), 
@@ -192,16 +240,22 @@ impl Task {
             Task::External(x)   => x.evaluate(handle, request, tm),
             Task::Facts(x)      => x.evaluate(handle, request, tm),
             Task::Fail(x)       => x.evaluate(handle, request, tm),  
+            Task::Fetch(x)      => x.evaluate(handle, request, tm), 
             Task::File(x)       => x.evaluate(handle, request, tm), 
             Task::Git(x)        => x.evaluate(handle, request, tm),
             Task::Group(x)      => x.evaluate(handle, request, tm),
             Task::Homebrew(x)   => x.evaluate(handle, request, tm),
+            Task::Include(x)    => x.evaluate(handle, request, tm),
+            Task::LineInFile(x) => x.evaluate(handle, request, tm),
+            Task::Link(x)       => x.evaluate(handle, request, tm),
             Task::Pacman(x)     => x.evaluate(handle, request, tm),
             Task::Sd_Service(x) => x.evaluate(handle, request, tm),
             Task::Set(x)        => x.evaluate(handle, request, tm),
             Task::Shell(x)      => x.evaluate(handle, request, tm), 
             Task::Stat(x)       => x.evaluate(handle, request, tm),
+            Task::Sync(x)       => x.evaluate(handle, request, tm),
             Task::Template(x)   => x.evaluate(handle, request, tm), 
+            Task::Unarchive(x)  => x.evaluate(handle, request, tm), 
             Task::User(x)       => x.evaluate(handle, request, tm),
             Task::Yum(x)        => x.evaluate(handle, request, tm), 
             Task::Zypper(x)     => x.evaluate(handle, request, tm), 
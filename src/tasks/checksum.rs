@@ -17,8 +17,12 @@
 use sha2::{Sha512, Digest};
 
 pub fn sha512(data: &String) -> String {
+    return sha512_bytes(data.as_bytes());
+}
+
+pub fn sha512_bytes(data: &[u8]) -> String {
     let mut hasher = Sha512::new();
-    hasher.update(data.as_bytes());
+    hasher.update(data);
     let result = hasher.finalize();
     format!("{result:x}")
 }
@@ -19,7 +19,7 @@
 
 use crate::inventory::hosts::HostOSType;
 use crate::tasks::FileAttributesInput;
-use crate::tasks::files::Recurse;
+use crate::tasks::files::{Recurse,ArchiveFormat};
 
 // **IMPORTANT**
 //
@@ -99,6 +99,14 @@ pub fn get_sha512_command(os_type: HostOSType, untrusted_path: &String) -> Resul
     }
 }
 
+pub fn get_size_command(os_type: HostOSType, untrusted_path: &String) -> Result<String,String>  {
+    let path = screen_path(untrusted_path)?;
+    return match os_type {
+        HostOSType::Linux => Ok(format!("stat --format '%s' '{}'", path)),
+        HostOSType::MacOS => Ok(format!("stat -f '%z' '{}'", path)),
+    }
+}
+
 pub fn get_ownership_command(_os_type: HostOSType, untrusted_path: &String) -> Result<String,String>  {
     let path = screen_path(untrusted_path)?;
     return Ok(format!("ls -ld '{}'", path));
@@ -119,6 +127,122 @@ pub fn get_create_directory_command(_os_type: HostOSType, untrusted_path: &Strin
     return Ok(format!("mkdir -p '{}'", path));
 }
 
+// both paths are screened, the backup path is built from the original one
+
+pub fn get_backup_command(_os_type: HostOSType, untrusted_path: &String, untrusted_backup_path: &String) -> Result<String,String>  {
+    let path = screen_path(untrusted_path)?;
+    let backup_path = screen_path(untrusted_backup_path)?;
+    return Ok(format!("cp -p '{}' '{}'", path, backup_path));
+}
+
+// prints the target of a symbolic link, and fails for anything that is not one
+
+pub fn get_link_target_command(_os_type: HostOSType, untrusted_path: &String) -> Result<String,String>  {
+    let path = screen_path(untrusted_path)?;
+    return Ok(format!("readlink '{}'", path));
+}
+
+// device and inode number, two paths with the same values are hard links to the same file
+
+pub fn get_inode_command(os_type: HostOSType, untrusted_path: &String) -> Result<String,String>  {
+    let path = screen_path(untrusted_path)?;
+    return match os_type {
+        HostOSType::Linux => Ok(format!("stat --format '%d:%i' '{}'", path)),
+        HostOSType::MacOS => Ok(format!("stat -f '%d:%i' '{}'", path)),
+    }
+}
+
+// with force, an existing file (or link) at the path is replaced.  -n keeps ln from following
+// an existing link to a directory and creating the new link inside of it.
+
+pub fn get_create_link_command(_os_type: HostOSType, untrusted_target: &String, untrusted_path: &String, symbolic: bool, force: bool) -> Result<String,String>  {
+    let target = screen_path(untrusted_target)?;
+    let path = screen_path(untrusted_path)?;
+    let flags = match (symbolic, force) {
+        (true, true)   => " -sfn",
+        (true, false)  => " -s",
+        (false, true)  => " -f",
+        (false, false) => ""
+    };
+    return Ok(format!("ln{} '{}' '{}'", flags, target, path));
+}
+
+// lists everything below a path, one per line, either just the directories or everything else
+
+pub fn get_find_command(_os_type: HostOSType, untrusted_path: &String, directories: bool) -> Result<String,String>  {
+    let path = screen_path(untrusted_path)?;
+    return match directories {
+        true  => Ok(format!("find '{}' -mindepth 1 -type d", path)),
+        false => Ok(format!("find '{}' -mindepth 1 ! -type d", path)),
+    }
+}
+
+pub fn get_copy_command(_os_type: HostOSType, untrusted_path: &String, untrusted_dest: &String) -> Result<String,String>  {
+    let path = screen_path(untrusted_path)?;
+    let dest = screen_path(untrusted_dest)?;
+    return Ok(format!("cp '{}' '{}'", path, dest));
+}
+
+pub fn get_move_command(_os_type: HostOSType, untrusted_path: &String, untrusted_dest: &String) -> Result<String,String>  {
+    let path = screen_path(untrusted_path)?;
+    let dest = screen_path(untrusted_dest)?;
+    return Ok(format!("mv '{}' '{}'", path, dest));
+}
+
+// prints anything below path (or path itself) modified more recently than the reference file
+
+pub fn get_find_newer_command(_os_type: HostOSType, untrusted_path: &String, untrusted_reference: &String) -> Result<String,String>  {
+    let path = screen_path(untrusted_path)?;
+    let reference = screen_path(untrusted_reference)?;
+    return Ok(format!("find '{}' -newer '{}'", path, reference));
+}
+
+// tar and bsdtar both take --strip-components, unzip has no equivalent
+
+pub fn get_extract_command(_os_type: HostOSType, format: ArchiveFormat, untrusted_archive: &String, untrusted_dest: &String, strip_components: u64) -> Result<String,String>  {
+    let archive = screen_path(untrusted_archive)?;
+    let dest = screen_path(untrusted_dest)?;
+    let flag = match format {
+        ArchiveFormat::Tar   => "",
+        ArchiveFormat::TarGz => "z",
+        ArchiveFormat::TarXz => "J",
+        ArchiveFormat::Zip   => {
+            if strip_components > 0 {
+                return Err(String::from("strip_components is not supported for zip files"));
+            }
+            return Ok(format!("unzip -o -q '{}' -d '{}'", archive, dest));
+        }
+    };
+    return Ok(format!("tar -x{}f '{}' --strip-components {} -C '{}'", flag, archive, strip_components, dest));
+}
+
+// each path is stored under its own name, so packing /var/www/app gives entries starting with app/
+
+pub fn get_create_archive_command(_os_type: HostOSType, format: ArchiveFormat, untrusted_dest: &String, untrusted_paths: &Vec<String>) -> Result<String,String>  {
+    let dest = screen_path(untrusted_dest)?;
+    let flag = match format {
+        ArchiveFormat::Tar   => "",
+        ArchiveFormat::TarGz => "z",
+        ArchiveFormat::TarXz => "J",
+        ArchiveFormat::Zip   => { return Err(String::from("the archive module only creates tarballs")); }
+    };
+    let mut cmd = format!("tar -c{}f '{}'", flag, dest);
+    for untrusted_path in untrusted_paths.iter() {
+        let path = screen_path(untrusted_path)?;
+        let trimmed = path.trim_end_matches('/');
+        let (parent, name) = match trimmed.rfind('/') {
+            Some(0) => ("/", &trimmed[1..]),
+            Some(x) => (&trimmed[..x], &trimmed[x+1..]),
+            None => (".", trimmed)
+        };
+        if name.is_empty() {
+            return Err(format!("cannot archive: {}", path));
+        }
+        cmd.push_str(&format!(" -C '{}' '{}'", parent, name));
+    }
+    return Ok(cmd);
+}
+
 pub fn get_delete_file_command(_os_type: HostOSType, untrusted_path: &String) -> Result<String,String>  {
     let path = screen_path(untrusted_path)?;
     return Ok(format!("rm -f '{}'", path));
@@ -141,6 +265,20 @@ pub fn set_owner_command(_os_type: HostOSType, untrusted_path: &String, untruste
     }
 }
 
+// the -h variants change a symbolic link itself rather than the file it points to
+
+pub fn set_link_owner_command(_os_type: HostOSType, untrusted_path: &String, untrusted_owner: &String) -> Result<String,String> {
+    let path = screen_path(untrusted_path)?;
+    let owner = screen_general_input_strict(untrusted_owner)?;
+    return Ok(format!("chown -h '{}' '{}'", owner, path));
+}
+
+pub fn set_link_group_command(_os_type: HostOSType, untrusted_path: &String, untrusted_group: &String) -> Result<String,String> {
+    let path = screen_path(untrusted_path)?;
+    let group = screen_general_input_strict(untrusted_group)?;
+    return Ok(format!("chgrp -h '{}' '{}'", group, path));
+}
+
 pub fn set_group_command(_os_type: HostOSType, untrusted_path: &String, untrusted_group: &String, recurse: Recurse) -> Result<String,String> {
     let path = screen_path(untrusted_path)?;
     let group = screen_general_input_strict(untrusted_group)?;
@@ -167,6 +305,54 @@ pub fn get_arch_command(os_type: HostOSType) -> Result<String,String> {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    #[test]
+    fn test_link_commands() {
+        let target = String::from("/opt/app/releases/2");
+        let path = String::from("/opt/app/current");
+        assert_eq!(get_create_link_command(HostOSType::Linux, &target, &path, true, false), Ok(String::from("ln -s '/opt/app/releases/2' '/opt/app/current'")));
+        assert_eq!(get_create_link_command(HostOSType::Linux, &target, &path, true, true), Ok(String::from("ln -sfn '/opt/app/releases/2' '/opt/app/current'")));
+        assert_eq!(get_create_link_command(HostOSType::Linux, &target, &path, false, false), Ok(String::from("ln '/opt/app/releases/2' '/opt/app/current'")));
+        assert_eq!(get_create_link_command(HostOSType::MacOS, &target, &path, false, true), Ok(String::from("ln -f '/opt/app/releases/2' '/opt/app/current'")));
+        assert!(get_create_link_command(HostOSType::Linux, &String::from("/tmp/a;rm"), &path, true, false).is_err());
+        assert_eq!(get_link_target_command(HostOSType::Linux, &path), Ok(String::from("readlink '/opt/app/current'")));
+        assert_eq!(get_inode_command(HostOSType::Linux, &path), Ok(String::from("stat --format '%d:%i' '/opt/app/current'")));
+        assert_eq!(get_inode_command(HostOSType::MacOS, &path), Ok(String::from("stat -f '%d:%i' '/opt/app/current'")));
+        assert_eq!(set_link_owner_command(HostOSType::Linux, &path, &String::from("www")), Ok(String::from("chown -h 'www' '/opt/app/current'")));
+        assert_eq!(set_link_group_command(HostOSType::Linux, &path, &String::from("www")), Ok(String::from("chgrp -h 'www' '/opt/app/current'")));
+        assert!(set_link_owner_command(HostOSType::Linux, &path, &String::from("$(id)")).is_err());
+    }
 
+    #[test]
+    fn test_extract_command() {
+        let archive = String::from("/tmp/app.tar.gz");
+        let dest = String::from("/opt/app");
+        assert_eq!(get_extract_command(HostOSType::Linux, ArchiveFormat::Tar, &archive, &dest, 0),
+            Ok(String::from("tar -xf '/tmp/app.tar.gz' --strip-components 0 -C '/opt/app'")));
+        assert_eq!(get_extract_command(HostOSType::Linux, ArchiveFormat::TarGz, &archive, &dest, 1),
+            Ok(String::from("tar -xzf '/tmp/app.tar.gz' --strip-components 1 -C '/opt/app'")));
+        assert_eq!(get_extract_command(HostOSType::MacOS, ArchiveFormat::TarXz, &archive, &dest, 2),
+            Ok(String::from("tar -xJf '/tmp/app.tar.gz' --strip-components 2 -C '/opt/app'")));
+        assert_eq!(get_extract_command(HostOSType::Linux, ArchiveFormat::Zip, &String::from("/tmp/app.zip"), &dest, 0),
+            Ok(String::from("unzip -o -q '/tmp/app.zip' -d '/opt/app'")));
+        assert!(get_extract_command(HostOSType::Linux, ArchiveFormat::Zip, &String::from("/tmp/app.zip"), &dest, 1).is_err());
+        assert!(get_extract_command(HostOSType::Linux, ArchiveFormat::Tar, &archive, &String::from("/opt/$(id)"), 0).is_err());
+    }
 
+    #[test]
+    fn test_create_archive_command() {
+        let dest = String::from("/tmp/app.tar.xz");
+        let paths = vec![String::from("/var/www/app/"), String::from("/etc"), String::from("notes")];
+        assert_eq!(get_create_archive_command(HostOSType::Linux, ArchiveFormat::TarXz, &dest, &paths),
+            Ok(String::from("tar -cJf '/tmp/app.tar.xz' -C '/var/www' 'app' -C '/' 'etc' -C '.' 'notes'")));
+        assert_eq!(get_create_archive_command(HostOSType::Linux, ArchiveFormat::TarGz, &dest, &vec![String::from("/etc")]),
+            Ok(String::from("tar -czf '/tmp/app.tar.xz' -C '/' 'etc'")));
+        assert_eq!(get_create_archive_command(HostOSType::Linux, ArchiveFormat::Tar, &dest, &vec![String::from("/etc")]),
+            Ok(String::from("tar -cf '/tmp/app.tar.xz' -C '/' 'etc'")));
+        assert!(get_create_archive_command(HostOSType::Linux, ArchiveFormat::Zip, &dest, &paths).is_err());
+        assert!(get_create_archive_command(HostOSType::Linux, ArchiveFormat::Tar, &dest, &vec![String::from("/")]).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use similar::TextDiff;
+
+// builds the text shown by --diff for a file.  'before' is None when the remote file does not
+// exist yet.  Content that is not valid UTF-8 is summarized rather than diffed.
+
+pub fn unified_diff(path: &String, before: Option<&[u8]>, after: &[u8]) -> String {
+    let old_header = match before {
+        Some(_) => format!("{} (remote)", path),
+        None => String::from("/dev/null")
+    };
+    let new_header = format!("{} (new)", path);
+    let old_text = match before {
+        Some(x) => std::str::from_utf8(x),
+        None => Ok("")
+    };
+    let new_text = std::str::from_utf8(after);
+    return match (old_text, new_text) {
+        (Ok(old), Ok(new)) => TextDiff::from_lines(old, new)
+            .unified_diff()
+            .context_radius(3)
+            .header(&old_header, &new_header)
+            .to_string(),
+        (_, _) => format!("binary files {} and {} differ\n", old_header, new_header)
+    };
+}
+
+// one line for an attribute such as mode or owner that would change
+
+pub fn attribute_diff(name: &str, before: &Option<String>, after: &String) -> String {
+    let old = match before {
+        Some(x) => x.clone(),
+        None => String::from("(none)")
+    };
+    return format!("{}: {} -> {}\n", name, old, after);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let path = String::from("/etc/motd");
+        let diff = unified_diff(&path, Some(b"one\ntwo\nthree\n"), b"one\n2\nthree\n");
+        assert!(diff.starts_with("--- /etc/motd (remote)\n+++ /etc/motd (new)\n"));
+        assert!(diff.contains("\n-two\n+2\n"));
+        let created = unified_diff(&path, None, b"hello\n");
+        assert!(created.starts_with("--- /dev/null\n"));
+        assert!(created.contains("+hello\n"));
+        let binary = unified_diff(&path, Some(&[0xff, 0xfe]), b"text\n");
+        assert!(binary.starts_with("binary files"));
+    }
+}
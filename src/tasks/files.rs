@@ -46,6 +46,34 @@ pub enum Recurse {
     Yes
 }
 
+// archive types understood by the archive and unarchive modules, see cmd_library for the commands
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    TarXz,
+    Zip
+}
+
+impl ArchiveFormat {
+
+    // the format is always picked by file extension
+    pub fn from_path(path: &String) -> Result<Self, String> {
+        let lower = path.to_lowercase();
+        if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+            return Ok(ArchiveFormat::TarGz);
+        } else if lower.ends_with(".tar.xz") || lower.ends_with(".txz") {
+            return Ok(ArchiveFormat::TarXz);
+        } else if lower.ends_with(".tar") {
+            return Ok(ArchiveFormat::Tar);
+        } else if lower.ends_with(".zip") {
+            return Ok(ArchiveFormat::Zip);
+        }
+        return Err(format!("unsupported archive type: {}, expected .tar, .tar.gz, .tgz, .tar.xz, .txz, or .zip", path));
+    }
+}
+
 impl FileAttributesInput {
 
     // given an octal string, like 0o755 or 755, return the numeric value
@@ -152,4 +180,19 @@ impl FileAttributesEvaluated {
     }
     */
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_format_from_path() {
+        assert_eq!(ArchiveFormat::from_path(&String::from("/tmp/app-1.0.tar.gz")), Ok(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_path(&String::from("app.TGZ")), Ok(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_path(&String::from("app.tar.xz")), Ok(ArchiveFormat::TarXz));
+        assert_eq!(ArchiveFormat::from_path(&String::from("app.tar")), Ok(ArchiveFormat::Tar));
+        assert_eq!(ArchiveFormat::from_path(&String::from("app.zip")), Ok(ArchiveFormat::Zip));
+        assert!(ArchiveFormat::from_path(&String::from("app.rar")).is_err());
+    }
+}
@@ -0,0 +1,285 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::tasks::*;
+use crate::handle::handle::TaskHandle;
+use crate::tasks::checksum::sha512;
+use crate::tasks::fields::Field;
+use crate::tasks::files::Recurse;
+use regex::Regex;
+use std::sync::Arc;
+use std::vec::Vec;
+
+// the text editing behind the lineinfile and blockinfile modules.  These work on the whole file
+// contents and return the new contents, so the modules can compare checksums to decide if anything
+// needs to change.
+
+// the parts of the lineinfile and blockinfile actions that do not depend on the edit.  The file is read,
+// edited and compared by checksum, and only written when the edit changed it.
+
+pub struct EditInPlace {
+    pub path: String,
+    pub create: bool,
+    pub backup: bool,
+    pub remove: bool,
+    pub attributes: Option<FileAttributesEvaluated>,
+}
+
+impl EditInPlace {
+
+    pub fn dispatch<F>(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, edit: F) -> Result<Arc<TaskResponse>, Arc<TaskResponse>>
+        where F: Fn(&String) -> Result<String, String> {
+
+        match request.request_type {
+
+            TaskRequestType::Query => {
+
+                let mut changes : Vec<Field> = Vec::new();
+                let remote_mode = handle.remote.query_common_file_attributes(request, &self.path, &self.attributes, &mut changes, Recurse::No)?;
+                if remote_mode.is_none() {
+                    if self.remove {
+                        return Ok(handle.response.is_matched(request));
+                    }
+                    if ! self.create {
+                        return Err(handle.response.is_failed(request, &format!("{} does not exist, set create to add it", self.path)));
+                    }
+                    let data = self.apply(handle, request, &edit, &String::new())?;
+                    handle.remote.show_file_diff(request, &self.path, &remote_mode, &self.attributes, &changes, || Ok(data.into_bytes()))?;
+                    return Ok(handle.response.needs_creation(request));
+                }
+                let data = self.apply(handle, request, &edit, &self.read_text(handle, request)?)?;
+                let remote_512 = handle.remote.get_sha512(request, &self.path)?;
+                if ! remote_512.eq(&sha512(&data)) {
+                    changes.push(Field::Content);
+                }
+                if ! changes.is_empty() {
+                    handle.remote.show_file_diff(request, &self.path, &remote_mode, &self.attributes, &changes, || Ok(data.into_bytes()))?;
+                    return Ok(handle.response.needs_modification(request, &changes));
+                }
+                return Ok(handle.response.is_matched(request));
+            },
+
+            TaskRequestType::Create => {
+                let data = self.apply(handle, request, &edit, &String::new())?;
+                self.write(handle, request, &data)?;
+                return Ok(handle.response.is_created(request));
+            }
+
+            TaskRequestType::Modify => {
+                if request.changes.contains(&Field::Content) {
+                    let data = self.apply(handle, request, &edit, &self.read_text(handle, request)?)?;
+                    if self.backup {
+                        handle.remote.backup_file(request, &self.path)?;
+                    }
+                    self.write(handle, request, &data)?;
+                }
+                else {
+                    handle.remote.process_common_file_attributes(request, &self.path, &self.attributes, &request.changes, Recurse::No)?;
+                }
+                return Ok(handle.response.is_modified(request, request.changes.clone()));
+            }
+
+            _ => { return Err(handle.response.not_supported(request)); }
+
+        }
+    }
+
+    fn apply<F>(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, edit: &F, content: &String) -> Result<String, Arc<TaskResponse>>
+        where F: Fn(&String) -> Result<String, String> {
+        return match edit(content) {
+            Ok(x) => Ok(x),
+            Err(y) => Err(handle.response.is_failed(request, &format!("{}: {}", self.path, y)))
+        };
+    }
+
+    fn read_text(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>) -> Result<String, Arc<TaskResponse>> {
+        return match String::from_utf8(handle.remote.read_file(request, &self.path)?) {
+            Ok(x) => Ok(x),
+            Err(_) => Err(handle.response.is_failed(request, &format!("{} is not a text file", self.path)))
+        };
+    }
+
+    fn write(&self, handle: &Arc<TaskHandle>, request: &Arc<TaskRequest>, data: &String) -> Result<(), Arc<TaskResponse>> {
+        return handle.remote.write_data(&request, data, &self.path, |f| { /* after save */
+            match handle.remote.process_all_common_file_attributes(request, &f, &self.attributes, Recurse::No) {
+                Ok(_x) => Ok(()), Err(y) => Err(y)
+            }
+        });
+    }
+
+}
+
+// where a new line or block goes when it is not already in the file
+
+pub enum Anchor {
+    End,
+    // after the last line matching the expression
+    After(Regex),
+    // before the first line matching the expression
+    Before(Regex)
+}
+
+// with a regex, the last matching line is replaced by the line.  Otherwise the line is added
+// at the anchor unless the file already has it.  With remove, every line matching the regex (or
+// equal to the line) is removed.
+
+pub fn ensure_line(content: &String, line: &Option<String>, regex: &Option<Regex>, remove: bool, anchor: &Anchor) -> String {
+    let (mut lines, trailing) = split_lines(content);
+    let matches = |x: &String| -> bool {
+        match (regex, line) {
+            (Some(re), _) => re.is_match(x),
+            (None, Some(l)) => x.eq(l),
+            (None, None) => false
+        }
+    };
+
+    if remove {
+        lines.retain(|x| ! matches(x));
+        return join_lines(&lines, trailing);
+    }
+
+    let line = match line {
+        Some(x) => x.clone(),
+        None => { return content.clone(); }
+    };
+    if let Some(index) = lines.iter().rposition(|x| matches(x)) {
+        lines[index] = line;
+    } else if ! lines.contains(&line) {
+        let index = anchor_index(&lines, anchor);
+        lines.insert(index, line);
+    }
+    return join_lines(&lines, trailing);
+}
+
+// a block is kept between a begin and an end marker line, which identify it on later runs.  An
+// existing block is replaced in place, otherwise it is added at the anchor.  With remove, the
+// block and its markers are removed.  Markers that do not form a single pair, such as a begin
+// line whose end was deleted by hand, are an error rather than a guess at where the block ends.
+
+pub fn ensure_block(content: &String, begin: &String, end: &String, block: &String, remove: bool, anchor: &Anchor) -> Result<String, String> {
+    let (mut lines, trailing) = split_lines(content);
+    let begins : Vec<usize> = lines.iter().enumerate().filter(|(_,x)| x.eq(&begin)).map(|(i,_)| i).collect();
+    let ends : Vec<usize> = lines.iter().enumerate().filter(|(_,x)| x.eq(&end)).map(|(i,_)| i).collect();
+    let existing = match (begins.as_slice(), ends.as_slice()) {
+        ([], []) => None,
+        ([start], [finish]) if start < finish => Some((*start, *finish)),
+        ([_], []) => { return Err(format!("found \"{}\" without a matching \"{}\"", begin, end)); },
+        ([], [_]) => { return Err(format!("found \"{}\" without a matching \"{}\"", end, begin)); },
+        (_, _) => { return Err(format!("the markers \"{}\" and \"{}\" must appear once each and in that order", begin, end)); }
+    };
+
+    let mut replacement : Vec<String> = Vec::new();
+    if ! remove {
+        replacement.push(begin.clone());
+        replacement.extend(block.lines().map(|x| String::from(x)));
+        replacement.push(end.clone());
+    }
+
+    match existing {
+        Some((start, finish)) => {
+            lines.splice(start..=finish, replacement);
+        },
+        None => {
+            if remove {
+                return Ok(content.clone());
+            }
+            let index = anchor_index(&lines, anchor);
+            lines.splice(index..index, replacement);
+        }
+    }
+    return Ok(join_lines(&lines, trailing));
+}
+
+fn anchor_index(lines: &Vec<String>, anchor: &Anchor) -> usize {
+    return match anchor {
+        Anchor::End => lines.len(),
+        Anchor::After(re) => match lines.iter().rposition(|x| re.is_match(x)) {
+            Some(x) => x + 1,
+            None => lines.len()
+        },
+        Anchor::Before(re) => match lines.iter().position(|x| re.is_match(x)) {
+            Some(x) => x,
+            None => lines.len()
+        }
+    };
+}
+
+// files are treated as ending with a newline unless they had content without one
+
+fn split_lines(content: &String) -> (Vec<String>, bool) {
+    let lines : Vec<String> = content.lines().map(|x| String::from(x)).collect();
+    return (lines, content.is_empty() || content.ends_with("\n"));
+}
+
+fn join_lines(lines: &Vec<String>, trailing: bool) -> String {
+    if lines.is_empty() {
+        return String::new();
+    }
+    let mut result = lines.join("\n");
+    if trailing {
+        result.push_str("\n");
+    }
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ensure_line() {
+        let content = String::from("a=1\nb=2\n");
+        let regex = Some(Regex::new("^b=").unwrap());
+        assert_eq!(ensure_line(&content, &Some(String::from("b=3")), &regex, false, &Anchor::End), "a=1\nb=3\n");
+        assert_eq!(ensure_line(&content, &Some(String::from("c=3")), &None, false, &Anchor::End), "a=1\nb=2\nc=3\n");
+        assert_eq!(ensure_line(&content, &Some(String::from("a=1")), &None, false, &Anchor::End), content);
+        let before = Anchor::Before(Regex::new("^b=").unwrap());
+        assert_eq!(ensure_line(&content, &Some(String::from("x")), &None, false, &before), "a=1\nx\nb=2\n");
+        assert_eq!(ensure_line(&content, &None, &regex, true, &Anchor::End), "a=1\n");
+    }
+
+    #[test]
+    fn test_ensure_block() {
+        let begin = String::from("# BEGIN");
+        let end = String::from("# END");
+        let block = String::from("x\ny");
+        let added = ensure_block(&String::from("a\n"), &begin, &end, &block, false, &Anchor::End).unwrap();
+        assert_eq!(added, "a\n# BEGIN\nx\ny\n# END\n");
+        let replaced = ensure_block(&added, &begin, &end, &String::from("z"), false, &Anchor::End).unwrap();
+        assert_eq!(replaced, "a\n# BEGIN\nz\n# END\n");
+        assert_eq!(ensure_block(&replaced, &begin, &end, &block, true, &Anchor::End).unwrap(), "a\n");
+    }
+
+    #[test]
+    fn test_ensure_block_unpaired_markers() {
+        let begin = String::from("# BEGIN");
+        let end = String::from("# END");
+        let block = String::from("x");
+
+        // with the end marker removed by hand, adding a new block would make the next run replace
+        // everything from the old begin marker to the new end marker
+        let orphaned = String::from("a\n# BEGIN\nold\nkeep me\n");
+        assert!(ensure_block(&orphaned, &begin, &end, &block, false, &Anchor::End).is_err());
+        assert!(ensure_block(&orphaned, &begin, &end, &block, true, &Anchor::End).is_err());
+
+        let no_begin = String::from("a\nold\n# END\n");
+        assert!(ensure_block(&no_begin, &begin, &end, &block, false, &Anchor::End).is_err());
+        let reversed = String::from("# END\na\n# BEGIN\n");
+        assert!(ensure_block(&reversed, &begin, &end, &block, false, &Anchor::End).is_err());
+        let twice = String::from("# BEGIN\nx\n# END\nkeep me\n# BEGIN\nx\n# END\n");
+        assert!(ensure_block(&twice, &begin, &end, &block, false, &Anchor::End).is_err());
+    }
+}
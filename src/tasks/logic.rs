@@ -33,7 +33,9 @@ pub struct PreLogicInput {
     pub sudo: Option<String>,
     pub items: Option<ItemsInput>,
     pub tags: Option<Vec<String>>,
-    pub delegate_to: Option<String>
+    pub delegate_to: Option<String>,
+    // hides command text and output for this task from the terminal and the log
+    pub no_log: Option<bool>
 }
 
 #[derive(Deserialize,Debug,Clone)]
@@ -58,7 +60,17 @@ pub struct PostLogicInput {
     pub notify: Option<String>,
     pub ignore_errors: Option<String>,
     pub retry: Option<String>,
-    pub delay: Option<String>
+    pub delay: Option<String>,
+    // stores the task result in this host variable, see TaskResponse::to_saved_mapping
+    pub save: Option<String>,
+    // conditions that override the reported outcome, tested against the same values 'save' stores
+    pub failed_when: Option<String>,
+    pub changed_when: Option<String>,
+    // retries the task until this condition is true, tested like changed_when.  Requires retry or
+    // timeout, as otherwise the task would give up after its first attempt.
+    pub until: Option<String>,
+    // the most seconds to spend retrying, counted from the first attempt
+    pub timeout: Option<String>
 }
 
 #[derive(Debug)]
@@ -67,11 +79,22 @@ pub struct PostLogicEvaluated {
     pub ignore_errors: bool,
     pub retry: u64,
     pub delay: u64,
+    pub save: Option<String>,
+    pub failed_when: Option<String>, // these are not evaluated here
+    pub changed_when: Option<String>,
+    pub until: Option<String>,
+    pub timeout: u64
 }
 
 
 impl PreLogicInput {
 
+    // used for blocks without a 'with' section
+
+    pub fn empty() -> Self {
+        return Self { condition: None, subscribe: None, sudo: None, items: None, tags: None, delegate_to: None, no_log: None };
+    }
+
     pub fn template(handle: &TaskHandle, request: &Arc<TaskRequest>, tm: TemplateMode, input: &Option<Self>) -> Result<Option<PreLogicEvaluated>,Arc<TaskResponse>> {
         if input.is_none() {
             return Ok(None);
@@ -95,14 +118,86 @@ impl PostLogicInput {
             return Ok(None);
         }
         let input2 = input.as_ref().unwrap();
+        let retry = handle.template.integer_option_to_integer(request, tm, &String::from("retry"), &input2.retry, 0)?;
+        let timeout = handle.template.integer_option_to_integer(request, tm, &String::from("timeout"), &input2.timeout, 0)?;
+        let delay = handle.template.integer_option_to_integer(request, tm, &String::from("delay"), &input2.delay, 1)?;
+        // with templates off (the first pass over a task) the numbers are not known yet
+        if tm != TemplateMode::Off {
+            if let Err(y) = check_until(&input2.until, retry, timeout, delay) {
+                return Err(handle.response.is_failed(request, &y));
+            }
+        }
         return Ok(Some(PostLogicEvaluated {
             notify: handle.template.string_option_trim(request, tm, &String::from("notify"), &input2.notify)?,
             // unsafe here means the options cannot be sent to the shell, which they are not.
-            delay:         handle.template.integer_option_to_integer(request, tm, &String::from("delay"), &input2.delay, 1)?,
+            delay:         delay,
             ignore_errors: handle.template.boolean_option_default_false(request, tm, &String::from("ignore_errors"), &input2.ignore_errors)?,
-            retry:         handle.template.integer_option_to_integer(request, tm, &String::from("retry"), &input2.retry, 0)?,
+            retry:         retry,
+            save:          Self::template_save(handle, request, tm, &input2.save)?,
+            failed_when:   input2.failed_when.clone(),
+            changed_when:  input2.changed_when.clone(),
+            until:         input2.until.clone(),
+            timeout:       timeout,
         }));
     }
+
+    // modules that have always had their own 'save', 'failed_when' and 'changed_when' parameters (shell,
+    // external and stat) use this, so that for example 'save' on the module is the same as 'and/save'
+
+    pub fn template_with_shorthand(handle: &TaskHandle, request: &Arc<TaskRequest>, tm: TemplateMode, input: &Option<Self>, 
+        save: &Option<String>, failed_when: &Option<String>, changed_when: &Option<String>) -> Result<Option<PostLogicEvaluated>,Arc<TaskResponse>> {
+
+        if save.is_none() && failed_when.is_none() && changed_when.is_none() {
+            return Self::template(handle, request, tm, input);
+        }
+        if let Some(input2) = input {
+            for (name, module_value, and_value) in [("save", save, &input2.save), ("failed_when", failed_when, &input2.failed_when), ("changed_when", changed_when, &input2.changed_when)] {
+                if module_value.is_some() && and_value.is_some() {
+                    return Err(handle.response.is_failed(request, &format!("{} and and/{} cannot both be used", name, name)));
+                }
+            }
+        }
+        let mut evaluated = match Self::template(handle, request, tm, input)? {
+            Some(x) => x,
+            None => PostLogicEvaluated { notify: None, ignore_errors: false, retry: 0, delay: 1, save: None, failed_when: None, changed_when: None, until: None, timeout: 0 }
+        };
+        if save.is_some() {
+            evaluated.save = Self::template_save(handle, request, tm, save)?;
+        }
+        if failed_when.is_some() {
+            evaluated.failed_when = failed_when.clone();
+        }
+        if changed_when.is_some() {
+            evaluated.changed_when = changed_when.clone();
+        }
+        return Ok(Some(evaluated));
+    }
+
+    // with templates off the variable name is kept as written, as a task skipped by its condition is never
+    // evaluated again but still saves its result.  The task FSM templates it then, see skip_task.
+
+    fn template_save(handle: &TaskHandle, request: &Arc<TaskRequest>, tm: TemplateMode, save: &Option<String>) -> Result<Option<String>,Arc<TaskResponse>> {
+        return match tm {
+            TemplateMode::Off => Ok(save.clone()),
+            _ => handle.template.string_option_no_spaces(request, tm, &String::from("save"), save)
+        };
+    }
+}
+
+// the task FSM counts attempts from retry, or from timeout and delay when there is no retry.  Without
+// either, or with a timeout shorter than the delay, an until condition could never be tried a second time.
+
+fn check_until(until: &Option<String>, retry: u64, timeout: u64, delay: u64) -> Result<(), String> {
+    if until.is_none() || retry > 0 {
+        return Ok(());
+    }
+    if timeout == 0 {
+        return Err(String::from("and/until requires and/retry or and/timeout"));
+    }
+    if timeout < delay {
+        return Err(format!("and/until: and/timeout ({}) must be at least and/delay ({}) to allow a second attempt", timeout, delay));
+    }
+    return Ok(());
 }
 
 /* this is called from the task_fsm, not above */
@@ -168,3 +263,19 @@ pub fn template_serde_sequence(
     }
     return Ok(output);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_until_needs_retry_or_timeout() {
+        let until = Some(String::from("(eq result.rc 0)"));
+        assert!(check_until(&until, 0, 0, 1).is_err());
+        assert!(check_until(&until, 3, 0, 1).is_ok());
+        assert!(check_until(&until, 0, 60, 5).is_ok());
+        assert!(check_until(&until, 0, 5, 10).is_err());
+        assert!(check_until(&until, 3, 5, 10).is_ok());
+        assert!(check_until(&None, 0, 0, 1).is_ok());
+    }
+}
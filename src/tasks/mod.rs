@@ -22,6 +22,8 @@ pub mod files;
 pub mod fields;
 pub mod cmd_library;
 pub mod checksum;
+pub mod diff;
+pub mod lines;
 
 pub use crate::connection::command::cmd_info;
 pub use crate::tasks::common::{IsTask,IsAction,EvaluatedTask};
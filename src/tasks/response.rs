@@ -25,7 +25,7 @@ use std::vec::Vec;
 // created directly but by helper functions in handle.rs, see
 // the various modules for examples/usage
 
-#[derive(Debug,PartialEq)]
+#[derive(Debug,PartialEq,Clone)]
 pub enum TaskStatus {
     IsCreated,
     IsRemoved,
@@ -49,8 +49,84 @@ pub struct TaskResponse {
     pub msg: Option<String>,
     pub command_result: Arc<Option<CommandResult>>,
     pub with: Arc<Option<PreLogicEvaluated>>,
-    pub and: Arc<Option<PostLogicEvaluated>>
+    pub and: Arc<Option<PostLogicEvaluated>>,
+    // module specific results, such as the attributes found by stat, see Response::with_data
+    pub data: Option<serde_yaml::Mapping>
 }
 
-//impl TaskResponse {
-//}
\ No newline at end of file
+impl TaskResponse {
+
+    // the value stored by 'save', which has the same shape for every module so later tasks can
+    // test things like result.changed or result.status.  rc and out are present when the module
+    // ran a command, and any module specific data is added alongside the common keys.
+
+    pub fn to_saved_mapping(&self) -> serde_yaml::Mapping {
+        let mut result = serde_yaml::Mapping::new();
+        let status = match self.status {
+            TaskStatus::IsCreated | TaskStatus::NeedsCreation         => "created",
+            TaskStatus::IsRemoved | TaskStatus::NeedsRemoval          => "removed",
+            TaskStatus::IsModified | TaskStatus::NeedsModification    => "modified",
+            TaskStatus::IsExecuted | TaskStatus::NeedsExecution       => "executed",
+            TaskStatus::IsPassive | TaskStatus::NeedsPassive          => "passive",
+            TaskStatus::IsMatched                                     => "matched",
+            TaskStatus::IsSkipped                                     => "skipped",
+            TaskStatus::Failed                                        => "failed"
+        };
+        let changed = match status {
+            "created" | "removed" | "modified" | "executed" => true,
+            _ => false
+        };
+        result.insert(yaml_key("status"), serde_yaml::Value::String(String::from(status)));
+        result.insert(yaml_key("changed"), serde_yaml::Value::Bool(changed));
+        result.insert(yaml_key("failed"), serde_yaml::Value::Bool(self.status == TaskStatus::Failed));
+        result.insert(yaml_key("skipped"), serde_yaml::Value::Bool(self.status == TaskStatus::IsSkipped));
+        result.insert(yaml_key("msg"), match &self.msg {
+            Some(x) => serde_yaml::Value::String(x.clone()),
+            None => serde_yaml::Value::Null
+        });
+        let changes : Vec<serde_yaml::Value> = self.changes.iter().map(|x| serde_yaml::Value::String(format!("{:?}", x).to_lowercase())).collect();
+        result.insert(yaml_key("changes"), serde_yaml::Value::Sequence(changes));
+        if let Some(command_result) = self.command_result.as_ref() {
+            result.insert(yaml_key("rc"), serde_yaml::Value::Number(command_result.rc.into()));
+            result.insert(yaml_key("out"), serde_yaml::Value::String(command_result.out.clone()));
+        }
+        if let Some(data) = &self.data {
+            for (k,v) in data.iter() {
+                result.insert(k.clone(), v.clone());
+            }
+        }
+        return result;
+    }
+
+}
+
+fn yaml_key(key: &str) -> serde_yaml::Value {
+    return serde_yaml::Value::String(String::from(key));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: TaskStatus, changes: Vec<Field>, command_result: Option<CommandResult>, data: Option<serde_yaml::Mapping>) -> TaskResponse {
+        return TaskResponse { status: status, changes: changes, msg: None, command_result: Arc::new(command_result), with: Arc::new(None), and: Arc::new(None), data: data };
+    }
+
+    fn mapping(yaml: &str) -> serde_yaml::Mapping {
+        return serde_yaml::from_str(yaml).unwrap();
+    }
+
+    #[test]
+    fn test_to_saved_mapping() {
+        let skipped = response(TaskStatus::IsSkipped, Vec::new(), None, None);
+        assert_eq!(skipped.to_saved_mapping(), mapping("status: skipped\nchanged: false\nfailed: false\nskipped: true\nmsg: null\nchanges: []"));
+
+        let modified = response(TaskStatus::IsModified, vec![Field::Content, Field::Mode], None, None);
+        assert_eq!(modified.to_saved_mapping(), mapping("status: modified\nchanged: true\nfailed: false\nskipped: false\nmsg: null\nchanges: [content, mode]"));
+
+        let command = CommandResult { cmd: String::from("false"), out: String::from("oops"), rc: 1 };
+        let mut failed = response(TaskStatus::Failed, Vec::new(), Some(command), Some(mapping("exists: true")));
+        failed.msg = Some(String::from("command failed"));
+        assert_eq!(failed.to_saved_mapping(), mapping("status: failed\nchanged: false\nfailed: true\nskipped: false\nmsg: command failed\nchanges: []\nrc: 1\nout: oops\nexists: true"));
+    }
+}
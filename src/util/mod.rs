@@ -17,3 +17,6 @@
 pub mod io;
 pub mod yaml;
 pub mod terminal;
+pub mod vault;
+pub mod secrets;
+pub mod wildcard;
@@ -0,0 +1,148 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::RwLock;
+use once_cell::sync::Lazy;
+
+// values that must never be shown to the user or written to the log, such as anything
+// that came out of the vault.  The visitor masks these in command text and output.
+
+pub const MASKED_SECRET : &str = "********";
+
+// shorter values in a vars file encrypted as a whole, such as "root", "prod" or "80", are common in ordinary
+// output, and masking them would garble host names and paths without protecting anything.  Values marked
+// !vault one at a time are always secret, however short.
+const MIN_SECRET_LENGTH : usize = 8;
+
+// kept sorted longest first so a secret containing another secret is masked whole
+static SECRETS: Lazy<RwLock<Vec<String>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+// ==============================================================================================================
+// PUBLIC API
+// ==============================================================================================================
+
+pub fn register_secret(secret: &String) {
+    // a secret may be used with or without its trailing newline
+    let trimmed = String::from(secret.trim());
+    if trimmed.is_empty() {
+        return;
+    }
+    let mut secrets = SECRETS.write().expect("secrets write");
+    if secrets.contains(&trimmed) {
+        return;
+    }
+    secrets.push(trimmed);
+    secrets.sort_by(|a,b| b.len().cmp(&a.len()));
+}
+
+// registers the values of every key in a variables file that is encrypted as a whole.  !vault values
+// inside it are skipped here, as they are registered once decrypted.
+
+pub fn register_secrets_in_value(value: &serde_yaml::Value) {
+    match value {
+        serde_yaml::Value::String(x) => {
+            if ! is_trivial(&String::from(x.trim())) {
+                register_secret(x);
+            }
+        },
+        serde_yaml::Value::Mapping(mapping) => {
+            for (_k,v) in mapping.iter() { register_secrets_in_value(v); }
+        },
+        serde_yaml::Value::Sequence(seq) => {
+            for v in seq.iter() { register_secrets_in_value(v); }
+        },
+        _ => {}
+    }
+}
+
+// every occurrence of a secret is masked, including inside longer words such as -pSECRET or pass_SECRET
+
+pub fn mask_secrets(text: &String) -> String {
+    let secrets = SECRETS.read().expect("secrets read");
+    let mut result = text.clone();
+    for secret in secrets.iter() {
+        if result.contains(secret.as_str()) {
+            result = result.replace(secret.as_str(), MASKED_SECRET);
+        }
+    }
+    return result;
+}
+
+// a copy of a structure with every string in it masked, for reports that print variables
+
+pub fn mask_secrets_in_value(value: &serde_yaml::Value) -> serde_yaml::Value {
+    return match value {
+        serde_yaml::Value::String(x) => serde_yaml::Value::String(mask_secrets(x)),
+        serde_yaml::Value::Mapping(mapping) => {
+            let mut result = serde_yaml::Mapping::new();
+            for (k,v) in mapping.iter() { result.insert(k.clone(), mask_secrets_in_value(v)); }
+            serde_yaml::Value::Mapping(result)
+        },
+        serde_yaml::Value::Sequence(seq) => serde_yaml::Value::Sequence(seq.iter().map(|v| mask_secrets_in_value(v)).collect()),
+        serde_yaml::Value::Tagged(tagged) => mask_secrets_in_value(&tagged.value),
+        x => x.clone()
+    };
+}
+
+// ==============================================================================================================
+// PRIVATE INTERNALS
+// ==============================================================================================================
+
+// short values, and runs of one character (such as an already masked value), are not taken to be
+// secrets when a whole file is encrypted
+
+fn is_trivial(value: &String) -> bool {
+    if value.chars().count() < MIN_SECRET_LENGTH {
+        return true;
+    }
+    let first = value.chars().next().unwrap();
+    return value.chars().all(|c| c == first);
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_mask_secrets() {
+        register_secret(&String::from("hunter22\n"));
+        register_secret(&String::from("hunter22-extended"));
+        assert_eq!(mask_secrets(&String::from("mysql -p hunter22-extended && echo hunter22")), "mysql -p ******** && echo ********");
+        assert_eq!(mask_secrets(&String::from("mysql -phunter22 'hunter22'")), "mysql -p******** '********'");
+        // inside a longer word
+        assert_eq!(mask_secrets(&String::from("xhunter22x pass_hunter22x")), "x********x pass_********x");
+        assert_eq!(mask_secrets(&String::from("nothing to see")), "nothing to see");
+    }
+
+    #[test]
+    fn test_trivial_values_are_not_secrets() {
+        let value : serde_yaml::Value = serde_yaml::from_str("user: root\nenv: prod\nport: '80'\nlines: '--------'\ntoken: s3cr3t-t0ken").unwrap();
+        register_secrets_in_value(&value);
+        assert_eq!(mask_secrets(&String::from("ssh root@prod:80 --------")), "ssh root@prod:80 --------");
+        assert_eq!(mask_secrets(&String::from("curl -H 'X-Token: s3cr3t-t0ken'")), "curl -H 'X-Token: ********'");
+    }
+
+    #[test]
+    fn test_short_vault_values_are_secrets() {
+        // as registered by decrypt_tagged_values for a value marked !vault
+        register_secret(&String::from("pw7chrs"));
+        assert_eq!(mask_secrets(&String::from("mysql -u app -ppw7chrs")), "mysql -u app -p********");
+        register_secret(&String::from("\n"));
+        assert_eq!(mask_secrets(&String::from("unchanged")), "unchanged");
+    }
+
+}
@@ -0,0 +1,317 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::RwLock;
+use once_cell::sync::Lazy;
+use chacha20poly1305::{ChaCha20Poly1305,Key,Nonce};
+use chacha20poly1305::aead::{Aead,AeadCore,KeyInit,OsRng,Payload};
+use chacha20poly1305::aead::rand_core::RngCore;
+use argon2::Argon2;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::process::Command;
+use crate::util::io::{read_local_file,is_executable};
+use crate::util::yaml::show_yaml_error_in_context;
+use crate::util::secrets::{register_secret,register_secrets_in_value};
+
+// the vault keeps secrets in vars files, group_vars/ and host_vars/ encrypted at rest.  Either a whole
+// file is encrypted, or single values are, using the YAML tag !vault:
+//
+//    db_password: !vault |
+//      $JETP_VAULT;1;CHACHA20POLY1305;ARGON2ID
+//      ...base64...
+//
+// the key is derived from the vault password with Argon2id and a random salt, and the content is
+// encrypted with ChaCha20-Poly1305, so tampering is detected rather than producing garbage.
+
+pub const VAULT_HEADER: &str = "$JETP_VAULT;1;CHACHA20POLY1305;ARGON2ID";
+const VAULT_TAG: &str = "vault";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const LINE_WIDTH: usize = 76;
+
+// the vault password is set once from the command line, before inventory or playbooks are loaded
+static VAULT_PASSWORD: Lazy<RwLock<Option<String>>> = Lazy::new(|| RwLock::new(None));
+
+// key derivation is deliberately slow, and every !vault value has its own salt, so keys are remembered
+static KEY_CACHE: Lazy<RwLock<HashMap<(String,Vec<u8>),[u8;32]>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+// ==============================================================================================================
+// PUBLIC API
+// ==============================================================================================================
+
+pub fn set_vault_password(password: &String) {
+    *VAULT_PASSWORD.write().unwrap() = Some(password.clone());
+}
+
+pub fn is_vaulted(contents: &String) -> bool {
+    return contents.trim_start().starts_with(VAULT_HEADER);
+}
+
+pub fn encrypt(plaintext: &String, password: &String) -> Result<String, String> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let key = derive_key(password, &salt)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    // the header is authenticated too, so it can't be swapped for another format
+    let payload = Payload { msg: plaintext.as_bytes(), aad: VAULT_HEADER.as_bytes() };
+    let ciphertext = match cipher.encrypt(&nonce, payload) {
+        Ok(x) => x,
+        Err(_) => { return Err(String::from("vault encryption failed")); }
+    };
+
+    let mut blob : Vec<u8> = Vec::new();
+    blob.extend_from_slice(&salt);
+    blob.extend_from_slice(&nonce);
+    blob.extend_from_slice(&ciphertext);
+    let encoded = STANDARD.encode(&blob);
+
+    let mut result = String::from(VAULT_HEADER);
+    result.push('\n');
+    for chunk in encoded.as_bytes().chunks(LINE_WIDTH) {
+        result.push_str(&String::from_utf8_lossy(chunk));
+        result.push('\n');
+    }
+    return Ok(result);
+}
+
+pub fn decrypt(contents: &String, password: &String) -> Result<String, String> {
+    let trimmed = contents.trim_start();
+    if ! trimmed.starts_with(VAULT_HEADER) {
+        return Err(String::from("not vault encrypted content"));
+    }
+    let encoded : String = trimmed[VAULT_HEADER.len()..].chars().filter(|c| !c.is_whitespace()).collect();
+    let blob = match STANDARD.decode(&encoded) {
+        Ok(x) => x,
+        Err(_) => { return Err(String::from("vault content is corrupted (invalid base64)")); }
+    };
+    if blob.len() < SALT_LEN + NONCE_LEN {
+        return Err(String::from("vault content is corrupted (too short)"));
+    }
+    let (salt, rest) = blob.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let key = derive_key(password, salt)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    let payload = Payload { msg: ciphertext, aad: VAULT_HEADER.as_bytes() };
+    let plaintext = match cipher.decrypt(Nonce::from_slice(nonce), payload) {
+        Ok(x) => x,
+        Err(_) => { return Err(String::from("vault decryption failed, the vault password is wrong or the content was modified")); }
+    };
+    return match String::from_utf8(plaintext) {
+        Ok(x) => Ok(x),
+        Err(_) => Err(String::from("vault content is not valid UTF-8"))
+    };
+}
+
+// password files may be scripts that print the password, for use with password managers
+
+pub fn read_vault_password_file(path: &Path) -> Result<String, String> {
+    let password = match is_executable(path) {
+        true => match Command::new(path).output() {
+            Ok(x) => match x.status.success() {
+                true => String::from_utf8_lossy(&x.stdout).to_string(),
+                false => { return Err(format!("vault password script failed: {}, rc={:?}", path.display(), x.status.code())); }
+            },
+            Err(y) => { return Err(format!("vault password script failed: {}, {}", path.display(), y)); }
+        },
+        false => read_local_file(path)?
+    };
+    let password = String::from(password.trim_end_matches(|c| c == '\n' || c == '\r'));
+    if password.is_empty() {
+        return Err(format!("vault password file is empty: {}", path.display()));
+    }
+    return Ok(password);
+}
+
+// re-encrypts every !vault value in a YAML document with a new password, leaving the rest of the
+// text (comments, ordering, formatting) alone.  Returns None if there were no such values.
+
+pub fn rekey_tagged_values(contents: &String, old_password: &String, new_password: &String) -> Result<Option<String>, String> {
+    let lines : Vec<&str> = contents.lines().collect();
+    let mut result = String::new();
+    let mut found = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        result.push_str(line);
+        result.push('\n');
+        i = i + 1;
+        let trimmed = line.trim_end();
+        if ! (trimmed.ends_with("!vault |") || trimmed.ends_with("!vault |-")) {
+            continue;
+        }
+        // the block is every following line indented further than the tagged line
+        let indent = line.len() - line.trim_start().len();
+        let mut block = String::new();
+        let mut block_indent : Option<usize> = None;
+        while i < lines.len() && !lines[i].trim().is_empty() && lines[i].len() - lines[i].trim_start().len() > indent {
+            if block_indent.is_none() {
+                block_indent = Some(lines[i].len() - lines[i].trim_start().len());
+            }
+            block.push_str(lines[i].trim());
+            block.push('\n');
+            i = i + 1;
+        }
+        let plaintext = decrypt(&block, old_password)?;
+        let padding = " ".repeat(block_indent.unwrap_or(indent + 2));
+        for new_line in encrypt(&plaintext, new_password)?.lines() {
+            result.push_str(&format!("{}{}\n", padding, new_line));
+        }
+        found = true;
+    }
+    return match found {
+        true => Ok(Some(result)),
+        false => Ok(None)
+    };
+}
+
+// formats an encrypted value for pasting into a YAML file, as printed by 'jetp vault encrypt -'
+
+pub fn format_vault_tag(encrypted: &String) -> String {
+    let mut result = String::from("!vault |\n");
+    for line in encrypted.lines() {
+        result.push_str(&format!("  {}\n", line));
+    }
+    return result;
+}
+
+// reads a YAML variables file (vars_files, group_vars/, host_vars/), decrypting the whole file
+// and any !vault values inside it as needed
+
+pub fn load_vars_file(path: &Path) -> Result<serde_yaml::Mapping, String> {
+    let contents = read_local_file(path)?;
+    let vaulted = is_vaulted(&contents);
+    let plaintext = match vaulted {
+        true => match decrypt(&contents, &get_vault_password()?) {
+            Ok(x) => x,
+            Err(y) => { return Err(format!("{}: {}", path.display(), y)); }
+        },
+        false => contents
+    };
+    let parsed: Result<serde_yaml::Value, serde_yaml::Error> = serde_yaml::from_str(&plaintext);
+    let value = match parsed {
+        Ok(x) => x,
+        Err(y) => {
+            // don't show the context of an encrypted file, that would be the ciphertext
+            if vaulted {
+                return Err(format!("{}: invalid YAML after decryption: {}", path.display(), y));
+            }
+            show_yaml_error_in_context(&y, &path);
+            return Err(format!("edit the file and try again?"));
+        }
+    };
+    // the values in an encrypted file are treated as secret, see register_secrets_in_value
+    if vaulted {
+        register_secrets_in_value(&value);
+    }
+    return match decrypt_tagged_values(value) {
+        Ok(serde_yaml::Value::Mapping(x)) => Ok(x),
+        Ok(serde_yaml::Value::Null) => Ok(serde_yaml::Mapping::new()),
+        Ok(_) => Err(format!("{}: expected a mapping of variables", path.display())),
+        Err(y) => Err(format!("{}: {}", path.display(), y))
+    };
+}
+
+// replaces every !vault tagged string with its decrypted value, anywhere in a structure
+
+pub fn decrypt_tagged_values(value: serde_yaml::Value) -> Result<serde_yaml::Value, String> {
+    return match value {
+        serde_yaml::Value::Tagged(tagged) => {
+            if tagged.tag != VAULT_TAG {
+                return Ok(serde_yaml::Value::Tagged(tagged));
+            }
+            match tagged.value.as_str() {
+                Some(x) => {
+                    let plaintext = decrypt(&String::from(x), &get_vault_password()?)?;
+                    register_secret(&plaintext);
+                    Ok(serde_yaml::Value::String(plaintext))
+                },
+                None => Err(String::from("!vault values must be strings"))
+            }
+        },
+        serde_yaml::Value::Mapping(mapping) => {
+            let mut result = serde_yaml::Mapping::new();
+            for (k,v) in mapping.into_iter() {
+                result.insert(k, decrypt_tagged_values(v)?);
+            }
+            Ok(serde_yaml::Value::Mapping(result))
+        },
+        serde_yaml::Value::Sequence(seq) => {
+            let mut result : Vec<serde_yaml::Value> = Vec::new();
+            for v in seq.into_iter() {
+                result.push(decrypt_tagged_values(v)?);
+            }
+            Ok(serde_yaml::Value::Sequence(result))
+        },
+        x => Ok(x)
+    };
+}
+
+// ==============================================================================================================
+// PRIVATE INTERNALS
+// ==============================================================================================================
+
+fn get_vault_password() -> Result<String, String> {
+    return match VAULT_PASSWORD.read().unwrap().as_ref() {
+        Some(x) => Ok(x.clone()),
+        None => Err(String::from("found vault encrypted content, but no vault password was given, use --vault-password-file, --ask-vault-password or $JET_VAULT_PASSWORD_FILE"))
+    };
+}
+
+fn derive_key(password: &String, salt: &[u8]) -> Result<[u8;32], String> {
+    let cache_key = (password.clone(), salt.to_vec());
+    if let Some(key) = KEY_CACHE.read().unwrap().get(&cache_key) {
+        return Ok(*key);
+    }
+    let mut key = [0u8; 32];
+    match Argon2::default().hash_password_into(password.as_bytes(), salt, &mut key) {
+        Ok(_) => {},
+        Err(y) => { return Err(format!("vault key derivation failed: {}", y)); }
+    };
+    KEY_CACHE.write().unwrap().insert(cache_key, key);
+    return Ok(key);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_and_decrypt() {
+        let password = String::from("correct horse");
+        let encrypted = encrypt(&String::from("db_password: hunter2\n"), &password).unwrap();
+        assert!(is_vaulted(&encrypted));
+        assert_eq!(decrypt(&encrypted, &password).unwrap(), "db_password: hunter2\n");
+        assert!(decrypt(&encrypted, &String::from("wrong")).is_err());
+        // flipping any part of the ciphertext must be detected
+        let tampered = encrypted.replacen("\n", "\nAAAA", 1);
+        assert!(decrypt(&tampered, &password).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_tagged_values() {
+        set_vault_password(&String::from("pw"));
+        let secret = format_vault_tag(&encrypt(&String::from("s3cret"), &String::from("pw")).unwrap());
+        let doc = format!("user: admin\npasswords:\n  db: {}", secret.replace("\n  ", "\n    "));
+        let value : serde_yaml::Value = serde_yaml::from_str(&doc).unwrap();
+        let decrypted = decrypt_tagged_values(value).unwrap();
+        assert_eq!(decrypted["passwords"]["db"].as_str(), Some("s3cret"));
+        assert_eq!(decrypted["user"].as_str(), Some("admin"));
+    }
+}
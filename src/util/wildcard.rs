@@ -0,0 +1,58 @@
+// Jetporch
+// Copyright (C) 2023 - Michael DeHaan <michael@michaeldehaan.net> + contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// at your option) any later version.
+// 
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+// 
+// You should have received a copy of the GNU General Public License
+// long with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// shell style patterns as used by inventory host patterns and SSH config Host lines.  Only '*', matching
+// any run of characters, and '?', matching any one character, are special.
+
+pub fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let p : Vec<char> = pattern.chars().collect();
+    let v : Vec<char> = value.chars().collect();
+    let (mut pi, mut vi) = (0usize, 0usize);
+    let mut star : Option<(usize,usize)> = None;
+    while vi < v.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == v[vi]) {
+            pi = pi + 1;
+            vi = vi + 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, vi));
+            pi = pi + 1;
+        } else if let Some((star_pi, star_vi)) = star {
+            pi = star_pi + 1;
+            vi = star_vi + 1;
+            star = Some((star_pi, star_vi + 1));
+        } else {
+            return false;
+        }
+    }
+    while pi < p.len() && p[pi] == '*' {
+        pi = pi + 1;
+    }
+    return pi == p.len();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("a*c?", "abbbcd"));
+        assert!(!wildcard_match("a*c", "abcd"));
+        assert!(wildcard_match("web*", "web"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("?", ""));
+    }
+}
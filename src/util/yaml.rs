@@ -16,11 +16,32 @@
 
 use std::path::Path;
 use std::fs::read_to_string;
+use std::collections::HashMap;
 use crate::util::terminal::banner;
 
 const YAML_ERROR_SHOW_LINES:usize = 10;
 const YAML_ERROR_WIDTH:usize = 180; // things will wrap in terminal anyway
 
+// one level of variable precedence (a group, a host, play vars, ...) along with where each of its
+// variables was defined, so that show-vars can explain how a value was arrived at.  Layers are
+// always blended lowest precedence first.
+
+pub struct VariableLayer {
+    pub name: String,
+    pub variables: serde_yaml::Mapping,
+    // top level variable name -> file (or other origin) it came from, if known
+    pub sources: HashMap<String,String>,
+}
+
+// where the variables handed to a layer visitor came from, see PlaybookContext::visit_variable_layers
+
+pub enum LayerSources<'a> {
+    // every variable in the layer has the same origin
+    All(&'a String),
+    // top level variable name -> origin
+    PerVariable(&'a HashMap<String,String>),
+}
+
 // ==============================================================================================================
 // PUBLIC API
 // ==============================================================================================================
@@ -88,6 +109,55 @@ pub fn show_yaml_error_in_context(yaml_error: &serde_yaml::Error, path: &Path) {
 
 }
 
+impl VariableLayer {
+
+    // a layer where every variable has the same origin
+    pub fn new(name: &String, variables: serde_yaml::Mapping, source: &String) -> Self {
+        let mut sources : HashMap<String,String> = HashMap::new();
+        for (k,_v) in variables.iter() {
+            sources.insert(yaml_key_as_string(k), source.clone());
+        }
+        return Self { name: name.clone(), variables, sources };
+    }
+
+    pub fn from_sources(name: &String, variables: &serde_yaml::Mapping, sources: &LayerSources) -> Self {
+        return match sources {
+            LayerSources::All(source) => Self::new(name, variables.clone(), source),
+            LayerSources::PerVariable(sources) => Self { name: name.clone(), variables: variables.clone(), sources: (*sources).clone() }
+        };
+    }
+
+    pub fn get_source(&self, key: &String) -> Option<String> {
+        return self.sources.get(key).cloned();
+    }
+}
+
+pub fn blend_layers(layers: &Vec<VariableLayer>) -> serde_yaml::Value {
+    let mut blended = serde_yaml::Value::from(serde_yaml::Mapping::new());
+    for layer in layers.iter() {
+        blend_variables(&mut blended, serde_yaml::Value::Mapping(layer.variables.clone()));
+    }
+    return blended;
+}
+
+// remembers the origin of the top level keys in a mapping, replacing any previous origin
+
+pub fn record_variable_sources(sources: &mut HashMap<String,String>, mapping: &serde_yaml::Mapping, source: &String) {
+    for (k,_v) in mapping.iter() {
+        sources.insert(yaml_key_as_string(k), source.clone());
+    }
+}
+
+pub fn yaml_key_as_string(key: &serde_yaml::Value) -> String {
+    return match key.as_str() {
+        Some(x) => String::from(x),
+        None => match serde_yaml::to_string(key) {
+            Ok(x) => String::from(x.trim()),
+            Err(_) => String::from("?")
+        }
+    };
+}
+
 pub fn blend_variables(a: &mut serde_yaml::Value, b: serde_yaml::Value) {
 
     match (a, b) {